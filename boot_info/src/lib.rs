@@ -0,0 +1,194 @@
+//! potato_loader から kernel_main に渡すブート情報
+//!
+//! The loader and the kernel are separate binaries that only agree on the
+//! layout of these types, so everything here is `#[repr(C)]`. Bump
+//! `BOOT_INFO_VERSION` whenever a field is added, removed or reordered.
+
+#![no_std]
+
+use core::slice;
+
+pub const BOOT_INFO_VERSION: u32 = 4;
+pub const MAX_KERNEL_SEGMENTS: usize = 8;
+// the loader cuts a longer command line
+pub const MAX_CMDLINE_LEN: usize = 1024;
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct BootInfo {
+    pub version: u32,
+    pub memory_map: MemoryMap,
+    pub frame_buffer: FrameBufferInfo,
+    // physical address of the ACPI RSDP (0 if firmware does not provide one)
+    pub rsdp: u64,
+    // physical range the kernel image was loaded to: [kernel_start, kernel_end)
+    pub kernel_start: u64,
+    pub kernel_end: u64,
+    // PT_LOAD segments of the kernel ELF, used by the kernel to set page permissions
+    pub kernel_segments: [KernelSegment; MAX_KERNEL_SEGMENTS],
+    pub kernel_segment_count: usize,
+    pub loader_image_base: u64,
+    // contents of cmdline.txt on the ESP (null if there is none), UTF-8, in LOADER_DATA
+    pub cmdline: *const u8,
+    pub cmdline_len: usize,
+}
+
+impl BootInfo {
+    pub fn is_compatible(&self) -> bool {
+        self.version == BOOT_INFO_VERSION
+    }
+
+    pub fn kernel_segments(&self) -> &[KernelSegment] {
+        &self.kernel_segments[..self.kernel_segment_count.min(MAX_KERNEL_SEGMENTS)]
+    }
+
+    pub fn rsdp(&self) -> Option<u64> {
+        match self.rsdp {
+            0 => None,
+            addr => Some(addr),
+        }
+    }
+
+    // "" if there is no command line or it is not UTF-8
+    pub fn cmdline(&self) -> &str {
+        if self.cmdline.is_null() {
+            return "";
+        }
+        let bytes = unsafe { slice::from_raw_parts(self.cmdline, self.cmdline_len.min(MAX_CMDLINE_LEN)) };
+        core::str::from_utf8(bytes).unwrap_or("")
+    }
+}
+
+// ------------------------------------------------------
+// Kernel Image
+// ------------------------------------------------------
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct KernelSegment {
+    // the kernel is loaded at its link address, so this is both virtual and physical
+    pub addr: u64,
+    pub mem_size: u64,
+    // ELF p_flags (PF_X, PF_W, PF_R)
+    pub flags: u32,
+}
+
+impl KernelSegment {
+    pub const PF_X: u32 = 0x1;
+    pub const PF_W: u32 = 0x2;
+    pub const PF_R: u32 = 0x4;
+
+    pub const fn empty() -> Self {
+        Self { addr: 0, mem_size: 0, flags: 0 }
+    }
+
+    pub fn is_executable(&self) -> bool {
+        self.flags & Self::PF_X != 0
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.flags & Self::PF_W != 0
+    }
+}
+
+// ------------------------------------------------------
+// Frame Buffer
+// ------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PixelFormat {
+    PixelRGBResv8BitPerColor,
+    PixelBGRResv8BitPerColor,
+    // 32-bit pixels laid out by `FrameBufferInfo::pixel_bitmask`
+    PixelBitMask,
+}
+
+// same layout as EFI_PIXEL_BITMASK
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct PixelBitmask {
+    pub red: u32,
+    pub green: u32,
+    pub blue: u32,
+    pub reserved: u32,
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct FrameBufferInfo {
+    pub frame_buffer: *mut u8,
+    pub pixel_per_scan_line: usize,
+    pub horizontal_resolution: usize,
+    pub vertical_resolution: usize,
+    pub pixel_format: PixelFormat,
+    // all zero unless `pixel_format` is PixelBitMask
+    pub pixel_bitmask: PixelBitmask,
+}
+
+// ------------------------------------------------------
+// Memory Map
+// ------------------------------------------------------
+
+// same values as EFI_MEMORY_TYPE
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct MemoryType(pub u32);
+
+impl MemoryType {
+    pub const RESERVED: Self = Self(0);
+    pub const LOADER_CODE: Self = Self(1);
+    pub const LOADER_DATA: Self = Self(2);
+    pub const BOOT_SERVICES_CODE: Self = Self(3);
+    pub const BOOT_SERVICES_DATA: Self = Self(4);
+    pub const RUNTIME_SERVICES_CODE: Self = Self(5);
+    pub const RUNTIME_SERVICES_DATA: Self = Self(6);
+    pub const CONVENTIONAL: Self = Self(7);
+    pub const UNUSABLE: Self = Self(8);
+    pub const ACPI_RECLAIM: Self = Self(9);
+    pub const ACPI_NON_VOLATILE: Self = Self(10);
+    pub const MMIO: Self = Self(11);
+    pub const MMIO_PORT_SPACE: Self = Self(12);
+    pub const PAL_CODE: Self = Self(13);
+    pub const PERSISTENT_MEMORY: Self = Self(14);
+}
+
+pub const UEFI_PAGE_SIZE: u64 = 0x1000;
+
+// same layout as EFI_MEMORY_DESCRIPTOR (without the firmware's trailing padding)
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct MemoryDescriptor {
+    pub ty: MemoryType,
+    pub phys_start: u64,
+    pub virt_start: u64,
+    pub page_count: u64,
+    pub attribute: u64,
+}
+
+impl MemoryDescriptor {
+    pub fn phys_end(&self) -> u64 {
+        self.phys_start + self.page_count * UEFI_PAGE_SIZE
+    }
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct MemoryMap {
+    pub descriptors: *const MemoryDescriptor,
+    pub len: usize,
+}
+
+impl MemoryMap {
+    pub fn descriptors(&self) -> &[MemoryDescriptor] {
+        if self.descriptors.is_null() {
+            return &[];
+        }
+        // the loader keeps the descriptors in LOADER_DATA, which stays alive for the kernel
+        unsafe { slice::from_raw_parts(self.descriptors, self.len) }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &MemoryDescriptor> {
+        self.descriptors().iter()
+    }
+}
@@ -19,7 +19,7 @@ namespace usb {
       if (std::find(prev_buf.begin(), prev_buf.end(), key) != prev_buf.end()) {
         continue;
       }
-      NotifyKeyPush(key);
+      NotifyKeyPush(Buffer()[0], key);
     }
     return MAKE_ERROR(Error::kSuccess);
   }
@@ -33,15 +33,15 @@ namespace usb {
   }
 
   void HIDKeyboardDriver::SubscribeKeyPush(
-      std::function<void (uint8_t keycode)> observer) {
+      std::function<void (uint8_t modifier, uint8_t keycode)> observer) {
     observers_[num_observers_++] = observer;
   }
 
   std::function<HIDKeyboardDriver::ObserverType> HIDKeyboardDriver::default_observer;
 
-  void HIDKeyboardDriver::NotifyKeyPush(uint8_t keycode) {
+  void HIDKeyboardDriver::NotifyKeyPush(uint8_t modifier, uint8_t keycode) {
     for (int i = 0; i < num_observers_; ++i) {
-      observers_[i](keycode);
+      observers_[i](modifier, keycode);
     }
   }
 }
@@ -11,9 +11,10 @@ namespace usb {
   }
 
   Error HIDMouseDriver::OnDataReceived() {
+    uint8_t buttons = Buffer()[0];
     int8_t displacement_x = Buffer()[1];
     int8_t displacement_y = Buffer()[2];
-    NotifyMouseMove(displacement_x, displacement_y);
+    NotifyMouseMove(buttons, displacement_x, displacement_y);
     Log(kDebug, "%02x,(%3d,%3d)\n", Buffer()[0], displacement_x, displacement_y);
     return MAKE_ERROR(Error::kSuccess);
   }
@@ -27,15 +28,15 @@ namespace usb {
   }
 
   void HIDMouseDriver::SubscribeMouseMove(
-      std::function<void (int8_t displacement_x, int8_t displacement_y)> observer) {
+      std::function<void (uint8_t buttons, int8_t displacement_x, int8_t displacement_y)> observer) {
     observers_[num_observers_++] = observer;
   }
 
   std::function<HIDMouseDriver::ObserverType> HIDMouseDriver::default_observer;
 
-  void HIDMouseDriver::NotifyMouseMove(int8_t displacement_x, int8_t displacement_y) {
+  void HIDMouseDriver::NotifyMouseMove(uint8_t buttons, int8_t displacement_x, int8_t displacement_y) {
     for (int i = 0; i < num_observers_; ++i) {
-      observers_[i](displacement_x, displacement_y);
+      observers_[i](buttons, displacement_x, displacement_y);
     }
   }
 }
@@ -16,7 +16,15 @@ namespace {
 
 namespace usb {
   alignas(64) uint8_t memory_pool[kMemoryPoolSize];
-  uintptr_t alloc_ptr = reinterpret_cast<uintptr_t>(memory_pool);
+  uintptr_t pool_base = reinterpret_cast<uintptr_t>(memory_pool);
+  size_t pool_size = kMemoryPoolSize;
+  uintptr_t alloc_ptr = pool_base;
+
+  void SetMemoryPool(uintptr_t base, size_t size) {
+    pool_base = base;
+    pool_size = size;
+    alloc_ptr = base;
+  }
 
   void* AllocMem(size_t size, unsigned int alignment, unsigned int boundary) {
     if (alignment > 0) {
@@ -29,8 +37,7 @@ namespace usb {
       }
     }
 
-    if (reinterpret_cast<uintptr_t>(memory_pool) + kMemoryPoolSize
-        < alloc_ptr + size) {
+    if (pool_base + pool_size < alloc_ptr + size) {
       return nullptr;
     }
 
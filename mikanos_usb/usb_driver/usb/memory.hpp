@@ -7,11 +7,22 @@
 #pragma once
 
 #include <cstddef>
+#include <cstdint>
 
 namespace usb {
   /** @brief 動的メモリ確保のためのメモリプールの最大容量（バイト） */
   static const size_t kMemoryPoolSize = 4096 * 32;
 
+  /** @brief AllocMem が使うメモリプールを差し替える．
+   *
+   * 何も確保していない初期化前に呼び出すこと．
+   * 呼び出さなければ静的に確保した kMemoryPoolSize バイトのプールを使う．
+   *
+   * @param base  プールの先頭アドレス（DMA 可能な物理アドレスと一致すること）
+   * @param size  プールのサイズ（バイト単位）
+   */
+  void SetMemoryPool(uintptr_t base, size_t size);
+
   /** @brief 指定されたバイト数のメモリ領域を確保して先頭ポインタを返す．
    *
    * 先頭アドレスが alignment に揃ったメモリ領域を確保する．
@@ -1,56 +1,92 @@
-use core::slice;
-use uefi::proto::console::gop::PixelFormat as UEFIPixelFormat;
-
-#[derive(Debug)]
-#[repr(u8)]
-pub enum PixelFormat {
-    PixelRGBResv8BitPerColor,
-    PixelBGRResv8BitPerColor,
+use uefi::proto::console::gop::{GraphicsOutput, ModeInfo, PixelFormat as UEFIPixelFormat};
+use boot_info::{FrameBufferInfo, PixelBitmask, PixelFormat};
+use potato_utils::pixel_bitmask::ChannelMasks;
+
+use uefi::prelude::{Boot, ResultExt, SystemTable};
+
+fn graphics_output(system_table: &SystemTable<Boot>) -> &mut GraphicsOutput {
+    let protocol = system_table
+        .boot_services()
+        .locate_protocol::<GraphicsOutput>()
+        .unwrap_success();
+    unsafe { &mut *protocol.get() }
 }
 
-#[derive(Debug)]
-#[repr(C)]
-pub struct FrameBuffer {
-    frame_buffer: *mut u8,
-    pixel_per_scan_line: usize,
-    horizontal_resolution: usize,
-    vertical_resolution: usize,
-    pixel_format: PixelFormat,
+// the kernel draws 32-bit RGB, BGR and bitmask pixels; BltOnly has no frame buffer at all
+fn is_drawable(info: &ModeInfo) -> bool {
+    match info.pixel_format() {
+        UEFIPixelFormat::Rgb | UEFIPixelFormat::Bgr => true,
+        UEFIPixelFormat::Bitmask => info.pixel_bitmask().map_or(false, |mask| {
+            ChannelMasks { red: mask.red, green: mask.green, blue: mask.blue, reserved: mask.reserved }.is_supported()
+        }),
+        UEFIPixelFormat::BltOnly => false,
+    }
 }
 
-use uefi::prelude::{Boot, ResultExt, SystemTable};
+// Switches GOP to the mode the kernel will draw to: one with the `preferred` resolution if
+// there is such a mode, otherwise the mode firmware picked, otherwise the largest one.
+// Returns the resolution in use.
+pub fn select_mode(system_table: &SystemTable<Boot>, preferred: Option<(usize, usize)>) -> (usize, usize) {
+    let gop = graphics_output(system_table);
+    let preferred_mode = preferred.and_then(|resolution| {
+        gop.modes()
+            .map(|mode| mode.unwrap())
+            .find(|mode| mode.info().resolution() == resolution && is_drawable(mode.info()))
+    });
+    let mode = match preferred_mode {
+        Some(mode) => Some(mode),
+        None if is_drawable(&gop.current_mode_info()) => None,
+        None => gop
+            .modes()
+            .map(|mode| mode.unwrap())
+            .filter(|mode| is_drawable(mode.info()))
+            .max_by_key(|mode| {
+                let (width, height) = mode.info().resolution();
+                width * height
+            }),
+    };
+    if let Some(mode) = mode {
+        gop.set_mode(&mode).unwrap_success();
+    }
+    let info = gop.current_mode_info();
+    assert!(is_drawable(&info), "no GOP mode with a frame buffer the kernel can draw to");
+    info.resolution()
+}
+
+pub fn from_system_table(system_table: &SystemTable<Boot>) -> FrameBufferInfo {
+    let gop = graphics_output(system_table);
+
+    // frame buffer
+    let frame_buffer_ptr = gop.frame_buffer().as_mut_ptr();
+
+    let mode_info = gop.current_mode_info();
+    // pixel per scan line
+    let pixel_per_scan_line = mode_info.stride();
+    // resolution
+    let resolution = mode_info.resolution();
+    // pixel format
+    let pixel_format = match mode_info.pixel_format() {
+        UEFIPixelFormat::Rgb => PixelFormat::PixelRGBResv8BitPerColor,
+        UEFIPixelFormat::Bgr => PixelFormat::PixelBGRResv8BitPerColor,
+        UEFIPixelFormat::Bitmask => PixelFormat::PixelBitMask,
+        UEFIPixelFormat::BltOnly => panic!("unexpected PixelFormat::BltOnly"),
+    };
+    let pixel_bitmask = match mode_info.pixel_bitmask() {
+        Some(mask) if pixel_format == PixelFormat::PixelBitMask => PixelBitmask {
+            red: mask.red,
+            green: mask.green,
+            blue: mask.blue,
+            reserved: mask.reserved,
+        },
+        _ => PixelBitmask { red: 0, green: 0, blue: 0, reserved: 0 },
+    };
 
-impl FrameBuffer {
-    pub fn from_system_table(system_table: &SystemTable<Boot>) -> Self {
-        use uefi::proto::console::gop::GraphicsOutput;
-        let protocol = system_table
-            .boot_services()
-            .locate_protocol::<GraphicsOutput>()
-            .unwrap_success();
-        let gop = unsafe { &mut *protocol.get() };
-
-        // frame buffer
-        let frame_buffer_ptr = gop.frame_buffer().as_mut_ptr();
-
-        let mode_info = gop.current_mode_info();
-        // pixel per scan line
-        let pixel_per_scan_line = mode_info.stride();
-        // resolution
-        let resolution = mode_info.resolution();
-        // pixel format
-        let pixel_format = match mode_info.pixel_format() {
-            UEFIPixelFormat::Rgb => PixelFormat::PixelRGBResv8BitPerColor,
-            UEFIPixelFormat::Bgr => PixelFormat::PixelBGRResv8BitPerColor,
-            UEFIPixelFormat::Bitmask => panic!("unexpected PixelFormat::Bitmask"),
-            UEFIPixelFormat::BltOnly => panic!("unexpected PixelFormat::BltOnly"),
-        };
-
-        Self {
-            frame_buffer: frame_buffer_ptr,
-            pixel_per_scan_line,
-            horizontal_resolution: resolution.0,
-            vertical_resolution: resolution.1,
-            pixel_format,
-        }
+    FrameBufferInfo {
+        frame_buffer: frame_buffer_ptr,
+        pixel_per_scan_line,
+        horizontal_resolution: resolution.0,
+        vertical_resolution: resolution.1,
+        pixel_format,
+        pixel_bitmask,
     }
 }
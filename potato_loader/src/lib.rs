@@ -2,6 +2,7 @@
 #![feature(asm)]
 
 pub mod frame_buffer;
+pub mod serial;
 
 use core::arch::asm;
 #[inline]
@@ -12,20 +12,70 @@ use uefi::prelude::ResultExt;
 use uefi::proto::loaded_image::LoadedImage;
 use uefi::proto::media::file::{File, FileAttribute, FileInfo, FileMode, RegularFile, FileType};
 use uefi::{
+    proto::media::file::Directory,
     proto::media::fs::SimpleFileSystem,
     table::boot::{MemoryDescriptor, MemoryType},
 };
 
-use potato_loader::frame_buffer::FrameBuffer;
+use potato_loader::frame_buffer;
+use potato_loader::serial::SerialWriter;
+use boot_info::{BootInfo, FrameBufferInfo, BOOT_INFO_VERSION, MAX_CMDLINE_LEN};
+use potato_utils::cmdline::{parse_resolution, CommandLine};
 use uefi::prelude::SystemTable;
 use uefi::table::Boot;
 
-// type EntryFn = extern "sysv64" fn(&FrameBuffer);
-type EntryFn = extern "sysv64" fn(FrameBuffer);
+type EntryFn = extern "sysv64" fn(&'static BootInfo) -> !;
 
-unsafe fn get_frame_buffer(system_table: &SystemTable<Boot>) -> FrameBuffer {
-    let frame_buffer = FrameBuffer::from_system_table(system_table);
-    frame_buffer
+// lets the panic handler print to the UEFI console until boot services are exited
+static mut PANIC_SYSTEM_TABLE: Option<SystemTable<Boot>> = None;
+
+unsafe fn get_frame_buffer(system_table: &SystemTable<Boot>) -> FrameBufferInfo {
+    frame_buffer::from_system_table(system_table)
+}
+
+fn find_rsdp(system_table: &SystemTable<Boot>) -> u64 {
+    use uefi::table::cfg::{ACPI2_GUID, ACPI_GUID};
+    let config_table = system_table.config_table();
+    // prefer ACPI 2.0 (XSDT) over ACPI 1.0 (RSDT)
+    config_table.iter()
+        .find(|entry| entry.guid == ACPI2_GUID)
+        .or_else(|| config_table.iter().find(|entry| entry.guid == ACPI_GUID))
+        .map(|entry| entry.address as u64)
+        .unwrap_or(0)
+}
+
+// cmdline.txt on the ESP, read into LOADER_DATA so that the kernel can parse it later;
+// "" if there is none
+fn read_cmdline(system_table: &SystemTable<Boot>, root_dir: &mut Directory) -> &'static str {
+    let file = match root_dir.open("cmdline.txt", FileMode::Read, FileAttribute::READ_ONLY) {
+        Ok(file) => file.unwrap(),
+        Err(_) => return "",
+    };
+    let mut file = unsafe { RegularFile::new(file) };
+
+    let buf = &mut [0u8; 512];
+    let info: &mut FileInfo = file.get_info(buf).unwrap_success();
+    let size = (info.file_size() as usize).min(MAX_CMDLINE_LEN);
+    if size == 0 {
+        file.close();
+        return "";
+    }
+    let cmdline_buf: &'static mut [u8] = {
+        let addr = system_table
+            .boot_services()
+            .allocate_pool(MemoryType::LOADER_DATA, size)
+            .unwrap_success();
+        unsafe { slice::from_raw_parts_mut(addr, size) }
+    };
+    let read = file.read(cmdline_buf).unwrap_success();
+    file.close();
+
+    // a cut in the middle of a character drops only that character
+    let cmdline = match core::str::from_utf8(&cmdline_buf[..read]) {
+        Ok(s) => s,
+        Err(e) => core::str::from_utf8(&cmdline_buf[..e.valid_up_to()]).unwrap(),
+    };
+    cmdline.trim()
 }
 
 struct FileWriter(RegularFile);
@@ -51,6 +101,7 @@ pub extern "efiapi" fn efi_main(
     let stdout = system_table.stdout();
     stdout.clear().unwrap_success();
     writeln!(stdout, "Hello Bootloader!").unwrap();
+    unsafe { PANIC_SYSTEM_TABLE = Some(system_table.unsafe_clone()) };
 
     let image_base = {
         let loaded_image = unsafe { system_table
@@ -128,6 +179,18 @@ pub extern "efiapi" fn efi_main(
     mmap_file.0.flush().unwrap_success();
     // ------------------------------------------------------
 
+    // kernel command line
+    let cmdline = read_cmdline(&system_table, &mut root_dir);
+    writeln!(system_table.stdout(), "cmdline: {}", cmdline).unwrap();
+    // resolution= is for the loader: the mode has to be set before boot services are exited
+    let preferred = CommandLine::new(cmdline).get("resolution");
+    let preferred_resolution = preferred.and_then(parse_resolution);
+    let (width, height) = frame_buffer::select_mode(&system_table, preferred_resolution);
+    if preferred.is_some() && preferred_resolution != Some((width, height)) {
+        writeln!(system_table.stdout(), "resolution {} is not available", preferred.unwrap()).unwrap();
+    }
+    writeln!(system_table.stdout(), "GOP mode: {}x{}", width, height).unwrap();
+
     // frame buffer
     let frame_buffer = unsafe { get_frame_buffer(&system_table) };
 
@@ -188,6 +251,18 @@ pub extern "efiapi" fn efi_main(
         kernel_end
     )
     .unwrap();
+    // the kernel maps its image from the segments in BootInfo; a dropped one would be unmapped
+    let load_segments = kernel_elf
+        .program_headers
+        .iter()
+        .filter(|ph| ph.p_type == elf::program_header::PT_LOAD)
+        .count();
+    assert!(
+        load_segments <= boot_info::MAX_KERNEL_SEGMENTS,
+        "the kernel has {} PT_LOAD segments, but BootInfo holds only {}",
+        load_segments,
+        boot_info::MAX_KERNEL_SEGMENTS,
+    );
 
     system_table
         .boot_services()
@@ -219,27 +294,107 @@ pub extern "efiapi" fn efi_main(
         unsafe { core::mem::transmute::<u64, EntryFn>(addr) }
     };
 
-    writeln!(system_table.stdout(), "entry point: {:#x}", entry_point as u64).unwrap();
+    // boot info
+    // allocated as LOADER_DATA so that the kernel can keep reading it after exit_boot_services
+    let rsdp = find_rsdp(&system_table);
+    let boot_info: &'static mut BootInfo = {
+        let ptr = system_table
+            .boot_services()
+            .allocate_pool(MemoryType::LOADER_DATA, mem::size_of::<BootInfo>())
+            .unwrap_success();
+        unsafe { &mut *(ptr as *mut BootInfo) }
+    };
+    // Counted from the map as it is now, whatever size the firmware's descriptors are. It
+    // may still grow a little until exit_boot_services (e.g. by the allocations below).
+    let (_map_key, current_map) = system_table.boot_services()
+        .memory_map(mmap_buf).unwrap_success();
+    let max_descriptors = current_map.len() + 16;
+    let descriptors: &'static mut [boot_info::MemoryDescriptor] = {
+        let ptr = system_table
+            .boot_services()
+            .allocate_pool(
+                MemoryType::LOADER_DATA,
+                max_descriptors * mem::size_of::<boot_info::MemoryDescriptor>(),
+            )
+            .unwrap_success();
+        unsafe { slice::from_raw_parts_mut(ptr as *mut boot_info::MemoryDescriptor, max_descriptors) }
+    };
 
-    // test entry (before exiting boot services)
-    // writeln!(system_table.stdout(), "{:?}", frame_buffer).unwrap();
-    // entry_point(frame_buffer);
+    writeln!(system_table.stdout(), "entry point: {:#x}", entry_point as u64).unwrap();
 
     writeln!(system_table.stdout(), "exiting boot services").unwrap();
     // exit boot services (and retreive memory_map)
+    unsafe { PANIC_SYSTEM_TABLE = None };
     uefi::alloc::exit_boot_services();
-    let (_system_table, _memory_map) = system_table
+    let (_system_table, memory_map) = system_table
         .exit_boot_services(image, mmap_storage)
         .unwrap_success();
 
-    entry_point(frame_buffer); //
+    let mut kernel_segments = [boot_info::KernelSegment::empty(); boot_info::MAX_KERNEL_SEGMENTS];
+    let mut kernel_segment_count = 0;
+    for (dst, pheader) in kernel_segments.iter_mut().zip(
+        kernel_elf
+            .program_headers
+            .iter()
+            .filter(|ph| ph.p_type == elf::program_header::PT_LOAD),
+    ) {
+        *dst = boot_info::KernelSegment {
+            addr: pheader.p_vaddr,
+            mem_size: pheader.p_memsz,
+            flags: pheader.p_flags,
+        };
+        kernel_segment_count += 1;
+    }
+
+    // copy the final memory map (firmware descriptors may be larger than boot_info::MemoryDescriptor).
+    // A dropped entry would hide memory from the kernel's frame allocator, so stop instead.
+    assert!(
+        memory_map.len() <= descriptors.len(),
+        "the memory map has {} entries, but there is room for only {}",
+        memory_map.len(),
+        descriptors.len(),
+    );
+    let mut len = 0;
+    for (dst, desc) in descriptors.iter_mut().zip(memory_map) {
+        *dst = boot_info::MemoryDescriptor {
+            ty: boot_info::MemoryType(desc.ty.0),
+            phys_start: desc.phys_start,
+            virt_start: desc.virt_start,
+            page_count: desc.page_count,
+            attribute: desc.att.bits(),
+        };
+        len += 1;
+    }
 
-    loop {}
+    *boot_info = BootInfo {
+        version: BOOT_INFO_VERSION,
+        memory_map: boot_info::MemoryMap {
+            descriptors: descriptors.as_ptr(),
+            len,
+        },
+        frame_buffer,
+        rsdp,
+        kernel_start: (kernel_start & !0xfff) as u64,
+        kernel_end: ((kernel_end + 0xfff) & !0xfff) as u64,
+        kernel_segments,
+        kernel_segment_count,
+        loader_image_base: image_base,
+        cmdline: cmdline.as_ptr(),
+        cmdline_len: cmdline.len(),
+    };
+
+    entry_point(boot_info);
 }
 
 #[panic_handler]
-fn panic(_info: &PanicInfo) -> ! {
-    loop {}
+fn panic(info: &PanicInfo) -> ! {
+    let _ = writeln!(SerialWriter, "loader panic: {}", info);
+    if let Some(system_table) = unsafe { PANIC_SYSTEM_TABLE.as_mut() } {
+        let _ = writeln!(system_table.stdout(), "loader panic: {}", info);
+    }
+    loop {
+        unsafe { core::arch::asm!("cli", "hlt", options(nomem, nostack)) };
+    }
 }
 
 #[alloc_error_handler]
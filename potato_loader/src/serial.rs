@@ -0,0 +1,44 @@
+//! COM1 への出力
+//!
+//! Polled, for the panic handler. The firmware has already configured the UART.
+
+use core::arch::asm;
+use core::fmt;
+
+const COM1: u16 = 0x3f8;
+const LINE_STATUS: u16 = COM1 + 5;
+const TRANSMIT_EMPTY: u8 = 1 << 5;
+
+fn read8(port: u16) -> u8 {
+    let value: u8;
+    unsafe { asm!("in al, dx", out("al") value, in("dx") port, options(nomem, nostack, preserves_flags)) };
+    value
+}
+
+fn write8(port: u16, value: u8) {
+    unsafe { asm!("out dx, al", in("dx") port, in("al") value, options(nomem, nostack, preserves_flags)) };
+}
+
+pub struct SerialWriter;
+
+impl SerialWriter {
+    fn write_byte(&mut self, byte: u8) {
+        // no UART reads back 0xff, which has TRANSMIT_EMPTY set
+        while read8(LINE_STATUS) & TRANSMIT_EMPTY == 0 {
+            core::hint::spin_loop();
+        }
+        write8(COM1, byte);
+    }
+}
+
+impl fmt::Write for SerialWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            if byte == b'\n' {
+                self.write_byte(b'\r');
+            }
+            self.write_byte(byte);
+        }
+        Ok(())
+    }
+}
@@ -82,4 +82,83 @@ fn to_regular_range<T: RangeBounds<usize>>(range: &T, bit_length: usize) -> Rang
         Bound::Unbounded => bit_length,
     };
     start..end
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BitField;
+    use proptest::prelude::*;
+
+    #[test]
+    fn get_bit() {
+        let value = 0b1010_0101u8;
+        assert!(value.get_bit(0));
+        assert!(!value.get_bit(1));
+        assert!(value.get_bit(7));
+    }
+
+    #[test]
+    fn get_bits() {
+        let value = 0b1010_0101u8;
+        assert_eq!(value.get_bits(4..8), 0b1010);
+        assert_eq!(value.get_bits(..=3), 0b0101);
+        assert_eq!(value.get_bits(2..), 0b10_1001);
+        assert_eq!(value.get_bits(..), value);
+        assert_eq!(u64::MAX.get_bits(32..), u32::MAX as u64);
+    }
+
+    #[test]
+    fn set_bit() {
+        let mut value = 0u16;
+        let _ = value.set_bit(15, true).set_bit(0, true);
+        assert_eq!(value, 0x8001);
+        let _ = value.set_bit(15, false);
+        assert_eq!(value, 1);
+    }
+
+    #[test]
+    fn set_bits() {
+        let mut value = 0b1111_0000u8;
+        let _ = value.set_bits(2..6, 0b1010);
+        assert_eq!(value, 0b1110_1000);
+        let _ = value.set_bits(.., 0x5a);
+        assert_eq!(value, 0x5a);
+        let mut value = u64::MAX;
+        let _ = value.set_bits(12..52, 0);
+        assert_eq!(value, 0xfff0_0000_0000_0fff);
+    }
+
+    fn bit_range() -> impl Strategy<Value = (usize, usize)> {
+        (0..64usize).prop_flat_map(|start| (Just(start), start + 1..=64))
+    }
+
+    fn mask(len: usize) -> u64 {
+        if len == 64 { u64::MAX } else { (1 << len) - 1 }
+    }
+
+    proptest! {
+        #[test]
+        fn get_bits_matches_shift_and_mask(value: u64, (start, end) in bit_range()) {
+            prop_assert_eq!(value.get_bits(start..end), (value >> start) & mask(end - start));
+        }
+
+        #[test]
+        fn set_bits_then_get_bits(value: u64, field: u64, (start, end) in bit_range()) {
+            let field = field & mask(end - start);
+            let mut result = value;
+            let _ = result.set_bits(start..end, field);
+            prop_assert_eq!(result.get_bits(start..end), field);
+            // bits outside the range are untouched
+            let outside = !(mask(end - start) << start);
+            prop_assert_eq!(result & outside, value & outside);
+        }
+
+        #[test]
+        fn set_bit_then_get_bit(value: u32, bit in 0..32usize, set: bool) {
+            let mut result = value;
+            let _ = result.set_bit(bit, set);
+            prop_assert_eq!(result.get_bit(bit), set);
+            prop_assert_eq!(result & !(1 << bit), value & !(1 << bit));
+        }
+    }
+}
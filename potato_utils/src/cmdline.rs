@@ -0,0 +1,192 @@
+//! カーネルコマンドライン
+//!
+//! Options separated by whitespace, each either `key=value` or a bare `flag`. A value may
+//! be double-quoted to contain whitespace: `init="shell -v"`. Later options override
+//! earlier ones with the same key.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CmdlineOption<'a> {
+    pub key: &'a str,
+    // None for a bare flag
+    pub value: Option<&'a str>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CommandLine<'a>(&'a str);
+
+impl<'a> CommandLine<'a> {
+    pub const fn new(cmdline: &'a str) -> Self {
+        Self(cmdline)
+    }
+
+    pub fn as_str(&self) -> &'a str {
+        self.0
+    }
+
+    pub fn options(&self) -> impl Iterator<Item = CmdlineOption<'a>> + 'a {
+        Tokens(self.0).map(|token| match token.split_once('=') {
+            Some((key, value)) => CmdlineOption { key, value: Some(unquote(value)) },
+            None => CmdlineOption { key: token, value: None },
+        })
+    }
+
+    // the value of the last `key=value`
+    pub fn get(&self, key: &str) -> Option<&'a str> {
+        self.options().filter(|option| option.key == key).filter_map(|option| option.value).last()
+    }
+
+    // `key` given either as a flag or with a value
+    pub fn has(&self, key: &str) -> bool {
+        self.options().any(|option| option.key == key)
+    }
+
+    // options whose key starts with `prefix`, with the prefix removed: for `log.` the
+    // option `log.pci=trace` comes out as `pci` and `trace`
+    pub fn with_prefix<'p>(&self, prefix: &'p str) -> impl Iterator<Item = CmdlineOption<'a>> + 'p
+    where
+        'a: 'p,
+    {
+        self.options().filter_map(move |option| {
+            option.key.strip_prefix(prefix).map(|key| CmdlineOption { key, value: option.value })
+        })
+    }
+}
+
+// `1024x768`
+pub fn parse_resolution(s: &str) -> Option<(usize, usize)> {
+    let (width, height) = s.split_once('x')?;
+    let width = width.parse().ok()?;
+    let height = height.parse().ok()?;
+    if width == 0 || height == 0 {
+        return None;
+    }
+    Some((width, height))
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .map(|rest| rest.strip_suffix('"').unwrap_or(rest))
+        .unwrap_or(value)
+}
+
+// whitespace-separated tokens; whitespace between double quotes does not separate
+struct Tokens<'a>(&'a str);
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let s = self.0.trim_start();
+        if s.is_empty() {
+            self.0 = s;
+            return None;
+        }
+        let mut quoted = false;
+        let end = s
+            .char_indices()
+            .find(|&(_, c)| {
+                if c == '"' {
+                    quoted = !quoted;
+                }
+                !quoted && c.is_whitespace()
+            })
+            .map_or(s.len(), |(i, _)| i);
+        self.0 = &s[end..];
+        Some(&s[..end])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_resolution, CmdlineOption, CommandLine};
+    use proptest::prelude::*;
+    use std::vec::Vec;
+
+    #[test]
+    fn options() {
+        let cmdline = CommandLine::new("  loglevel=info quiet\n log.pci=trace  ");
+        let options: Vec<_> = cmdline.options().collect();
+        assert_eq!(
+            options,
+            [
+                CmdlineOption { key: "loglevel", value: Some("info") },
+                CmdlineOption { key: "quiet", value: None },
+                CmdlineOption { key: "log.pci", value: Some("trace") },
+            ]
+        );
+    }
+
+    #[test]
+    fn get_and_has() {
+        let cmdline = CommandLine::new("console=fb quiet console=serial empty=");
+        assert_eq!(cmdline.get("console"), Some("serial"));
+        assert_eq!(cmdline.get("quiet"), None);
+        assert_eq!(cmdline.get("empty"), Some(""));
+        assert_eq!(cmdline.get("missing"), None);
+        assert!(cmdline.has("quiet"));
+        assert!(cmdline.has("console"));
+        assert!(!cmdline.has("consol"));
+    }
+
+    #[test]
+    fn quoted_values() {
+        let cmdline = CommandLine::new(r#"init="shell -v  x" a=b=c unterminated="x y"#);
+        assert_eq!(cmdline.get("init"), Some("shell -v  x"));
+        assert_eq!(cmdline.get("a"), Some("b=c"));
+        assert_eq!(cmdline.get("unterminated"), Some("x y"));
+    }
+
+    #[test]
+    fn with_prefix() {
+        let cmdline = CommandLine::new("log.pci=trace loglevel=warn log.potatOS::xhc=debug");
+        let options: Vec<_> = cmdline.with_prefix("log.").collect();
+        assert_eq!(
+            options,
+            [
+                CmdlineOption { key: "pci", value: Some("trace") },
+                CmdlineOption { key: "potatOS::xhc", value: Some("debug") },
+            ]
+        );
+    }
+
+    #[test]
+    fn resolution() {
+        assert_eq!(parse_resolution("1024x768"), Some((1024, 768)));
+        assert_eq!(parse_resolution("0x768"), None);
+        assert_eq!(parse_resolution("1024"), None);
+        assert_eq!(parse_resolution("1024x"), None);
+        assert_eq!(parse_resolution("axb"), None);
+    }
+
+    proptest! {
+        #[test]
+        fn unquoted_options_round_trip(
+            options in prop::collection::vec(("[a-z.]{1,8}", prop::option::of("[a-z0-9=]{0,8}")), 0..8),
+            separator in "[ \t\n]{1,3}",
+        ) {
+            let cmdline: Vec<_> = options
+                .iter()
+                .map(|(key, value)| match value {
+                    Some(value) => std::format!("{}={}", key, value),
+                    None => key.clone(),
+                })
+                .collect();
+            let cmdline = cmdline.join(&separator);
+            let parsed: Vec<_> = CommandLine::new(&cmdline).options().collect();
+            prop_assert_eq!(parsed.len(), options.len());
+            for (parsed, (key, value)) in parsed.iter().zip(&options) {
+                prop_assert_eq!(parsed.key, key.as_str());
+                prop_assert_eq!(parsed.value, value.as_deref());
+            }
+        }
+
+        #[test]
+        fn keys_have_no_unquoted_whitespace(cmdline in "\\PC{0,64}") {
+            let cmdline = CommandLine::new(&cmdline);
+            for option in cmdline.options() {
+                prop_assert!(!option.key.chars().any(char::is_whitespace) || option.key.contains('"'));
+            }
+        }
+    }
+}
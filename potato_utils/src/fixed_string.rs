@@ -0,0 +1,124 @@
+//! 固定長文字列
+//!
+//! A string in an inline `[u8; N]`, for formatting where there is no heap or where
+//! allocating is not allowed (interrupt handlers, the logger). Writes past the capacity
+//! are cut at a character boundary and set `is_truncated`.
+
+use core::fmt;
+
+#[derive(Clone, Copy)]
+pub struct FixedString<const N: usize> {
+    bytes: [u8; N],
+    len: usize,
+    truncated: bool,
+}
+
+impl<const N: usize> FixedString<N> {
+    pub const fn new() -> Self {
+        Self { bytes: [0; N], len: 0, truncated: false }
+    }
+
+    pub fn as_str(&self) -> &str {
+        // only whole characters are ever copied in
+        unsafe { core::str::from_utf8_unchecked(&self.bytes[..self.len]) }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.truncated = false;
+    }
+
+    pub fn push_str(&mut self, s: &str) {
+        let mut end = s.len().min(N - self.len);
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.bytes[self.len..self.len + end].copy_from_slice(&s.as_bytes()[..end]);
+        self.len += end;
+        self.truncated |= end < s.len();
+    }
+}
+
+impl<const N: usize> Default for FixedString<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> From<&str> for FixedString<N> {
+    fn from(s: &str) -> Self {
+        let mut string = Self::new();
+        string.push_str(s);
+        string
+    }
+}
+
+// never fails; see `is_truncated`
+impl<const N: usize> fmt::Write for FixedString<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push_str(s);
+        Ok(())
+    }
+}
+
+impl<const N: usize> fmt::Display for FixedString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl<const N: usize> fmt::Debug for FixedString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FixedString;
+    use core::fmt::Write;
+    use proptest::prelude::*;
+
+    #[test]
+    fn write() {
+        let mut s: FixedString<16> = FixedString::new();
+        let name = "abc";
+        write!(s, "{}-{:#x}", name, 255).unwrap();
+        assert_eq!(s.as_str(), "abc-0xff");
+        assert!(!s.is_truncated());
+        s.clear();
+        assert!(s.is_empty());
+    }
+
+    #[test]
+    fn truncates_at_a_char_boundary() {
+        let mut s: FixedString<5> = FixedString::new();
+        s.push_str("abcあ");
+        assert_eq!(s.as_str(), "abc");
+        assert!(s.is_truncated());
+        s.push_str("de");
+        assert_eq!(s.as_str(), "abcde");
+    }
+
+    proptest! {
+        #[test]
+        fn is_a_prefix_of_the_input(input in "\\PC{0,40}") {
+            let s: FixedString<24> = FixedString::from(input.as_str());
+            prop_assert!(input.starts_with(s.as_str()));
+            prop_assert_eq!(s.is_truncated(), s.len() < input.len());
+            prop_assert!(input.len() <= 24 || s.len() + 4 > 24);
+        }
+    }
+}
@@ -17,6 +17,12 @@ pub struct FixedVec<'a, T, const CAPACITY: usize> {
     _phantom: &'a PhantomData<()>,
 }
 
+impl<T, const CAPACITY: usize> Default for FixedVec<'_, T, CAPACITY> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<'vec_lifetime, T, const CAPACITY: usize> FixedVec<'vec_lifetime, T, CAPACITY> {
     
     pub const fn new() -> Self {
@@ -31,6 +37,10 @@ impl<'vec_lifetime, T, const CAPACITY: usize> FixedVec<'vec_lifetime, T, CAPACIT
         self.len
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
     pub fn as_slice(&self) -> &[T] {
         let p = self.data.as_ptr() as *const T;
         unsafe { core::slice::from_raw_parts(p, self.len) }
@@ -42,6 +52,10 @@ impl<'vec_lifetime, T, const CAPACITY: usize> FixedVec<'vec_lifetime, T, CAPACIT
         }
     }
 
+    /// # Safety
+    ///
+    /// References from `get`/`try_get` and their `_mut` versions live for `'vec_lifetime`,
+    /// not for the borrow of the vector. None of them may point at the slot being written.
     pub unsafe fn try_push(&mut self, val: T) -> Result<()> {
         if self.len < CAPACITY {
             let len = self.len;
@@ -60,6 +74,10 @@ impl<'vec_lifetime, T, const CAPACITY: usize> FixedVec<'vec_lifetime, T, CAPACIT
         }
     }
 
+    /// # Safety
+    ///
+    /// The element is moved out, so no reference from `get`/`try_get` or their `_mut`
+    /// versions may point at it any more.
     pub unsafe fn try_pop(&mut self) -> Result<T> {
         if self.len > 0 {
             self.len -= 1;
@@ -76,6 +94,11 @@ impl<'vec_lifetime, T, const CAPACITY: usize> FixedVec<'vec_lifetime, T, CAPACIT
         }
     }
 
+    /// # Safety
+    ///
+    /// The reference lives for `'vec_lifetime`, not for the borrow of `self`: the caller
+    /// must not pop the element, drop the vector or take a mutable reference to the same
+    /// element while it is in use.
     pub unsafe fn try_get(&self, idx: usize) -> Result<&'vec_lifetime T> {
         if idx < self.len {
             let ptr = (self.data.as_ptr() as *const T).add(idx);
@@ -91,6 +114,11 @@ impl<'vec_lifetime, T, const CAPACITY: usize> FixedVec<'vec_lifetime, T, CAPACIT
         }
     }
 
+    /// # Safety
+    ///
+    /// The reference lives for `'vec_lifetime`, not for the borrow of `self`: the caller
+    /// must not pop the element, drop the vector or take any other reference to the same
+    /// element while it is in use.
     pub unsafe fn try_get_mut(&mut self, idx: usize) -> Result<&'vec_lifetime mut T> {
         if idx < self.len {
             let ptr = (self.data.as_mut_ptr() as *mut T).add(idx);
@@ -154,3 +182,82 @@ impl<'a, T, const CAPACITY: usize> Iterator for FixedVecIterMut<'a, T, CAPACITY>
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::FixedVec;
+    use proptest::prelude::*;
+    use std::vec::Vec;
+
+    #[test]
+    fn push_and_pop() {
+        let mut vec: FixedVec<u32, 4> = FixedVec::new();
+        vec.push(1);
+        vec.push(2);
+        vec.push(3);
+        assert_eq!(vec.len(), 3);
+        assert_eq!(vec.as_slice(), &[1, 2, 3]);
+        assert_eq!(vec.pop(), 3);
+        assert_eq!(vec.as_slice(), &[1, 2]);
+    }
+
+    #[test]
+    fn get() {
+        let mut vec: FixedVec<u32, 4> = FixedVec::new();
+        vec.push(1);
+        vec.push(2);
+        *vec.get_mut(1) = 5;
+        assert_eq!(*vec.get(1), 5);
+        assert!(unsafe { vec.try_get(2) }.is_err());
+    }
+
+    #[test]
+    fn try_push_full() {
+        let mut vec: FixedVec<u32, 2> = FixedVec::new();
+        vec.push(1);
+        vec.push(2);
+        assert!(unsafe { vec.try_push(3) }.is_err());
+        assert_eq!(vec.len(), 2);
+    }
+
+    #[test]
+    fn iter() {
+        let mut vec: FixedVec<u32, 4> = FixedVec::new();
+        vec.push(1);
+        vec.push(2);
+        vec.push(3);
+        assert_eq!(vec.iter().sum::<u32>(), 6);
+    }
+
+    #[test]
+    #[should_panic]
+    fn pop_empty() {
+        let mut vec: FixedVec<u32, 4> = FixedVec::new();
+        vec.pop();
+    }
+
+    proptest! {
+        // `Some(v)` pushes, `None` pops
+        #[test]
+        fn behaves_like_a_bounded_vec(ops in prop::collection::vec(prop::option::of(any::<u32>()), 0..64)) {
+            let mut vec: FixedVec<u32, 8> = FixedVec::new();
+            let mut model = Vec::new();
+            for op in ops {
+                match op {
+                    Some(value) => {
+                        let result = unsafe { vec.try_push(value) };
+                        prop_assert_eq!(result.is_ok(), model.len() < 8);
+                        if model.len() < 8 {
+                            model.push(value);
+                        }
+                    }
+                    None => {
+                        let result = unsafe { vec.try_pop() }.ok();
+                        prop_assert_eq!(result, model.pop());
+                    }
+                }
+                prop_assert_eq!(vec.as_slice(), model.as_slice());
+            }
+        }
+    }
+}
@@ -0,0 +1,100 @@
+
+#[derive(Default, Debug)]
+pub struct InitOnce<T> {
+    inner: Option<T>,
+}
+
+impl<T> InitOnce<T> {
+    pub const fn new() -> Self {
+        Self { inner: None }
+    }
+
+    pub fn init<F>(&mut self, f: F) 
+    where F: FnOnce() -> T {
+        if self.inner.is_none() {
+            self.inner = Some(f());
+        }
+    }
+
+    pub fn is_initalized(&self) -> bool {
+        self.inner.is_some()
+    }
+
+    pub fn get_or_init<F, E>(&mut self, f: F) -> &T
+    where F: FnOnce() -> T  {
+        match self.get_or_try_init( || Ok::<T, E>(f()) ) {
+            Ok(val) => val,
+            Err(_) => panic!(), // should be initalized (unreachable)
+        }
+    }
+
+    pub fn get_or_try_init<F, E>(&mut self, f: F) -> Result<&T, E>
+    where 
+        F: FnOnce() -> Result<T, E> 
+    {   
+        if self.inner.is_none() {
+            self.inner = Some(f()?);
+        }
+        match &self.inner {
+            Some(val) => Ok(val),
+            None => unreachable!(),
+        }
+
+    }
+
+    pub fn get(&self) -> Option<&T> {
+        self.inner.as_ref()
+    }
+
+    pub fn set(&mut self, val: T) -> Result<(), T> {
+        if self.get().is_some() {
+            Err(val)
+        } else {
+            self.inner = Some(val);
+            Ok(())
+        }
+    }
+
+    pub fn take(&mut self) -> Option<T> {
+        self.inner.take()
+    }
+
+    pub fn into_inner(self) -> Option<T> {
+        self.inner
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::InitOnce;
+
+    #[test]
+    fn init_runs_once() {
+        let mut value = InitOnce::new();
+        assert!(!value.is_initalized());
+        value.init(|| 1);
+        value.init(|| 2);
+        assert_eq!(value.get(), Some(&1));
+    }
+
+    #[test]
+    fn set() {
+        let mut value = InitOnce::new();
+        assert_eq!(value.set(1), Ok(()));
+        assert_eq!(value.set(2), Err(2));
+        assert_eq!(value.take(), Some(1));
+        assert!(!value.is_initalized());
+    }
+
+    #[test]
+    fn get_or_try_init() {
+        let mut value: InitOnce<u32> = InitOnce::new();
+        assert_eq!(value.get_or_try_init(|| Err(())), Err(()));
+        assert!(!value.is_initalized());
+        assert_eq!(value.get_or_try_init(|| Ok::<_, ()>(3)), Ok(&3));
+        assert_eq!(value.get_or_try_init(|| Ok::<_, ()>(4)), Ok(&3));
+        assert_eq!(value.into_inner(), Some(3));
+    }
+}
@@ -0,0 +1,484 @@
+//! レイヤーの重ね合わせ
+//!
+//! Every layer owns a shadow buffer of already-encoded 32-bit pixels, a position on the
+//! screen (which may be partly off it), a z value, a visibility flag and optionally a
+//! transparent pixel value. `LayerStack` keeps the layers ordered bottom to top: by z, and
+//! among equal z by when they were last put there, so `set_z` with the same z raises a
+//! layer within its band.
+//!
+//! Every change records the screen area it affects; `compose` then repaints only those
+//! areas, bottom layer first, into a `Canvas` (the kernel's back buffer) and returns them
+//! so the caller can flush them. Composing does not allocate: rows are built in a buffer
+//! as wide as the screen that is allocated once, since the kernel composes on every line
+//! it logs.
+
+use crate::rect::{DirtyRegion, Rect};
+use alloc::vec;
+use alloc::vec::Vec;
+
+pub const MAX_DIRTY_RECTS: usize = 16;
+// what shows where no visible layer covers the screen; black in every pixel format
+pub const BACKGROUND: u32 = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LayerId(usize);
+
+// where composed rows go
+pub trait Canvas {
+    fn write_row(&mut self, x: usize, y: usize, pixels: &[u32]);
+}
+
+pub struct Layer {
+    x: isize,
+    y: isize,
+    width: usize,
+    height: usize,
+    pixels: Vec<u32>,
+    z: i32,
+    visible: bool,
+    transparent: Option<u32>,
+}
+
+impl Layer {
+    // visible, at (0, 0), filled with `fill`
+    pub fn new(width: usize, height: usize, z: i32, fill: u32) -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            width,
+            height,
+            pixels: vec![fill; width * height],
+            z,
+            visible: true,
+            transparent: None,
+        }
+    }
+
+    // pixels of `transparent` show the layers below
+    pub fn with_transparent(self, transparent: Option<u32>) -> Self {
+        Self { transparent, ..self }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn position(&self) -> (isize, isize) {
+        (self.x, self.y)
+    }
+
+    pub fn z(&self) -> i32 {
+        self.z
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn transparent(&self) -> Option<u32> {
+        self.transparent
+    }
+
+    // rows of `width` pixels
+    pub fn pixels(&self) -> &[u32] {
+        &self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [u32] {
+        &mut self.pixels
+    }
+
+    // whether (x, y) on the screen shows this layer: inside it and not transparent
+    pub fn is_opaque_at(&self, x: usize, y: usize) -> bool {
+        let (lx, ly) = (x as isize - self.x, y as isize - self.y);
+        if lx < 0 || ly < 0 || lx as usize >= self.width || ly as usize >= self.height {
+            return false;
+        }
+        let pixel = self.pixels[ly as usize * self.width + lx as usize];
+        self.transparent != Some(pixel)
+    }
+
+    // `rect` (in layer coordinates) on a `screen_width` x `screen_height` screen
+    fn to_screen(&self, rect: Rect, screen_width: usize, screen_height: usize) -> Option<Rect> {
+        let rect = rect.clip(self.width, self.height)?;
+        let left = (self.x + rect.x as isize).max(0);
+        let top = (self.y + rect.y as isize).max(0);
+        let right = (self.x + rect.right() as isize).min(screen_width as isize);
+        let bottom = (self.y + rect.bottom() as isize).min(screen_height as isize);
+        if left >= right || top >= bottom {
+            return None;
+        }
+        Some(Rect::new(left as usize, top as usize, (right - left) as usize, (bottom - top) as usize))
+    }
+}
+
+pub struct LayerStack {
+    width: usize,
+    height: usize,
+    // bottom to top
+    layers: Vec<(LayerId, Layer)>,
+    next_id: usize,
+    dirty: DirtyRegion<MAX_DIRTY_RECTS>,
+    // one row of the screen, reused by every `compose`
+    row: Vec<u32>,
+}
+
+impl LayerStack {
+    // for a `width` x `height` screen
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            layers: Vec::new(),
+            next_id: 0,
+            dirty: DirtyRegion::new(),
+            row: vec![0; width],
+        }
+    }
+
+    pub fn add(&mut self, layer: Layer) -> LayerId {
+        let id = LayerId(self.next_id);
+        self.next_id += 1;
+        self.insert(id, layer);
+        self.invalidate(id);
+        id
+    }
+
+    pub fn remove(&mut self, id: LayerId) -> Option<Layer> {
+        self.invalidate(id);
+        let index = self.index(id)?;
+        Some(self.layers.remove(index).1)
+    }
+
+    pub fn get(&self, id: LayerId) -> Option<&Layer> {
+        self.layers.iter().find(|(i, _)| *i == id).map(|(_, layer)| layer)
+    }
+
+    // changing the pixels is up to the caller to report with `invalidate_rect`
+    pub fn get_mut(&mut self, id: LayerId) -> Option<&mut Layer> {
+        self.layers.iter_mut().find(|(i, _)| *i == id).map(|(_, layer)| layer)
+    }
+
+    // bottom to top
+    pub fn ids(&self) -> impl DoubleEndedIterator<Item = LayerId> + '_ {
+        self.layers.iter().map(|(id, _)| *id)
+    }
+
+    // the topmost visible layer showing at (x, y)
+    pub fn layer_at(&self, x: usize, y: usize) -> Option<LayerId> {
+        self.layers
+            .iter()
+            .rev()
+            .find(|(_, layer)| layer.visible && layer.is_opaque_at(x, y))
+            .map(|(id, _)| *id)
+    }
+
+    pub fn move_to(&mut self, id: LayerId, x: isize, y: isize) {
+        self.invalidate(id);
+        if let Some(layer) = self.get_mut(id) {
+            layer.x = x;
+            layer.y = y;
+        }
+        self.invalidate(id);
+    }
+
+    pub fn move_relative(&mut self, id: LayerId, dx: isize, dy: isize) {
+        if let Some((x, y)) = self.get(id).map(Layer::position) {
+            self.move_to(id, x + dx, y + dy);
+        }
+    }
+
+    pub fn set_visible(&mut self, id: LayerId, visible: bool) {
+        if let Some(layer) = self.get_mut(id) {
+            layer.visible = visible;
+        }
+        self.invalidate(id);
+    }
+
+    // puts the layer above every other layer with a z up to `z`
+    pub fn set_z(&mut self, id: LayerId, z: i32) {
+        let Some(index) = self.index(id) else {
+            return;
+        };
+        let (_, mut layer) = self.layers.remove(index);
+        layer.z = z;
+        self.insert(id, layer);
+        self.invalidate(id);
+    }
+
+    pub fn set_transparent(&mut self, id: LayerId, transparent: Option<u32>) {
+        if let Some(layer) = self.get_mut(id) {
+            layer.transparent = transparent;
+        }
+        self.invalidate(id);
+    }
+
+    // the whole layer needs repainting
+    pub fn invalidate(&mut self, id: LayerId) {
+        if let Some(layer) = self.get(id) {
+            let whole = Rect::new(0, 0, layer.width, layer.height);
+            self.invalidate_rect(id, whole);
+        }
+    }
+
+    // `rect` of the layer (in its own coordinates) was drawn to
+    pub fn invalidate_rect(&mut self, id: LayerId, rect: Rect) {
+        let area = self.get(id).and_then(|layer| layer.to_screen(rect, self.width, self.height));
+        if let Some(area) = area {
+            self.dirty.add(area);
+        }
+    }
+
+    // the screen area of the layer, clipped to the screen
+    pub fn area(&self, id: LayerId) -> Option<Rect> {
+        let layer = self.get(id)?;
+        layer.to_screen(Rect::new(0, 0, layer.width, layer.height), self.width, self.height)
+    }
+
+    // repaints everything that changed since the last call and returns it
+    pub fn compose(&mut self, canvas: &mut impl Canvas) -> DirtyRegion<MAX_DIRTY_RECTS> {
+        let dirty = self.dirty.take();
+        for rect in dirty.iter() {
+            compose_rect(&self.layers, &mut self.row, *rect, canvas);
+        }
+        dirty
+    }
+
+    fn index(&self, id: LayerId) -> Option<usize> {
+        self.layers.iter().position(|(i, _)| *i == id)
+    }
+
+    // above every layer with z <= layer.z
+    fn insert(&mut self, id: LayerId, layer: Layer) {
+        let index = self.layers.iter().position(|(_, l)| l.z > layer.z).unwrap_or(self.layers.len());
+        self.layers.insert(index, (id, layer));
+    }
+}
+
+// `rect` of the screen, row by row through `row` (at least `rect.width` long)
+fn compose_rect(layers: &[(LayerId, Layer)], row: &mut [u32], rect: Rect, canvas: &mut impl Canvas) {
+    let row = &mut row[..rect.width];
+    for y in rect.y..rect.bottom() {
+        // the buffer still holds the previous row
+        row.fill(BACKGROUND);
+        for (_, layer) in layers.iter().filter(|(_, layer)| layer.visible) {
+            let ly = y as isize - layer.y;
+            if ly < 0 || ly as usize >= layer.height {
+                continue;
+            }
+            // the columns of `rect` this layer covers
+            let left = (layer.x.max(rect.x as isize)) as usize;
+            let right = (layer.x + layer.width as isize).min(rect.right() as isize);
+            if right <= left as isize {
+                continue;
+            }
+            let right = right as usize;
+            let start = ly as usize * layer.width + (left as isize - layer.x) as usize;
+            let src = &layer.pixels[start..start + (right - left)];
+            let dst = &mut row[left - rect.x..right - rect.x];
+            match layer.transparent {
+                None => dst.copy_from_slice(src),
+                Some(transparent) => {
+                    for (d, &s) in dst.iter_mut().zip(src) {
+                        if s != transparent {
+                            *d = s;
+                        }
+                    }
+                }
+            }
+        }
+        canvas.write_row(rect.x, y, row);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Canvas, Layer, LayerId, LayerStack, BACKGROUND};
+    use crate::rect::Rect;
+    use proptest::prelude::*;
+    use std::vec;
+    use std::vec::Vec;
+
+    const WIDTH: usize = 16;
+    const HEIGHT: usize = 8;
+
+    // the screen, plus the rows that were written
+    struct Screen {
+        pixels: Vec<u32>,
+        written: Vec<(usize, usize, usize)>,
+    }
+
+    impl Screen {
+        fn new() -> Self {
+            Self { pixels: vec![0; WIDTH * HEIGHT], written: Vec::new() }
+        }
+
+        fn at(&self, x: usize, y: usize) -> u32 {
+            self.pixels[y * WIDTH + x]
+        }
+    }
+
+    impl Canvas for Screen {
+        fn write_row(&mut self, x: usize, y: usize, pixels: &[u32]) {
+            self.pixels[y * WIDTH + x..][..pixels.len()].copy_from_slice(pixels);
+            self.written.push((x, y, pixels.len()));
+        }
+    }
+
+    fn desktop(stack: &mut LayerStack) -> LayerId {
+        stack.add(Layer::new(WIDTH, HEIGHT, 0, 1))
+    }
+
+    #[test]
+    fn upper_layers_cover_lower_ones() {
+        let mut stack = LayerStack::new(WIDTH, HEIGHT);
+        desktop(&mut stack);
+        let a = stack.add(Layer::new(4, 4, 10, 2));
+        let b = stack.add(Layer::new(4, 4, 10, 3));
+        stack.move_to(b, 2, 2);
+        let mut screen = Screen::new();
+        stack.compose(&mut screen);
+        assert_eq!(screen.at(0, 0), 2);
+        assert_eq!(screen.at(3, 3), 3);
+        assert_eq!(screen.at(7, 7), 1);
+        // same z again raises `a` within the band
+        stack.set_z(a, 10);
+        stack.compose(&mut screen);
+        assert_eq!(screen.at(3, 3), 2);
+        assert_eq!(stack.ids().last(), Some(a));
+    }
+
+    #[test]
+    fn transparent_pixels_show_what_is_below() {
+        let mut stack = LayerStack::new(WIDTH, HEIGHT);
+        desktop(&mut stack);
+        let cursor = stack.add(Layer::new(2, 2, 100, 9).with_transparent(Some(9)));
+        stack.get_mut(cursor).unwrap().pixels_mut()[0] = 5;
+        stack.invalidate(cursor);
+        let mut screen = Screen::new();
+        stack.compose(&mut screen);
+        assert_eq!(screen.at(0, 0), 5);
+        assert_eq!(screen.at(1, 1), 1);
+        assert_eq!(stack.layer_at(0, 0), Some(cursor));
+        assert_ne!(stack.layer_at(1, 1), Some(cursor));
+        stack.set_transparent(cursor, None);
+        stack.compose(&mut screen);
+        assert_eq!(screen.at(1, 1), 9);
+    }
+
+    #[test]
+    fn moving_repaints_only_the_old_and_new_area() {
+        let mut stack = LayerStack::new(WIDTH, HEIGHT);
+        desktop(&mut stack);
+        let layer = stack.add(Layer::new(2, 2, 10, 7));
+        let mut screen = Screen::new();
+        stack.compose(&mut screen);
+        screen.written.clear();
+
+        stack.move_to(layer, 10, 4);
+        let repainted = stack.compose(&mut screen);
+        let rects: Vec<_> = repainted.iter().copied().collect();
+        assert_eq!(rects, [Rect::new(0, 0, 2, 2), Rect::new(10, 4, 2, 2)]);
+        assert!(screen.written.iter().all(|&(_, _, len)| len == 2));
+        assert_eq!(screen.at(0, 0), 1);
+        assert_eq!(screen.at(11, 5), 7);
+    }
+
+    #[test]
+    fn off_screen_parts_are_clipped() {
+        let mut stack = LayerStack::new(WIDTH, HEIGHT);
+        desktop(&mut stack);
+        let layer = stack.add(Layer::new(4, 4, 10, 7));
+        stack.move_to(layer, -2, HEIGHT as isize - 1);
+        assert_eq!(stack.area(layer), Some(Rect::new(0, HEIGHT - 1, 2, 1)));
+        let mut screen = Screen::new();
+        stack.compose(&mut screen);
+        assert_eq!(screen.at(1, HEIGHT - 1), 7);
+        assert_eq!(screen.at(2, HEIGHT - 1), 1);
+        stack.move_to(layer, -10, -10);
+        assert_eq!(stack.area(layer), None);
+    }
+
+    #[test]
+    fn hidden_and_removed_layers_disappear() {
+        let mut stack = LayerStack::new(WIDTH, HEIGHT);
+        desktop(&mut stack);
+        let a = stack.add(Layer::new(2, 2, 10, 7));
+        let b = stack.add(Layer::new(2, 2, 10, 8));
+        stack.move_to(b, 4, 0);
+        let mut screen = Screen::new();
+        stack.compose(&mut screen);
+        stack.set_visible(a, false);
+        stack.remove(b).unwrap();
+        stack.compose(&mut screen);
+        assert_eq!(screen.at(0, 0), 1);
+        assert_eq!(screen.at(4, 0), 1);
+        assert!(stack.get(b).is_none());
+    }
+
+    #[test]
+    fn uncovered_parts_are_background() {
+        let mut stack = LayerStack::new(WIDTH, HEIGHT);
+        let desktop = desktop(&mut stack);
+        let layer = stack.add(Layer::new(4, 4, 10, 7));
+        let mut screen = Screen::new();
+        stack.compose(&mut screen);
+        stack.set_visible(desktop, false);
+        stack.move_to(layer, 2, 0);
+        stack.compose(&mut screen);
+        assert_eq!(screen.at(0, 0), BACKGROUND);
+        assert_eq!(screen.at(2, 0), 7);
+        assert_eq!(screen.at(8, 7), BACKGROUND);
+    }
+
+    #[test]
+    fn compose_reuses_the_row_buffer() {
+        let mut stack = LayerStack::new(WIDTH, HEIGHT);
+        desktop(&mut stack);
+        let layer = stack.add(Layer::new(4, 2, 10, 7));
+        stack.move_to(layer, WIDTH as isize - 2, 3);
+        let row = stack.row.as_ptr();
+        let mut screen = Screen::new();
+        // the whole screen, then a rect at its right edge
+        stack.compose(&mut screen);
+        stack.move_to(layer, WIDTH as isize - 3, 3);
+        stack.compose(&mut screen);
+        assert_eq!(stack.row.as_ptr(), row);
+        assert_eq!(stack.row.len(), WIDTH);
+        assert_eq!(screen.at(WIDTH - 3, 4), 7);
+        assert_eq!(screen.at(WIDTH - 1, 5), 1);
+    }
+
+    proptest! {
+        // composing only the dirty areas gives the same screen as composing everything
+        #[test]
+        fn incremental_matches_full(
+            moves in prop::collection::vec((0usize..3, -4isize..20, -4isize..12, any::<bool>()), 0..12),
+            hide_desktop in any::<bool>(),
+        ) {
+            let mut stack = LayerStack::new(WIDTH, HEIGHT);
+            let desktop = desktop(&mut stack);
+            // then nothing covers what the layers leave behind
+            stack.set_visible(desktop, !hide_desktop);
+            let ids: Vec<_> = (0..3).map(|i| stack.add(Layer::new(3, 3, 10, 10 + i))).collect();
+            let mut screen = Screen::new();
+            stack.compose(&mut screen);
+            for (i, x, y, visible) in moves {
+                stack.move_to(ids[i], x, y);
+                stack.set_visible(ids[i], visible);
+                stack.compose(&mut screen);
+            }
+
+            let mut full = Screen::new();
+            for id in stack.ids().collect::<Vec<_>>() {
+                stack.invalidate(id);
+            }
+            stack.compose(&mut full);
+            prop_assert_eq!(screen.pixels, full.pixels);
+        }
+    }
+}
@@ -0,0 +1,26 @@
+//! カーネルと共有する純粋なロジック
+//!
+//! Code that touches no hardware and no kernel state. It is `no_std` (with `alloc`) so the
+//! kernel links it as is, and it builds for the host so that `cargo test` can run here:
+//! see `tools/test-host.sh`.
+
+#![no_std]
+
+extern crate alloc;
+#[cfg(test)]
+extern crate std;
+
+pub mod bit_field;
+pub mod cmdline;
+pub mod fixed_string;
+pub mod fixed_vec;
+pub mod init_once;
+pub mod layer;
+pub mod log_filter;
+pub mod pci;
+pub mod pixel_bitmask;
+pub mod raster;
+pub mod rect;
+pub mod ring;
+pub mod text_buffer;
+pub mod window;
@@ -0,0 +1,203 @@
+//! ログのフィルタ
+//!
+//! Per-module log levels, written like `RUST_LOG` of env_logger:
+//! `warn,potatOS::xhc=debug,potatOS::acpi=off`. An entry without `=` sets the default
+//! level; the others apply to a module path and everything below it, the longest match
+//! winning.
+
+use crate::fixed_string::FixedString;
+use log::LevelFilter;
+
+pub const MAX_RULES: usize = 16;
+pub const MAX_TARGET_LEN: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterError {
+    InvalidLevel,
+    EmptyTarget,
+    TargetTooLong,
+    TooManyRules,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Rule {
+    target: FixedString<MAX_TARGET_LEN>,
+    level: LevelFilter,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LogFilter {
+    default: LevelFilter,
+    rules: [Option<Rule>; MAX_RULES],
+}
+
+impl LogFilter {
+    pub const fn new(default: LevelFilter) -> Self {
+        Self { default, rules: [None; MAX_RULES] }
+    }
+
+    pub fn parse(spec: &str) -> Result<Self, FilterError> {
+        let mut filter = Self::new(LevelFilter::Error);
+        for entry in spec.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            match entry.split_once('=') {
+                Some((target, level)) => filter.set_module_level(target.trim(), parse_level(level)?)?,
+                None => filter.default = parse_level(entry)?,
+            }
+        }
+        Ok(filter)
+    }
+
+    pub fn default_level(&self) -> LevelFilter {
+        self.default
+    }
+
+    pub fn set_default_level(&mut self, level: LevelFilter) {
+        self.default = level;
+    }
+
+    // replaces the rule for `target` if there is one
+    pub fn set_module_level(&mut self, target: &str, level: LevelFilter) -> Result<(), FilterError> {
+        if target.is_empty() {
+            return Err(FilterError::EmptyTarget);
+        }
+        if target.len() > MAX_TARGET_LEN {
+            return Err(FilterError::TargetTooLong);
+        }
+        if let Some(rule) = self.rules.iter_mut().flatten().find(|rule| rule.target.as_str() == target) {
+            rule.level = level;
+            return Ok(());
+        }
+        let slot = self.rules.iter_mut().find(|slot| slot.is_none()).ok_or(FilterError::TooManyRules)?;
+        *slot = Some(Rule { target: FixedString::from(target), level });
+        Ok(())
+    }
+
+    pub fn level_for(&self, target: &str) -> LevelFilter {
+        self.rules
+            .iter()
+            .flatten()
+            .filter(|rule| is_module_prefix(rule.target.as_str(), target))
+            .max_by_key(|rule| rule.target.len())
+            .map_or(self.default, |rule| rule.level)
+    }
+
+    pub fn enabled(&self, target: &str, level: log::Level) -> bool {
+        level <= self.level_for(target)
+    }
+
+    // the most verbose level any module gets, for `log::set_max_level`
+    pub fn max_level(&self) -> LevelFilter {
+        self.rules.iter().flatten().map(|rule| rule.level).fold(self.default, Ord::max)
+    }
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        Self::new(LevelFilter::Error)
+    }
+}
+
+fn parse_level(s: &str) -> Result<LevelFilter, FilterError> {
+    s.trim().parse().map_err(|_| FilterError::InvalidLevel)
+}
+
+// `potatOS::xhc` covers `potatOS::xhc` and `potatOS::xhc::ring`, not `potatOS::xhci`
+fn is_module_prefix(prefix: &str, target: &str) -> bool {
+    match target.strip_prefix(prefix) {
+        Some(rest) => rest.is_empty() || rest.starts_with("::"),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FilterError, LogFilter, MAX_RULES};
+    use log::{Level, LevelFilter};
+    use proptest::prelude::*;
+
+    #[test]
+    fn parse() {
+        let filter = LogFilter::parse("warn, potatOS::xhc=debug,potatOS::acpi=off").unwrap();
+        assert_eq!(filter.default_level(), LevelFilter::Warn);
+        assert_eq!(filter.level_for("potatOS::xhc"), LevelFilter::Debug);
+        assert_eq!(filter.level_for("potatOS::acpi::madt"), LevelFilter::Off);
+        assert_eq!(filter.level_for("potatOS::timer"), LevelFilter::Warn);
+        assert_eq!(filter.max_level(), LevelFilter::Debug);
+    }
+
+    #[test]
+    fn empty_spec_is_errors_only() {
+        let filter = LogFilter::parse("").unwrap();
+        assert_eq!(filter.level_for("potatOS"), LevelFilter::Error);
+        assert_eq!(filter.max_level(), LevelFilter::Error);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(LogFilter::parse("loud").unwrap_err(), FilterError::InvalidLevel);
+        assert_eq!(LogFilter::parse("potatOS=loud").unwrap_err(), FilterError::InvalidLevel);
+        assert_eq!(LogFilter::parse("=info").unwrap_err(), FilterError::EmptyTarget);
+        let long = std::format!("{}=info", "a".repeat(100));
+        assert_eq!(LogFilter::parse(&long).unwrap_err(), FilterError::TargetTooLong);
+    }
+
+    #[test]
+    fn longest_prefix_wins() {
+        let filter = LogFilter::parse("potatOS=info,potatOS::xhc=trace,potatOS::xhc::ring=off").unwrap();
+        assert_eq!(filter.level_for("potatOS::pci"), LevelFilter::Info);
+        assert_eq!(filter.level_for("potatOS::xhc::port"), LevelFilter::Trace);
+        assert_eq!(filter.level_for("potatOS::xhc::ring"), LevelFilter::Off);
+        // not a module boundary
+        assert_eq!(filter.level_for("potatOS::xhci"), LevelFilter::Info);
+        assert_eq!(filter.level_for("potatOSx"), LevelFilter::Error);
+        assert!(filter.enabled("potatOS::xhc", Level::Trace));
+        assert!(!filter.enabled("potatOS", Level::Debug));
+    }
+
+    #[test]
+    fn set_module_level_replaces() {
+        let mut filter = LogFilter::default();
+        for _ in 0..MAX_RULES + 1 {
+            filter.set_module_level("potatOS::xhc", LevelFilter::Debug).unwrap();
+        }
+        assert_eq!(filter.level_for("potatOS::xhc"), LevelFilter::Debug);
+        for i in 1..MAX_RULES {
+            filter.set_module_level(&std::format!("m{}", i), LevelFilter::Info).unwrap();
+        }
+        assert_eq!(filter.set_module_level("full", LevelFilter::Info), Err(FilterError::TooManyRules));
+    }
+
+    fn level() -> impl Strategy<Value = LevelFilter> {
+        prop::sample::select(&[
+            LevelFilter::Off,
+            LevelFilter::Error,
+            LevelFilter::Warn,
+            LevelFilter::Info,
+            LevelFilter::Debug,
+            LevelFilter::Trace,
+        ][..])
+    }
+
+    proptest! {
+        #[test]
+        fn max_level_bounds_every_module(
+            default in level(),
+            rules in prop::collection::vec(("[a-c]{1,3}(::[a-c]{1,3}){0,2}", level()), 0..MAX_RULES),
+            target in "[a-c]{1,3}(::[a-c]{1,3}){0,3}",
+        ) {
+            let mut filter = LogFilter::new(default);
+            for (module, level) in &rules {
+                filter.set_module_level(module, *level).unwrap();
+            }
+            prop_assert!(filter.level_for(&target) <= filter.max_level());
+        }
+
+        #[test]
+        fn spec_round_trips(default in level(), module in "[a-z]{1,8}(::[a-z]{1,8}){0,2}", level in level()) {
+            let spec = std::format!("{},{}={}", default, module, level);
+            let filter = LogFilter::parse(&spec).unwrap();
+            prop_assert_eq!(filter.default_level(), default);
+            prop_assert_eq!(filter.level_for(&module), level);
+        }
+    }
+}
@@ -0,0 +1,51 @@
+//! PCI コンフィギュレーション空間のアドレス
+
+use crate::bit_field::BitField;
+
+// The value for CONFIG_ADDRESS (0xcf8) that selects the dword containing `reg_addr`.
+// `device` and `function` are truncated to 5 and 3 bits.
+pub fn config_address(bus: u8, device: u8, function: u8, reg_addr: u8) -> u32 {
+    let mut value = 0;
+    value = *value.set_bit(31, true)
+        .set_bits(24..31, 0)
+        .set_bits(16..24, bus as u32)
+        .set_bits(11..16, device as u32 & 0x1f)
+        .set_bits(8..11, function as u32 & 0x7)
+        .set_bits(0..8, reg_addr as u32 & 0xfc);
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::config_address;
+    use proptest::prelude::*;
+
+    #[test]
+    fn known_addresses() {
+        assert_eq!(config_address(0, 0, 0, 0), 0x8000_0000);
+        // bus 1, device 2, function 3, class code register
+        assert_eq!(config_address(1, 2, 3, 0x08), 0x8001_1308);
+        assert_eq!(config_address(0xff, 0x1f, 0x7, 0xfc), 0x80ff_fffc);
+    }
+
+    proptest! {
+        #[test]
+        fn fields_do_not_overlap(bus: u8, device in 0..32u8, function in 0..8u8, reg_addr: u8) {
+            let address = config_address(bus, device, function, reg_addr);
+            prop_assert_eq!(address >> 31, 1);
+            prop_assert_eq!((address >> 24) & 0x7f, 0);
+            prop_assert_eq!((address >> 16) as u8, bus);
+            prop_assert_eq!((address >> 11) & 0x1f, device as u32);
+            prop_assert_eq!((address >> 8) & 0x7, function as u32);
+            prop_assert_eq!(address & 0xff, reg_addr as u32 & 0xfc);
+        }
+
+        #[test]
+        fn out_of_range_device_and_function_are_truncated(bus: u8, device: u8, function: u8, reg_addr: u8) {
+            prop_assert_eq!(
+                config_address(bus, device, function, reg_addr),
+                config_address(bus, device & 0x1f, function & 0x7, reg_addr)
+            );
+        }
+    }
+}
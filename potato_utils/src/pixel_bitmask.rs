@@ -0,0 +1,135 @@
+//! ビットマスク形式のピクセル
+//!
+//! GOP modes with `PixelBitMask` describe each channel by a mask instead of a fixed byte
+//! order, e.g. 10 bits per color (`0x3ff00000` / `0x000ffc00` / `0x000003ff`). The
+//! kernel's writers only deal in 32-bit pixels, so `is_supported` also requires the masks
+//! to make up a 32-bit pixel.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ChannelMasks {
+    pub red: u32,
+    pub green: u32,
+    pub blue: u32,
+    pub reserved: u32,
+}
+
+impl ChannelMasks {
+    pub const EMPTY: Self = Self { red: 0, green: 0, blue: 0, reserved: 0 };
+
+    // the size of a pixel as the UEFI spec defines it: up to the highest set bit
+    pub fn bits_per_pixel(&self) -> u32 {
+        32 - (self.red | self.green | self.blue | self.reserved).leading_zeros()
+    }
+
+    // each color is a single run of bits, no two channels share a bit and the pixel is
+    // 32 bits wide
+    pub fn is_supported(&self) -> bool {
+        let colors = [self.red, self.green, self.blue];
+        let channels = [self.red, self.green, self.blue, self.reserved];
+        let overlapping = channels
+            .iter()
+            .enumerate()
+            .any(|(i, a)| channels[i + 1..].iter().any(|b| a & b != 0));
+        colors.iter().all(|&mask| is_contiguous(mask))
+            && !overlapping
+            && self.bits_per_pixel() > 24
+    }
+
+    pub fn encode(&self, red: u8, green: u8, blue: u8) -> u32 {
+        pack_channel(self.red, red) | pack_channel(self.green, green) | pack_channel(self.blue, blue)
+    }
+
+    pub fn decode(&self, pixel: u32) -> (u8, u8, u8) {
+        (
+            unpack_channel(self.red, pixel),
+            unpack_channel(self.green, pixel),
+            unpack_channel(self.blue, pixel),
+        )
+    }
+}
+
+fn is_contiguous(mask: u32) -> bool {
+    if mask == 0 {
+        return false;
+    }
+    let low = mask >> mask.trailing_zeros();
+    low & low.wrapping_add(1) == 0
+}
+
+// the largest value of a channel `bits` wide, without overflowing at 32 bits
+fn channel_max(bits: u32) -> u64 {
+    (1u64 << bits) - 1
+}
+
+// `value` scaled from 8 bits to the width of `mask` (rounded) and moved into place
+fn pack_channel(mask: u32, value: u8) -> u32 {
+    if mask == 0 {
+        return 0;
+    }
+    let bits = mask.count_ones();
+    let scaled = (value as u64 * channel_max(bits) + 127) / 255;
+    ((scaled as u32) << mask.trailing_zeros()) & mask
+}
+
+fn unpack_channel(mask: u32, pixel: u32) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+    let bits = mask.count_ones();
+    let value = ((pixel & mask) >> mask.trailing_zeros()) as u64;
+    ((value * 255 + channel_max(bits) / 2) / channel_max(bits)) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ChannelMasks;
+    use proptest::prelude::*;
+
+    const BGRX: ChannelMasks = ChannelMasks { red: 0xff0000, green: 0xff00, blue: 0xff, reserved: 0xff000000 };
+    const RGB10: ChannelMasks = ChannelMasks { red: 0x3ff00000, green: 0xffc00, blue: 0x3ff, reserved: 0xc0000000 };
+    const RGB565: ChannelMasks = ChannelMasks { red: 0xf800, green: 0x7e0, blue: 0x1f, reserved: 0 };
+
+    #[test]
+    fn eight_bit_channels_are_bytes() {
+        assert_eq!(BGRX.encode(0x12, 0x34, 0x56), 0x123456);
+        assert_eq!(BGRX.decode(0xff123456), (0x12, 0x34, 0x56));
+        assert_eq!(BGRX.bits_per_pixel(), 32);
+    }
+
+    #[test]
+    fn wider_and_narrower_channels_are_scaled() {
+        assert_eq!(RGB10.encode(255, 0, 255), 0x3ff003ff);
+        assert_eq!(RGB10.encode(0, 128, 0), 514 << 10);
+        assert_eq!(RGB565.encode(255, 255, 255), 0xffff);
+        assert_eq!(RGB565.bits_per_pixel(), 16);
+    }
+
+    #[test]
+    fn supported_masks() {
+        assert!(BGRX.is_supported());
+        assert!(RGB10.is_supported());
+        // 16-bit pixels
+        assert!(!RGB565.is_supported());
+        // 24-bit pixels
+        assert!(!ChannelMasks { reserved: 0, ..BGRX }.is_supported());
+        // overlapping channels
+        assert!(!ChannelMasks { green: 0x1ff00, ..BGRX }.is_supported());
+        // split channel
+        assert!(!ChannelMasks { blue: 0x81, ..BGRX }.is_supported());
+        // missing channel
+        assert!(!ChannelMasks { blue: 0, ..BGRX }.is_supported());
+        assert!(!ChannelMasks::EMPTY.is_supported());
+    }
+
+    proptest! {
+        // a channel of at least 8 bits keeps every 8-bit value
+        #[test]
+        fn round_trip(shift in 0u32..=2, width in 8u32..=10, r: u8, g: u8, b: u8) {
+            let mask = |i: u32| ((1u32 << width) - 1) << (shift + i * width);
+            let masks = ChannelMasks { red: mask(2), green: mask(1), blue: mask(0), reserved: 0 };
+            let pixel = masks.encode(r, g, b);
+            prop_assert_eq!(pixel & !(masks.red | masks.green | masks.blue), 0);
+            prop_assert_eq!(masks.decode(pixel), (r, g, b));
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! 直線と円のラスタライズ
+//!
+//! Integer-only algorithms that hand every pixel to a callback, so the caller does the
+//! clipping and the writing. Coordinates are signed because a shape may stick out over
+//! the top or left edge of the screen.
+
+// Bresenham; both ends are drawn, every pixel once
+pub fn line(x0: isize, y0: isize, x1: isize, y1: isize, mut plot: impl FnMut(isize, isize)) {
+    let dx = (x1 - x0).abs();
+    let dy = -(y1 - y0).abs();
+    let sx = if x0 < x1 { 1 } else { -1 };
+    let sy = if y0 < y1 { 1 } else { -1 };
+    let (mut x, mut y) = (x0, y0);
+    let mut err = dx + dy;
+    loop {
+        plot(x, y);
+        if x == x1 && y == y1 {
+            return;
+        }
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y += sy;
+        }
+    }
+}
+
+// midpoint circle outline; every pixel once, nothing for a negative radius
+pub fn circle(cx: isize, cy: isize, radius: isize, mut plot: impl FnMut(isize, isize)) {
+    if radius < 0 {
+        return;
+    }
+    let (mut x, mut y) = (radius, 0);
+    let mut d = 1 - radius;
+    while y <= x {
+        // the eight octants meet at x == y and at the axes, where points coincide
+        let points = [(x, y), (y, x), (-y, x), (-x, y), (-x, -y), (-y, -x), (y, -x), (x, -y)];
+        for (i, &(px, py)) in points.iter().enumerate() {
+            if !points[..i].contains(&(px, py)) {
+                plot(cx + px, cy + py);
+            }
+        }
+        y += 1;
+        if d < 0 {
+            d += 2 * y + 1;
+        } else {
+            x -= 1;
+            d += 2 * (y - x) + 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{circle, line};
+    use proptest::prelude::*;
+    use std::collections::BTreeSet;
+    use std::vec::Vec;
+
+    fn line_points(x0: isize, y0: isize, x1: isize, y1: isize) -> Vec<(isize, isize)> {
+        let mut points = Vec::new();
+        line(x0, y0, x1, y1, |x, y| points.push((x, y)));
+        points
+    }
+
+    fn circle_points(radius: isize) -> Vec<(isize, isize)> {
+        let mut points = Vec::new();
+        circle(0, 0, radius, |x, y| points.push((x, y)));
+        points
+    }
+
+    #[test]
+    fn horizontal_and_diagonal_lines() {
+        assert_eq!(line_points(0, 0, 3, 0), [(0, 0), (1, 0), (2, 0), (3, 0)]);
+        assert_eq!(line_points(2, 2, 0, 0), [(2, 2), (1, 1), (0, 0)]);
+        assert_eq!(line_points(5, 5, 5, 5), [(5, 5)]);
+    }
+
+    #[test]
+    fn small_circles() {
+        assert_eq!(circle_points(0), [(0, 0)]);
+        let points: BTreeSet<_> = circle_points(1).into_iter().collect();
+        let expected: BTreeSet<_> = [(1, 0), (0, 1), (-1, 0), (0, -1)].iter().copied().collect();
+        assert_eq!(points, expected);
+        assert!(circle_points(-1).is_empty());
+    }
+
+    proptest! {
+        #[test]
+        fn line_is_connected(x0 in -50isize..50, y0 in -50isize..50, x1 in -50isize..50, y1 in -50isize..50) {
+            let points = line_points(x0, y0, x1, y1);
+            prop_assert_eq!(points.first(), Some(&(x0, y0)));
+            prop_assert_eq!(points.last(), Some(&(x1, y1)));
+            prop_assert_eq!(points.len() as isize, (x1 - x0).abs().max((y1 - y0).abs()) + 1);
+            for pair in points.windows(2) {
+                let (a, b) = (pair[0], pair[1]);
+                prop_assert!((a.0 - b.0).abs() <= 1 && (a.1 - b.1).abs() <= 1 && a != b);
+            }
+        }
+
+        #[test]
+        fn circle_points_are_on_the_circle(radius in 0isize..100) {
+            let points = circle_points(radius);
+            let unique: BTreeSet<_> = points.iter().copied().collect();
+            prop_assert_eq!(unique.len(), points.len());
+            for (x, y) in points {
+                let distance = ((x * x + y * y) as f64).sqrt();
+                prop_assert!((distance - radius as f64).abs() < 1.0);
+                prop_assert!(unique.contains(&(-x, y)) && unique.contains(&(y, x)));
+            }
+        }
+    }
+}
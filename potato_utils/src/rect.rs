@@ -0,0 +1,294 @@
+//! 矩形と更新領域
+//!
+//! `Rect` is a half-open pixel rectangle: `x..x + width` by `y..y + height`.
+//! `DirtyRegion` collects the rectangles drawn into since the last flush in a fixed
+//! number of slots. Overlapping rectangles are merged into their bounding box, and when
+//! the slots run out the new rectangle is merged into the one it grows the least, so the
+//! region always covers everything added and may cover a little more. `clip_copy` clips
+//! a blit against the screen on both ends.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    pub const fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Self { x, y, width, height }
+    }
+
+    // exclusive
+    pub const fn right(&self) -> usize {
+        self.x + self.width
+    }
+
+    // exclusive
+    pub const fn bottom(&self) -> usize {
+        self.y + self.height
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    pub const fn area(&self) -> usize {
+        self.width * self.height
+    }
+
+    pub fn contains_point(&self, x: usize, y: usize) -> bool {
+        self.x <= x && x < self.right() && self.y <= y && y < self.bottom()
+    }
+
+    // an empty rectangle is contained in everything
+    pub fn contains(&self, other: &Rect) -> bool {
+        other.is_empty()
+            || (self.x <= other.x
+                && self.y <= other.y
+                && other.right() <= self.right()
+                && other.bottom() <= self.bottom())
+    }
+
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+        if x < right && y < bottom {
+            Some(Rect::new(x, y, right - x, bottom - y))
+        } else {
+            None
+        }
+    }
+
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.intersection(other).is_some()
+    }
+
+    // the bounding box of both; an empty rectangle adds nothing
+    pub fn union(&self, other: &Rect) -> Rect {
+        if self.is_empty() {
+            return *other;
+        }
+        if other.is_empty() {
+            return *self;
+        }
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = self.right().max(other.right());
+        let bottom = self.bottom().max(other.bottom());
+        Rect::new(x, y, right - x, bottom - y)
+    }
+
+    // the part inside a `width` x `height` screen
+    pub fn clip(&self, width: usize, height: usize) -> Option<Rect> {
+        self.intersection(&Rect::new(0, 0, width, height))
+    }
+}
+
+// For copying `src` so that its top-left corner lands on `dst`: the part of `src` that
+// is inside `bounds` and also lands inside it, and where that part goes.
+pub fn clip_copy(src: Rect, dst: (usize, usize), bounds: Rect) -> Option<(Rect, (usize, usize))> {
+    let visible = src.intersection(&bounds)?;
+    let moved = Rect::new(
+        dst.0 + (visible.x - src.x),
+        dst.1 + (visible.y - src.y),
+        visible.width,
+        visible.height,
+    );
+    let landed = moved.intersection(&bounds)?;
+    let src = Rect::new(
+        visible.x + (landed.x - moved.x),
+        visible.y + (landed.y - moved.y),
+        landed.width,
+        landed.height,
+    );
+    Some((src, (landed.x, landed.y)))
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DirtyRegion<const N: usize> {
+    rects: [Option<Rect>; N],
+}
+
+impl<const N: usize> DirtyRegion<N> {
+    pub const fn new() -> Self {
+        Self { rects: [None; N] }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rects.iter().all(Option::is_none)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Rect> {
+        self.rects.iter().flatten()
+    }
+
+    pub fn clear(&mut self) {
+        self.rects = [None; N];
+    }
+
+    // the rectangles added since the last call
+    pub fn take(&mut self) -> Self {
+        core::mem::take(self)
+    }
+
+    pub fn bounding_box(&self) -> Option<Rect> {
+        self.iter().copied().reduce(|a, b| a.union(&b))
+    }
+
+    pub fn add(&mut self, rect: Rect) {
+        if rect.is_empty() || self.iter().any(|r| r.contains(&rect)) {
+            return;
+        }
+        // merging can make the box overlap other rectangles, so repeat until it does not
+        let mut rect = rect;
+        while let Some(slot) = self.rects.iter_mut().find(|r| matches!(r, Some(r) if r.intersects(&rect))) {
+            rect = rect.union(&slot.take().unwrap());
+        }
+        if let Some(slot) = self.rects.iter_mut().find(|r| r.is_none()) {
+            *slot = Some(rect);
+            return;
+        }
+        // full (or N == 0)
+        let Some(slot) = self
+            .rects
+            .iter_mut()
+            .min_by_key(|r| r.map_or(0, |r| r.union(&rect).area() - r.area()))
+        else {
+            return;
+        };
+        *slot = Some(slot.unwrap().union(&rect));
+    }
+}
+
+impl<const N: usize> Default for DirtyRegion<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{clip_copy, DirtyRegion, Rect};
+    use proptest::prelude::*;
+    use std::vec::Vec;
+
+    #[test]
+    fn rect_geometry() {
+        let a = Rect::new(0, 0, 10, 10);
+        let b = Rect::new(5, 5, 10, 10);
+        assert_eq!(a.intersection(&b), Some(Rect::new(5, 5, 5, 5)));
+        assert_eq!(a.union(&b), Rect::new(0, 0, 15, 15));
+        assert!(a.contains_point(9, 9));
+        assert!(!a.contains_point(10, 9));
+        // touching edges do not intersect
+        assert!(!a.intersects(&Rect::new(10, 0, 5, 5)));
+        assert!(a.contains(&Rect::new(2, 2, 8, 8)));
+        assert!(a.contains(&Rect::new(100, 100, 0, 0)));
+        assert_eq!(a.union(&Rect::new(100, 100, 0, 5)), a);
+        assert_eq!(b.clip(12, 8), Some(Rect::new(5, 5, 7, 3)));
+        assert_eq!(b.clip(5, 5), None);
+    }
+
+    #[test]
+    fn overlapping_rects_merge() {
+        let mut region = DirtyRegion::<4>::new();
+        region.add(Rect::new(0, 0, 10, 10));
+        region.add(Rect::new(20, 0, 10, 10));
+        assert_eq!(region.iter().count(), 2);
+        // bridges both
+        region.add(Rect::new(5, 5, 20, 2));
+        let rects: Vec<_> = region.iter().copied().collect();
+        assert_eq!(rects, [Rect::new(0, 0, 30, 10)]);
+    }
+
+    #[test]
+    fn contained_rect_is_ignored() {
+        let mut region = DirtyRegion::<4>::new();
+        region.add(Rect::new(0, 0, 10, 10));
+        region.add(Rect::new(1, 1, 1, 1));
+        region.add(Rect::new(3, 3, 0, 0));
+        let rects: Vec<_> = region.iter().copied().collect();
+        assert_eq!(rects, [Rect::new(0, 0, 10, 10)]);
+    }
+
+    #[test]
+    fn full_region_merges_into_the_closest() {
+        let mut region = DirtyRegion::<2>::new();
+        region.add(Rect::new(0, 0, 1, 1));
+        region.add(Rect::new(100, 100, 1, 1));
+        region.add(Rect::new(2, 0, 1, 1));
+        let rects: Vec<_> = region.iter().copied().collect();
+        assert_eq!(rects, [Rect::new(0, 0, 3, 1), Rect::new(100, 100, 1, 1)]);
+    }
+
+    #[test]
+    fn take_empties() {
+        let mut region = DirtyRegion::<2>::new();
+        region.add(Rect::new(0, 0, 1, 1));
+        let taken = region.take();
+        assert!(region.is_empty());
+        assert_eq!(taken.bounding_box(), Some(Rect::new(0, 0, 1, 1)));
+    }
+
+    #[test]
+    fn copy_is_clipped_on_both_ends() {
+        let screen = Rect::new(0, 0, 100, 100);
+        assert_eq!(
+            clip_copy(Rect::new(0, 10, 100, 90), (0, 0), screen),
+            Some((Rect::new(0, 10, 100, 90), (0, 0)))
+        );
+        // the source sticks out to the right, the destination out at the bottom
+        assert_eq!(
+            clip_copy(Rect::new(90, 0, 20, 20), (10, 90), screen),
+            Some((Rect::new(90, 0, 10, 10), (10, 90)))
+        );
+        assert_eq!(clip_copy(Rect::new(0, 0, 10, 10), (100, 0), screen), None);
+    }
+
+    fn rect() -> impl Strategy<Value = Rect> {
+        (0usize..64, 0usize..64, 0usize..16, 0usize..16).prop_map(|(x, y, w, h)| Rect::new(x, y, w, h))
+    }
+
+    proptest! {
+        #[test]
+        fn region_covers_every_added_pixel(rects in prop::collection::vec(rect(), 0..32)) {
+            let mut region = DirtyRegion::<4>::new();
+            for rect in &rects {
+                region.add(*rect);
+            }
+            prop_assert!(region.iter().count() <= 4);
+            for rect in &rects {
+                for y in rect.y..rect.bottom() {
+                    for x in rect.x..rect.right() {
+                        prop_assert!(region.iter().any(|r| r.contains_point(x, y)));
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn clipped_copy_stays_inside(src in rect(), dx in 0usize..80, dy in 0usize..80) {
+            let screen = Rect::new(0, 0, 48, 48);
+            if let Some((clipped, (x, y))) = clip_copy(src, (dx, dy), screen) {
+                prop_assert!(screen.contains(&clipped) && src.contains(&clipped));
+                prop_assert!(screen.contains(&Rect::new(x, y, clipped.width, clipped.height)));
+                // the same offset as the whole copy
+                prop_assert_eq!(x as isize - clipped.x as isize, dx as isize - src.x as isize);
+                prop_assert_eq!(y as isize - clipped.y as isize, dy as isize - src.y as isize);
+            }
+        }
+
+        #[test]
+        fn intersection_is_in_both(a in rect(), b in rect()) {
+            if let Some(i) = a.intersection(&b) {
+                prop_assert!(a.contains(&i) && b.contains(&i));
+            }
+            let u = a.union(&b);
+            prop_assert!(u.contains(&a) && u.contains(&b));
+        }
+    }
+}
@@ -0,0 +1,122 @@
+//! 上書きリングバッファ
+//!
+//! Keeps the last `N` values pushed; a push into a full ring drops the oldest one.
+
+pub struct Ring<T, const N: usize> {
+    slots: [Option<T>; N],
+    // index of the slot the next push goes to
+    head: usize,
+    len: usize,
+    pushed: u64,
+}
+
+impl<T, const N: usize> Ring<T, N> {
+    pub const fn new() -> Self {
+        Self {
+            slots: [const { None }; N],
+            head: 0,
+            len: 0,
+            pushed: 0,
+        }
+    }
+
+    pub fn push(&mut self, value: T) {
+        if N == 0 {
+            return;
+        }
+        self.slots[self.head] = Some(value);
+        self.head = (self.head + 1) % N;
+        self.len = (self.len + 1).min(N);
+        self.pushed += 1;
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    // values pushed over the lifetime of the ring, including dropped ones
+    pub fn pushed(&self) -> u64 {
+        self.pushed
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.pushed - self.len as u64
+    }
+
+    pub fn clear(&mut self) {
+        self.slots.iter_mut().for_each(|slot| *slot = None);
+        self.len = 0;
+    }
+
+    // oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        let start = (self.head + N - self.len) % N.max(1);
+        (0..self.len).filter_map(move |i| self.slots[(start + i) % N].as_ref())
+    }
+}
+
+impl<T, const N: usize> Default for Ring<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Ring;
+    use proptest::prelude::*;
+    use std::vec::Vec;
+
+    #[test]
+    fn keeps_the_last_values() {
+        let mut ring: Ring<u32, 3> = Ring::new();
+        assert!(ring.is_empty());
+        for i in 0..5 {
+            ring.push(i);
+        }
+        assert_eq!(ring.iter().copied().collect::<Vec<_>>(), [2, 3, 4]);
+        assert_eq!(ring.len(), 3);
+        assert_eq!(ring.pushed(), 5);
+        assert_eq!(ring.dropped(), 2);
+    }
+
+    #[test]
+    fn clear() {
+        let mut ring: Ring<u32, 3> = Ring::new();
+        ring.push(1);
+        ring.push(2);
+        ring.clear();
+        assert!(ring.is_empty());
+        ring.push(3);
+        assert_eq!(ring.iter().copied().collect::<Vec<_>>(), [3]);
+    }
+
+    #[test]
+    fn zero_capacity() {
+        let mut ring: Ring<u32, 0> = Ring::new();
+        ring.push(1);
+        assert!(ring.is_empty());
+        assert_eq!(ring.iter().count(), 0);
+    }
+
+    proptest! {
+        #[test]
+        fn iter_is_the_tail_of_what_was_pushed(values in prop::collection::vec(any::<u16>(), 0..40)) {
+            let mut ring: Ring<u16, 8> = Ring::new();
+            for &value in &values {
+                ring.push(value);
+            }
+            let tail = &values[values.len().saturating_sub(8)..];
+            prop_assert_eq!(ring.iter().copied().collect::<Vec<_>>(), tail);
+            prop_assert_eq!(ring.pushed(), values.len() as u64);
+        }
+    }
+}
@@ -0,0 +1,182 @@
+//! コンソールの文字バッファ
+//!
+//! The characters and the cursor of the kernel console, without the drawing. `put_string`
+//! stops storing at the second-to-last column of a line; a new line on the last row
+//! scrolls everything up by one row and raises the flag `take_scrolled` returns, because
+//! every cell then has to be redrawn.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+    pub x: usize,
+    pub y: usize,
+}
+
+pub struct TextBuffer {
+    rows: usize,
+    columns: usize,
+    buffer: Vec<char>, // rows * columns, allocated by `init`
+    cursor: Cursor,
+    scrolled: bool,
+}
+
+impl TextBuffer {
+    pub const fn new(rows: usize, columns: usize) -> Self {
+        Self {
+            rows,
+            columns,
+            buffer: Vec::new(),
+            cursor: Cursor { x: 0, y: 0 },
+            scrolled: false,
+        }
+    }
+
+    pub fn init(&mut self) {
+        self.buffer = vec![' '; self.rows * self.columns];
+        self.cursor = Cursor { x: 0, y: 0 };
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        !self.buffer.is_empty()
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn cursor(&self) -> Cursor {
+        self.cursor
+    }
+
+    // ' ' for a cell nothing was written to
+    pub fn get(&self, x: usize, y: usize) -> char {
+        self.buffer[y * self.columns + x]
+    }
+
+    pub fn row(&self, y: usize) -> &[char] {
+        &self.buffer[y * self.columns..(y + 1) * self.columns]
+    }
+
+    // whether the buffer scrolled since the last call
+    pub fn take_scrolled(&mut self) -> bool {
+        core::mem::replace(&mut self.scrolled, false)
+    }
+
+    pub fn put_string(&mut self, s: &str) {
+        if !self.is_initialized() {
+            return;
+        }
+        s.chars().for_each(|c| {
+            if c == '\n' { self.new_line() }
+            else if self.cursor.x < self.columns - 1 {
+                let index = self.cursor.y * self.columns + self.cursor.x;
+                self.buffer[index] = c;
+                self.cursor.x += 1;
+            }
+            // TODO: else  { todo!() }
+            // (currently it stops storing s to self.buffer when self.cursor.x > self.columns)
+        });
+    }
+
+    fn new_line(&mut self) {
+        self.cursor.x = 0;
+        if self.cursor.y == self.rows - 1 {
+            // スクロールの必要あり
+            self.scroll_up();
+        } else {
+            // スクロールの必要なし
+            self.cursor.y += 1;
+        }
+    }
+
+    fn scroll_up(&mut self) {
+        self.scrolled = true;
+        let end = self.rows * self.columns;
+        let src = self.columns..end;
+        self.buffer.copy_within(src, 0);
+        self.buffer[(end-self.columns)..end].fill(' ');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cursor, TextBuffer};
+    use proptest::prelude::*;
+    use std::string::String;
+
+    const ROWS: usize = 10;
+    const COLUMNS: usize = 80;
+
+    fn buffer() -> TextBuffer {
+        let mut text = TextBuffer::new(ROWS, COLUMNS);
+        text.init();
+        text
+    }
+
+    #[test]
+    fn put_string() {
+        let mut text = buffer();
+        text.put_string("ab\nc");
+        assert_eq!(&text.row(0)[..3], &['a', 'b', ' ']);
+        assert_eq!(text.get(0, 1), 'c');
+        assert_eq!(text.cursor(), Cursor { x: 1, y: 1 });
+        assert!(!text.take_scrolled());
+    }
+
+    #[test]
+    fn long_line_is_truncated() {
+        let mut text = buffer();
+        for _ in 0..COLUMNS + 5 {
+            text.put_string("x");
+        }
+        assert_eq!(text.cursor(), Cursor { x: COLUMNS - 1, y: 0 });
+        assert_eq!(text.get(COLUMNS - 1, 0), ' ');
+    }
+
+    #[test]
+    fn new_line_on_last_row_scrolls() {
+        let mut text = buffer();
+        for i in 0..ROWS {
+            text.put_string(&std::format!("{}\n", i));
+        }
+        assert!(text.take_scrolled());
+        assert!(!text.take_scrolled());
+        assert_eq!(text.get(0, 0), '1');
+        assert_eq!(text.get(0, ROWS - 2), '9');
+        assert!(text.row(ROWS - 1).iter().all(|&c| c == ' '));
+        assert_eq!(text.cursor(), Cursor { x: 0, y: ROWS - 1 });
+    }
+
+    #[test]
+    fn output_before_init_is_dropped() {
+        let mut text = TextBuffer::new(ROWS, COLUMNS);
+        text.put_string("dropped");
+        assert!(!text.is_initialized());
+        assert_eq!(text.cursor(), Cursor { x: 0, y: 0 });
+    }
+
+    proptest! {
+        // the buffer shows the last ROWS lines, each cut at COLUMNS - 1 characters
+        #[test]
+        fn shows_the_tail_of_the_output(lines in prop::collection::vec("[a-z ]{0,100}", 1..30)) {
+            let mut text = buffer();
+            let output: String = lines.iter().map(|line| std::format!("{}\n", line)).collect();
+            text.put_string(&output);
+
+            let mut expected: std::vec::Vec<&str> = lines.iter().map(|line| &line[..line.len().min(COLUMNS - 1)]).collect();
+            expected.push("");
+            let visible = &expected[expected.len().saturating_sub(ROWS)..];
+            for (y, line) in visible.iter().enumerate() {
+                let row: String = text.row(y).iter().collect();
+                prop_assert_eq!(row.trim_end(), line.trim_end());
+            }
+            prop_assert_eq!(text.cursor(), Cursor { x: 0, y: visible.len() - 1 });
+        }
+    }
+}
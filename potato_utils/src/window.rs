@@ -0,0 +1,152 @@
+//! ウィンドウの枠
+//!
+//! The layout of a window's decorations: a border around everything, a title bar along the
+//! top with a close button at its right end, and the client area below the title bar.
+//! Coordinates are relative to the window's top left corner, so the same frame works
+//! wherever the window is dragged to.
+
+use crate::rect::Rect;
+
+pub const BORDER_WIDTH: usize = 1;
+pub const TITLE_BAR_HEIGHT: usize = 20;
+pub const CLOSE_BUTTON_SIZE: usize = 14;
+// between the close button and the edges of the title bar
+const CLOSE_BUTTON_MARGIN: usize = (TITLE_BAR_HEIGHT - CLOSE_BUTTON_SIZE) / 2;
+// left of the title text
+const TITLE_PADDING: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FramePart {
+    CloseButton,
+    TitleBar,
+    Client,
+    Border,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowFrame {
+    width: usize,
+    height: usize,
+}
+
+impl WindowFrame {
+    // a frame around a `client_width` x `client_height` client area
+    pub const fn for_client(client_width: usize, client_height: usize) -> Self {
+        Self {
+            width: client_width + 2 * BORDER_WIDTH,
+            height: client_height + TITLE_BAR_HEIGHT + 2 * BORDER_WIDTH,
+        }
+    }
+
+    // of the whole window
+    pub const fn width(&self) -> usize {
+        self.width
+    }
+
+    pub const fn height(&self) -> usize {
+        self.height
+    }
+
+    pub const fn bounds(&self) -> Rect {
+        Rect::new(0, 0, self.width, self.height)
+    }
+
+    pub const fn title_bar(&self) -> Rect {
+        Rect::new(BORDER_WIDTH, BORDER_WIDTH, self.width - 2 * BORDER_WIDTH, TITLE_BAR_HEIGHT)
+    }
+
+    // at the right end of the title bar; overlaps the title bar's left part in windows
+    // narrower than a close button
+    pub fn close_button(&self) -> Rect {
+        let title_bar = self.title_bar();
+        let x = title_bar.right().saturating_sub(CLOSE_BUTTON_MARGIN + CLOSE_BUTTON_SIZE);
+        Rect::new(x, title_bar.y + CLOSE_BUTTON_MARGIN, CLOSE_BUTTON_SIZE, CLOSE_BUTTON_SIZE)
+    }
+
+    pub const fn client_area(&self) -> Rect {
+        Rect::new(
+            BORDER_WIDTH,
+            BORDER_WIDTH + TITLE_BAR_HEIGHT,
+            self.width - 2 * BORDER_WIDTH,
+            self.height - TITLE_BAR_HEIGHT - 2 * BORDER_WIDTH,
+        )
+    }
+
+    // where the title text starts, for glyphs `glyph_height` high
+    pub fn title_origin(&self, glyph_height: usize) -> (usize, usize) {
+        let title_bar = self.title_bar();
+        (title_bar.x + TITLE_PADDING, title_bar.y + TITLE_BAR_HEIGHT.saturating_sub(glyph_height) / 2)
+    }
+
+    // how many `char_width` wide characters fit between the title's start and the close button
+    pub fn title_capacity(&self, char_width: usize) -> usize {
+        let (x, _) = self.title_origin(0);
+        self.close_button().x.saturating_sub(x + TITLE_PADDING) / char_width.max(1)
+    }
+
+    // the part of the frame at (x, y), None outside the window
+    pub fn hit_test(&self, x: isize, y: isize) -> Option<FramePart> {
+        if x < 0 || y < 0 {
+            return None;
+        }
+        let (x, y) = (x as usize, y as usize);
+        if !self.bounds().contains_point(x, y) {
+            None
+        } else if self.close_button().contains_point(x, y) {
+            Some(FramePart::CloseButton)
+        } else if self.title_bar().contains_point(x, y) {
+            Some(FramePart::TitleBar)
+        } else if self.client_area().contains_point(x, y) {
+            Some(FramePart::Client)
+        } else {
+            Some(FramePart::Border)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FramePart, WindowFrame, BORDER_WIDTH, TITLE_BAR_HEIGHT};
+    use crate::rect::Rect;
+    use proptest::prelude::*;
+
+    #[test]
+    fn layout() {
+        let frame = WindowFrame::for_client(100, 50);
+        assert_eq!((frame.width(), frame.height()), (102, 72));
+        assert_eq!(frame.title_bar(), Rect::new(1, 1, 100, 20));
+        assert_eq!(frame.close_button(), Rect::new(84, 4, 14, 14));
+        assert_eq!(frame.client_area(), Rect::new(1, 21, 100, 50));
+        assert_eq!(frame.title_origin(16), (5, 3));
+        // from x = 5 to 80
+        assert_eq!(frame.title_capacity(8), 9);
+    }
+
+    #[test]
+    fn hit_test() {
+        let frame = WindowFrame::for_client(100, 50);
+        assert_eq!(frame.hit_test(0, 0), Some(FramePart::Border));
+        assert_eq!(frame.hit_test(10, 10), Some(FramePart::TitleBar));
+        assert_eq!(frame.hit_test(90, 10), Some(FramePart::CloseButton));
+        assert_eq!(frame.hit_test(50, 40), Some(FramePart::Client));
+        assert_eq!(frame.hit_test(101, 71), Some(FramePart::Border));
+        assert_eq!(frame.hit_test(102, 10), None);
+        assert_eq!(frame.hit_test(-1, 10), None);
+    }
+
+    proptest! {
+        #[test]
+        fn parts_are_inside_the_window(width in 0usize..300, height in 0usize..300) {
+            let frame = WindowFrame::for_client(width, height);
+            let bounds = frame.bounds();
+            prop_assert!(bounds.contains(&frame.title_bar()));
+            prop_assert!(bounds.contains(&frame.client_area()));
+            prop_assert_eq!(frame.client_area().width, width);
+            prop_assert_eq!(frame.client_area().height, height);
+            prop_assert!(!frame.title_bar().intersects(&frame.client_area()));
+            prop_assert_eq!(frame.title_bar().bottom(), frame.client_area().y);
+            prop_assert_eq!(frame.client_area().bottom() + BORDER_WIDTH, frame.height());
+            prop_assert!(frame.title_bar().height == TITLE_BAR_HEIGHT);
+        }
+    }
+}
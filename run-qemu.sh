@@ -20,6 +20,8 @@ sudo mkdir -p $MNT/EFI/BOOT
 cd potato_loader && cargo build 
 cd ../
 
+sh tools/embed-symbols.sh $KERNEL_TARGET
+
 sudo cp $BOOTLOADER_TARGET $MNT/EFI/BOOT/BOOTX64.EFI
 sudo cp $KERNEL_TARGET $MNT/potatOS.elf
 
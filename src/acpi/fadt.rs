@@ -0,0 +1,97 @@
+//! FADT (Fixed ACPI Description Table, signature "FACP")
+
+use super::{GenericAddress, TableBytes};
+
+// flags
+pub const TMR_VAL_EXT: u32 = 1 << 8;
+pub const RESET_REG_SUP: u32 = 1 << 10;
+pub const HW_REDUCED_ACPI: u32 = 1 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PmTimer {
+    pub port: u16,
+    // the counter is 24 bits wide otherwise
+    pub is_32bit: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Fadt {
+    pub revision: u8,
+    pub flags: u32,
+    pub dsdt: u64,
+    pub sci_interrupt: u16,
+    pub smi_command_port: u32,
+    pub acpi_enable: u8,
+    pub acpi_disable: u8,
+    pub pm_timer: Option<PmTimer>,
+    // PM1a/PM1b control blocks (SLP_TYPx, SLP_EN) for legacy sleep
+    pub pm1a_control: Option<GenericAddress>,
+    pub pm1b_control: Option<GenericAddress>,
+    // only present if RESET_REG_SUP is set
+    pub reset_register: Option<GenericAddress>,
+    pub reset_value: u8,
+    // used instead of PM1x on hardware-reduced platforms
+    pub sleep_control: Option<GenericAddress>,
+    pub sleep_status: Option<GenericAddress>,
+    pub century: u8,
+    pub iapc_boot_arch: u16,
+}
+
+// the 64-bit X_ field if present, the 32-bit port otherwise
+fn io_block(table: &TableBytes, port_offset: usize, x_offset: usize) -> Option<GenericAddress> {
+    if let Some(gas) = table.gas(x_offset).filter(|gas| gas.is_valid()) {
+        return Some(gas);
+    }
+    let port = table.u32(port_offset).filter(|&port| port != 0)?;
+    Some(GenericAddress {
+        address_space: GenericAddress::SYSTEM_IO,
+        bit_width: 0,
+        bit_offset: 0,
+        access_size: 0,
+        address: port as u64,
+    })
+}
+
+impl Fadt {
+    pub(super) fn parse(table: TableBytes) -> Self {
+        let flags = table.u32(112).unwrap_or(0);
+        let dsdt = table.u64(140)
+            .filter(|&addr| addr != 0)
+            .or_else(|| table.u32(40).map(|addr| addr as u64))
+            .unwrap_or(0);
+        let pm_timer = if flags & HW_REDUCED_ACPI != 0 {
+            None
+        } else {
+            io_block(&table, 76, 208)
+                .and_then(|gas| gas.io_port())
+                .map(|port| PmTimer { port, is_32bit: flags & TMR_VAL_EXT != 0 })
+        };
+        let reset_register = if flags & RESET_REG_SUP != 0 {
+            table.gas(116).filter(|gas| gas.is_valid())
+        } else {
+            None
+        };
+        Self {
+            revision: table.revision(),
+            flags,
+            dsdt,
+            sci_interrupt: table.u16(46).unwrap_or(0),
+            smi_command_port: table.u32(48).unwrap_or(0),
+            acpi_enable: table.u8(52).unwrap_or(0),
+            acpi_disable: table.u8(53).unwrap_or(0),
+            pm_timer,
+            pm1a_control: io_block(&table, 64, 172),
+            pm1b_control: io_block(&table, 68, 184),
+            reset_register,
+            reset_value: table.u8(128).unwrap_or(0),
+            sleep_control: table.gas(244).filter(|gas| gas.is_valid()),
+            sleep_status: table.gas(256).filter(|gas| gas.is_valid()),
+            century: table.u8(108).unwrap_or(0),
+            iapc_boot_arch: table.u16(109).unwrap_or(0),
+        }
+    }
+
+    pub fn is_hardware_reduced(&self) -> bool {
+        self.flags & HW_REDUCED_ACPI != 0
+    }
+}
@@ -0,0 +1,41 @@
+//! HPET (High Precision Event Timer description table)
+
+use super::{GenericAddress, TableBytes};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Hpet {
+    pub hardware_revision: u8,
+    pub comparator_count: u8,
+    pub counter_is_64bit: bool,
+    pub legacy_replacement_capable: bool,
+    pub pci_vendor_id: u16,
+    // register block; always system memory in practice
+    pub base_address: GenericAddress,
+    pub hpet_number: u8,
+    // in periodic mode, in main counter ticks
+    pub minimum_tick: u16,
+    pub page_protection: u8,
+}
+
+impl Hpet {
+    pub(super) fn parse(table: TableBytes) -> Self {
+        let block_id = table.u32(36).unwrap_or(0);
+        Self {
+            hardware_revision: block_id as u8,
+            comparator_count: ((block_id >> 8) & 0x1f) as u8 + 1,
+            counter_is_64bit: block_id & (1 << 13) != 0,
+            legacy_replacement_capable: block_id & (1 << 15) != 0,
+            pci_vendor_id: (block_id >> 16) as u16,
+            base_address: table.gas(40).unwrap_or(GenericAddress {
+                address_space: GenericAddress::SYSTEM_MEMORY,
+                bit_width: 0,
+                bit_offset: 0,
+                access_size: 0,
+                address: 0,
+            }),
+            hpet_number: table.u8(52).unwrap_or(0),
+            minimum_tick: table.u16(53).unwrap_or(0),
+            page_protection: table.u8(55).unwrap_or(0),
+        }
+    }
+}
@@ -0,0 +1,190 @@
+//! MADT (Multiple APIC Description Table, signature "APIC")
+
+use super::TableBytes;
+use alloc::vec::Vec;
+
+const PCAT_COMPAT: u32 = 1 << 0;
+
+// entry types
+const PROCESSOR_LOCAL_APIC: u8 = 0;
+const IO_APIC: u8 = 1;
+const INTERRUPT_SOURCE_OVERRIDE: u8 = 2;
+const LOCAL_APIC_NMI: u8 = 4;
+const LOCAL_APIC_ADDRESS_OVERRIDE: u8 = 5;
+const PROCESSOR_LOCAL_X2APIC: u8 = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Polarity {
+    ConformsToBus,
+    ActiveHigh,
+    ActiveLow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerMode {
+    ConformsToBus,
+    Edge,
+    Level,
+}
+
+// MPS INTI flags
+fn inti_flags(flags: u16) -> (Polarity, TriggerMode) {
+    let polarity = match flags & 0b11 {
+        0b01 => Polarity::ActiveHigh,
+        0b11 => Polarity::ActiveLow,
+        _ => Polarity::ConformsToBus,
+    };
+    let trigger = match (flags >> 2) & 0b11 {
+        0b01 => TriggerMode::Edge,
+        0b11 => TriggerMode::Level,
+        _ => TriggerMode::ConformsToBus,
+    };
+    (polarity, trigger)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalApic {
+    pub processor_uid: u32,
+    pub apic_id: u32,
+    pub enabled: bool,
+    // disabled, but can be brought online later
+    pub online_capable: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoApic {
+    pub id: u8,
+    pub address: u32,
+    pub gsi_base: u32,
+}
+
+// ISA IRQ `source` is wired to `gsi` instead of the identity mapping
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InterruptSourceOverride {
+    pub bus: u8,
+    pub source: u8,
+    pub gsi: u32,
+    pub polarity: Polarity,
+    pub trigger: TriggerMode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalApicNmi {
+    // None: all processors
+    pub processor_uid: Option<u32>,
+    pub polarity: Polarity,
+    pub trigger: TriggerMode,
+    pub lint: u8,
+}
+
+#[derive(Debug, Clone)]
+pub struct Madt {
+    pub local_apic_address: u64,
+    // the 8259 PICs are present and have to be masked
+    pub has_legacy_pics: bool,
+    pub local_apics: Vec<LocalApic>,
+    pub io_apics: Vec<IoApic>,
+    pub interrupt_source_overrides: Vec<InterruptSourceOverride>,
+    pub local_apic_nmis: Vec<LocalApicNmi>,
+}
+
+impl Madt {
+    pub(super) fn parse(table: TableBytes) -> Self {
+        let mut madt = Self {
+            local_apic_address: table.u32(36).unwrap_or(0) as u64,
+            has_legacy_pics: table.u32(40).unwrap_or(0) & PCAT_COMPAT != 0,
+            local_apics: Vec::new(),
+            io_apics: Vec::new(),
+            interrupt_source_overrides: Vec::new(),
+            local_apic_nmis: Vec::new(),
+        };
+
+        let mut entries = table.tail(44);
+        while let (Some(ty), Some(len)) = (entries.u8(0), entries.u8(1)) {
+            if len < 2 {
+                break; // broken table
+            }
+            madt.parse_entry(ty, &entries);
+            entries = entries.tail(len as usize);
+        }
+        madt
+    }
+
+    fn parse_entry(&mut self, ty: u8, e: &TableBytes) -> Option<()> {
+        match ty {
+            PROCESSOR_LOCAL_APIC => {
+                let flags = e.u32(4)?;
+                self.local_apics.push(LocalApic {
+                    processor_uid: e.u8(2)? as u32,
+                    apic_id: e.u8(3)? as u32,
+                    enabled: flags & 1 != 0,
+                    online_capable: flags & 2 != 0,
+                });
+            }
+            PROCESSOR_LOCAL_X2APIC => {
+                let flags = e.u32(8)?;
+                self.local_apics.push(LocalApic {
+                    processor_uid: e.u32(12)?,
+                    apic_id: e.u32(4)?,
+                    enabled: flags & 1 != 0,
+                    online_capable: flags & 2 != 0,
+                });
+            }
+            IO_APIC => {
+                self.io_apics.push(IoApic {
+                    id: e.u8(2)?,
+                    address: e.u32(4)?,
+                    gsi_base: e.u32(8)?,
+                });
+            }
+            INTERRUPT_SOURCE_OVERRIDE => {
+                let (polarity, trigger) = inti_flags(e.u16(8)?);
+                self.interrupt_source_overrides.push(InterruptSourceOverride {
+                    bus: e.u8(2)?,
+                    source: e.u8(3)?,
+                    gsi: e.u32(4)?,
+                    polarity,
+                    trigger,
+                });
+            }
+            LOCAL_APIC_NMI => {
+                let (polarity, trigger) = inti_flags(e.u16(3)?);
+                let uid = e.u8(2)?;
+                self.local_apic_nmis.push(LocalApicNmi {
+                    processor_uid: if uid == 0xff { None } else { Some(uid as u32) },
+                    polarity,
+                    trigger,
+                    lint: e.u8(5)?,
+                });
+            }
+            LOCAL_APIC_ADDRESS_OVERRIDE => {
+                self.local_apic_address = e.u64(4)?;
+            }
+            _ => {}
+        }
+        Some(())
+    }
+
+    pub fn override_for(&self, isa_irq: u8) -> Option<&InterruptSourceOverride> {
+        self.interrupt_source_overrides.iter().find(|o| o.bus == 0 && o.source == isa_irq)
+    }
+
+    // GSI, polarity and trigger mode of an ISA IRQ. ISA interrupts are edge-triggered and
+    // active-high unless overridden.
+    pub fn isa_irq_to_gsi(&self, isa_irq: u8) -> (u32, Polarity, TriggerMode) {
+        match self.override_for(isa_irq) {
+            Some(o) => {
+                let polarity = match o.polarity {
+                    Polarity::ConformsToBus => Polarity::ActiveHigh,
+                    p => p,
+                };
+                let trigger = match o.trigger {
+                    TriggerMode::ConformsToBus => TriggerMode::Edge,
+                    t => t,
+                };
+                (o.gsi, polarity, trigger)
+            }
+            None => (isa_irq as u32, Polarity::ActiveHigh, TriggerMode::Edge),
+        }
+    }
+}
@@ -0,0 +1,54 @@
+//! MCFG (PCI Express memory mapped configuration space)
+
+use super::TableBytes;
+use alloc::vec::Vec;
+
+// ECAM area of one PCI segment group
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EcamRange {
+    pub base_address: u64,
+    pub segment_group: u16,
+    pub start_bus: u8,
+    pub end_bus: u8,
+}
+
+impl EcamRange {
+    // physical address of the 4 KiB configuration space of bus:device.function
+    pub fn config_address(&self, bus: u8, device: u8, function: u8) -> Option<u64> {
+        if bus < self.start_bus || self.end_bus < bus || device >= 32 || function >= 8 {
+            return None;
+        }
+        let offset = ((bus - self.start_bus) as u64) << 20 | (device as u64) << 15 | (function as u64) << 12;
+        Some(self.base_address + offset)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Mcfg {
+    pub ranges: Vec<EcamRange>,
+}
+
+impl Mcfg {
+    pub(super) fn parse(table: TableBytes) -> Self {
+        const ENTRY_SIZE: usize = 16;
+        // header + 8 reserved bytes
+        let mut entries = table.tail(44);
+        let mut ranges = Vec::new();
+        while entries.len() >= ENTRY_SIZE {
+            ranges.push(EcamRange {
+                base_address: entries.u64(0).unwrap_or(0),
+                segment_group: entries.u16(8).unwrap_or(0),
+                start_bus: entries.u8(10).unwrap_or(0),
+                end_bus: entries.u8(11).unwrap_or(0),
+            });
+            entries = entries.tail(ENTRY_SIZE);
+        }
+        Self { ranges }
+    }
+
+    pub fn range_for(&self, segment_group: u16, bus: u8) -> Option<&EcamRange> {
+        self.ranges.iter().find(|range| {
+            range.segment_group == segment_group && range.start_bus <= bus && bus <= range.end_bus
+        })
+    }
+}
@@ -0,0 +1,307 @@
+//! ACPI テーブル
+//!
+//! Starts from the RSDP found by potato_loader, walks the XSDT (or the RSDT on ACPI 1.0)
+//! and parses the tables the kernel cares about into owned structures. Tables are read
+//! through the direct map, so this needs `paging::init_paging` and the heap.
+//! AML (DSDT/SSDT) is not interpreted.
+
+pub mod fadt;
+pub mod madt;
+pub mod mcfg;
+pub mod hpet;
+
+pub use fadt::Fadt;
+pub use madt::Madt;
+pub use mcfg::Mcfg;
+pub use hpet::Hpet;
+
+use crate::paging::{self, PhysAddr};
+use crate::utils::init_once::InitOnce;
+use crate::{debug, warn};
+use alloc::vec::Vec;
+use boot_info::BootInfo;
+use core::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcpiError {
+    // the loader did not find an RSDP in the UEFI configuration table
+    NoRsdp,
+    InvalidSignature(Signature),
+    InvalidChecksum(Signature),
+    TableTooShort(Signature),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Signature(pub [u8; 4]);
+
+impl Signature {
+    pub const FADT: Self = Self(*b"FACP");
+    pub const MADT: Self = Self(*b"APIC");
+    pub const MCFG: Self = Self(*b"MCFG");
+    pub const HPET: Self = Self(*b"HPET");
+    pub const XSDT: Self = Self(*b"XSDT");
+    pub const RSDT: Self = Self(*b"RSDT");
+    // stands in for the 8-byte "RSD PTR " in errors
+    pub const RSDP: Self = Self(*b"RSD ");
+}
+
+impl fmt::Debug for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for &c in self.0.iter() {
+            write!(f, "{}", c as char)?;
+        }
+        Ok(())
+    }
+}
+
+// ------------------------------------------------------
+// Raw table access
+// ------------------------------------------------------
+
+const SDT_HEADER_SIZE: usize = 36;
+
+// Little-endian field access into a table. Older revisions of a table are shorter, so
+// fields beyond its length read as None. A whole table is at least a header long; only
+// `tail`s are shorter.
+#[derive(Clone, Copy)]
+pub struct TableBytes(&'static [u8]);
+
+impl TableBytes {
+    // The table at `phys`, sized by the length in its header. The signature and length are
+    // read from memory first, so an entry pointing at zeroes or at a cut off table is
+    // TableTooShort rather than a slice shorter than its header.
+    unsafe fn from_phys(phys: PhysAddr) -> Result<Self, AcpiError> {
+        let p = paging::phys_to_virt(phys) as *const u8;
+        let signature = Signature(unsafe { *(p as *const [u8; 4]) });
+        let length = u32::from_le_bytes(unsafe { *(p.add(4) as *const [u8; 4]) }) as usize;
+        if length < SDT_HEADER_SIZE {
+            return Err(AcpiError::TableTooShort(signature));
+        }
+        Ok(Self(unsafe { core::slice::from_raw_parts(p, length) }))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn signature(&self) -> Signature {
+        Signature([self.0[0], self.0[1], self.0[2], self.0[3]])
+    }
+
+    pub fn revision(&self) -> u8 {
+        self.0[8]
+    }
+
+    pub fn u8(&self, offset: usize) -> Option<u8> {
+        self.0.get(offset).copied()
+    }
+
+    pub fn u16(&self, offset: usize) -> Option<u16> {
+        let b = self.0.get(offset..offset + 2)?;
+        Some(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    pub fn u32(&self, offset: usize) -> Option<u32> {
+        let b = self.0.get(offset..offset + 4)?;
+        Some(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub fn u64(&self, offset: usize) -> Option<u64> {
+        let b = self.0.get(offset..offset + 8)?;
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(b);
+        Some(u64::from_le_bytes(bytes))
+    }
+
+    pub fn gas(&self, offset: usize) -> Option<GenericAddress> {
+        Some(GenericAddress {
+            address_space: self.u8(offset)?,
+            bit_width: self.u8(offset + 1)?,
+            bit_offset: self.u8(offset + 2)?,
+            access_size: self.u8(offset + 3)?,
+            address: self.u64(offset + 4)?,
+        })
+    }
+
+    // [offset, len)
+    pub fn tail(&self, offset: usize) -> TableBytes {
+        Self(self.0.get(offset..).unwrap_or(&[]))
+    }
+
+    fn validate(&self, expected: Signature) -> Result<(), AcpiError> {
+        let signature = self.signature();
+        if signature != expected {
+            return Err(AcpiError::InvalidSignature(signature));
+        }
+        if !checksum_ok(self.0) {
+            return Err(AcpiError::InvalidChecksum(signature));
+        }
+        Ok(())
+    }
+}
+
+fn checksum_ok(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) == 0
+}
+
+// Generic Address Structure
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GenericAddress {
+    pub address_space: u8,
+    pub bit_width: u8,
+    pub bit_offset: u8,
+    pub access_size: u8,
+    pub address: u64,
+}
+
+impl GenericAddress {
+    pub const SYSTEM_MEMORY: u8 = 0;
+    pub const SYSTEM_IO: u8 = 1;
+    pub const PCI_CONFIG: u8 = 2;
+
+    pub fn is_valid(&self) -> bool {
+        self.address != 0
+    }
+
+    pub fn io_port(&self) -> Option<u16> {
+        if self.address_space == Self::SYSTEM_IO && self.is_valid() {
+            Some(self.address as u16)
+        } else {
+            None
+        }
+    }
+}
+
+// ------------------------------------------------------
+// RSDP / XSDT
+// ------------------------------------------------------
+
+const RSDP_V1_SIZE: usize = 20;
+const RSDP_V2_SIZE: usize = 36;
+
+// returns (revision, address of the XSDT or RSDT, size of an entry)
+fn parse_rsdp(phys: PhysAddr) -> Result<(u8, PhysAddr, usize), AcpiError> {
+    let p = paging::phys_to_virt(phys) as *const u8;
+    let v1 = unsafe { core::slice::from_raw_parts(p, RSDP_V1_SIZE) };
+    if &v1[0..8] != b"RSD PTR " {
+        return Err(AcpiError::InvalidSignature(Signature([v1[0], v1[1], v1[2], v1[3]])));
+    }
+    if !checksum_ok(v1) {
+        return Err(AcpiError::InvalidChecksum(Signature::RSDP));
+    }
+    let revision = v1[15];
+    if revision < 2 {
+        let rsdt = u32::from_le_bytes([v1[16], v1[17], v1[18], v1[19]]);
+        return Ok((revision, rsdt as u64, 4));
+    }
+    let v2 = unsafe { core::slice::from_raw_parts(p, RSDP_V2_SIZE) };
+    if !checksum_ok(v2) {
+        return Err(AcpiError::InvalidChecksum(Signature::RSDP));
+    }
+    let mut xsdt = [0u8; 8];
+    xsdt.copy_from_slice(&v2[24..32]);
+    Ok((revision, u64::from_le_bytes(xsdt), 8))
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TableInfo {
+    pub signature: Signature,
+    pub address: PhysAddr,
+    pub length: usize,
+}
+
+#[derive(Debug)]
+pub struct AcpiTables {
+    pub revision: u8,
+    // every table listed in the XSDT, including the ones that are not parsed
+    pub tables: Vec<TableInfo>,
+    pub fadt: Option<Fadt>,
+    pub madt: Option<Madt>,
+    pub mcfg: Option<Mcfg>,
+    pub hpet: Option<Hpet>,
+}
+
+impl AcpiTables {
+    fn parse(rsdp: PhysAddr) -> Result<Self, AcpiError> {
+        let (revision, root, entry_size) = parse_rsdp(rsdp)?;
+        let root = unsafe { TableBytes::from_phys(root)? };
+        root.validate(if entry_size == 8 { Signature::XSDT } else { Signature::RSDT })?;
+
+        let mut acpi = Self { revision, tables: Vec::new(), fadt: None, madt: None, mcfg: None, hpet: None };
+        let entries = (root.len() - SDT_HEADER_SIZE) / entry_size;
+        for i in 0..entries {
+            let offset = SDT_HEADER_SIZE + i * entry_size;
+            let address = if entry_size == 8 {
+                root.u64(offset)
+            } else {
+                root.u32(offset).map(|a| a as u64)
+            };
+            if let Some(address) = address.filter(|&a| a != 0) {
+                acpi.add_table(address);
+            }
+        }
+        Ok(acpi)
+    }
+
+    fn add_table(&mut self, address: PhysAddr) {
+        let table = match unsafe { TableBytes::from_phys(address) } {
+            Ok(table) => table,
+            Err(e) => {
+                warn!("acpi: ignoring the table at {:#x}: {:?}", address, e);
+                return;
+            }
+        };
+        let signature = table.signature();
+        if let Err(e) = table.validate(signature) {
+            warn!("acpi: ignoring {:?} at {:#x}: {:?}", signature, address, e);
+            return;
+        }
+        debug!("acpi: {:?} at {:#x} (revision {})", signature, address, table.revision());
+        self.tables.push(TableInfo { signature, address, length: table.len() });
+        match signature {
+            Signature::FADT => self.fadt = Some(Fadt::parse(table)),
+            Signature::MADT => self.madt = Some(Madt::parse(table)),
+            Signature::MCFG => self.mcfg = Some(Mcfg::parse(table)),
+            Signature::HPET => self.hpet = Some(Hpet::parse(table)),
+            _ => {}
+        }
+    }
+
+    // for tables without a parser here
+    pub fn find_table(&self, signature: Signature) -> Option<TableBytes> {
+        self.tables.iter()
+            .find(|info| info.signature == signature)
+            .and_then(|info| unsafe { TableBytes::from_phys(info.address) }.ok())
+    }
+}
+
+static mut ACPI_TABLES: InitOnce<AcpiTables> = InitOnce::new();
+
+// ACPI テーブルを読み込む. 初期化時に一度だけ呼び出すこと
+pub fn init_acpi(boot_info: &BootInfo) -> Result<(), AcpiError> {
+    let rsdp = boot_info.rsdp().ok_or(AcpiError::NoRsdp)?;
+    let tables = AcpiTables::parse(rsdp)?;
+    unsafe { ACPI_TABLES.get_or_try_init(|| Ok::<_, AcpiError>(tables))? };
+    Ok(())
+}
+
+// None until `init_acpi` has succeeded
+pub fn tables() -> Option<&'static AcpiTables> {
+    unsafe { ACPI_TABLES.get() }
+}
+
+pub fn fadt() -> Option<&'static Fadt> {
+    tables()?.fadt.as_ref()
+}
+
+pub fn madt() -> Option<&'static Madt> {
+    tables()?.madt.as_ref()
+}
+
+pub fn mcfg() -> Option<&'static Mcfg> {
+    tables()?.mcfg.as_ref()
+}
+
+pub fn hpet() -> Option<&'static Hpet> {
+    tables()?.hpet.as_ref()
+}
@@ -0,0 +1,174 @@
+//! カーネルヒープ
+//!
+//! Small allocations are served from per-size-class free lists that are refilled one
+//! page at a time; anything larger than the biggest class gets its own frames.
+//! All memory is accessed through the direct map, so the heap is only usable after
+//! `paging::init_paging`.
+
+use crate::memory_manager::{self, FrameConstraint, FrameId, FRAME_SIZE};
+use crate::paging;
+use crate::sync::SpinMutex;
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr;
+
+const SIZE_CLASSES: [usize; 9] = [8, 16, 32, 64, 128, 256, 512, 1024, 2048];
+
+// a free block; lives in the block itself
+struct FreeBlock {
+    next: *mut FreeBlock,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct HeapStats {
+    // bytes handed out to callers (rounded up to the size class / page)
+    pub allocated_bytes: usize,
+    // frames currently owned by the heap
+    pub heap_frames: usize,
+    pub alloc_count: usize,
+    pub dealloc_count: usize,
+    pub failed_count: usize,
+}
+
+impl HeapStats {
+    const fn new() -> Self {
+        Self {
+            allocated_bytes: 0,
+            heap_frames: 0,
+            alloc_count: 0,
+            dealloc_count: 0,
+            failed_count: 0,
+        }
+    }
+}
+
+struct Heap {
+    free_lists: [*mut FreeBlock; SIZE_CLASSES.len()],
+    stats: HeapStats,
+}
+
+impl Heap {
+    const fn new() -> Self {
+        Self {
+            free_lists: [ptr::null_mut(); SIZE_CLASSES.len()],
+            stats: HeapStats::new(),
+        }
+    }
+
+    fn size_class(layout: &Layout) -> Option<usize> {
+        let size = layout.size().max(layout.align());
+        SIZE_CLASSES.iter().position(|&class| size <= class)
+    }
+
+    fn alloc(&mut self, layout: Layout) -> *mut u8 {
+        let p = match Self::size_class(&layout) {
+            Some(class) => self.alloc_block(class),
+            None => self.alloc_frames(&layout),
+        };
+        if p.is_null() {
+            self.stats.failed_count += 1;
+        } else {
+            self.stats.alloc_count += 1;
+        }
+        p
+    }
+
+    fn dealloc(&mut self, p: *mut u8, layout: Layout) {
+        match Self::size_class(&layout) {
+            Some(class) => {
+                let block = p as *mut FreeBlock;
+                unsafe { block.write(FreeBlock { next: self.free_lists[class] }) };
+                self.free_lists[class] = block;
+                self.stats.allocated_bytes -= SIZE_CLASSES[class];
+            }
+            None => {
+                let num_frames = frames_for(layout.size());
+                let frame = FrameId::containing_address(p as u64 - paging::PHYSICAL_MEMORY_OFFSET);
+                // the frames came from the memory manager, so this cannot be out of range
+                let _ = memory_manager::free_frames(frame, num_frames);
+                self.stats.allocated_bytes -= num_frames * FRAME_SIZE;
+                self.stats.heap_frames -= num_frames;
+            }
+        }
+        self.stats.dealloc_count += 1;
+    }
+
+    fn alloc_block(&mut self, class: usize) -> *mut u8 {
+        if self.free_lists[class].is_null() && !self.refill(class) {
+            return ptr::null_mut();
+        }
+        let block = self.free_lists[class];
+        self.free_lists[class] = unsafe { (*block).next };
+        self.stats.allocated_bytes += SIZE_CLASSES[class];
+        block as *mut u8
+    }
+
+    // carves a fresh page into blocks of SIZE_CLASSES[class] bytes
+    fn refill(&mut self, class: usize) -> bool {
+        let frame = match memory_manager::allocate_frame() {
+            Ok(frame) => frame,
+            Err(_) => return false,
+        };
+        self.stats.heap_frames += 1;
+        let page = paging::phys_to_virt(frame.addr()) as *mut u8;
+        let block_size = SIZE_CLASSES[class];
+        for offset in (0..FRAME_SIZE).step_by(block_size).rev() {
+            let block = unsafe { page.add(offset) } as *mut FreeBlock;
+            unsafe { block.write(FreeBlock { next: self.free_lists[class] }) };
+            self.free_lists[class] = block;
+        }
+        true
+    }
+
+    fn alloc_frames(&mut self, layout: &Layout) -> *mut u8 {
+        let num_frames = frames_for(layout.size());
+        let align = (layout.align() / FRAME_SIZE).max(1);
+        match memory_manager::allocate_frames_with(num_frames, FrameConstraint::NONE.aligned(align)) {
+            Ok(frame) => {
+                self.stats.allocated_bytes += num_frames * FRAME_SIZE;
+                self.stats.heap_frames += num_frames;
+                paging::phys_to_virt(frame.addr()) as *mut u8
+            }
+            Err(_) => ptr::null_mut(),
+        }
+    }
+}
+
+fn frames_for(size: usize) -> usize {
+    (size + FRAME_SIZE - 1) / FRAME_SIZE
+}
+
+pub struct KernelAllocator {
+    heap: SpinMutex<Heap>,
+}
+
+impl KernelAllocator {
+    const fn new() -> Self {
+        Self { heap: SpinMutex::new(Heap::new()) }
+    }
+
+    pub fn stats(&self) -> HeapStats {
+        self.heap.lock().stats
+    }
+}
+
+unsafe impl GlobalAlloc for KernelAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.heap.lock().alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, p: *mut u8, layout: Layout) {
+        self.heap.lock().dealloc(p, layout)
+    }
+}
+
+#[global_allocator]
+pub static ALLOCATOR: KernelAllocator = KernelAllocator::new();
+
+pub fn heap_stats() -> HeapStats {
+    ALLOCATOR.stats()
+}
+
+#[alloc_error_handler]
+fn alloc_error_handler(layout: Layout) -> ! {
+    panic!("allocation error: {:?}", layout)
+}
@@ -0,0 +1,132 @@
+//! Local APIC
+//!
+//! The register page is located through IA32_APIC_BASE and mapped uncached with
+//! `paging::map_mmio`. Only xAPIC (MMIO) mode is supported.
+
+use crate::asm;
+use crate::paging::{self, VirtAddr};
+use crate::utils::bit_field::BitField;
+use crate::interrupts::idt::InterruptVector;
+use crate::debug;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+const IA32_APIC_BASE: u32 = 0x1b;
+const APIC_BASE_ENABLE: usize = 11;
+
+// register offsets
+const ID: u64 = 0x20;
+const VERSION: u64 = 0x30;
+const TASK_PRIORITY: u64 = 0x80;
+const EOI: u64 = 0xb0;
+const SPURIOUS_INTERRUPT_VECTOR: u64 = 0xf0;
+const LVT_TIMER: u64 = 0x320;
+const TIMER_INITIAL_COUNT: u64 = 0x380;
+const TIMER_CURRENT_COUNT: u64 = 0x390;
+const TIMER_DIVIDE_CONFIG: u64 = 0x3e0;
+
+const SPURIOUS_VECTOR: u8 = InterruptVector::Spurious as u8;
+const LVT_MASKED: usize = 16;
+
+// 0 until `init_local_apic`
+static LOCAL_APIC_BASE: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerMode {
+    OneShot = 0b00,
+    Periodic = 0b01,
+}
+
+// value for TIMER_DIVIDE_CONFIG (bits 0, 1 and 3)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerDivide {
+    By1 = 0b1011,
+    By2 = 0b0000,
+    By4 = 0b0001,
+    By8 = 0b0010,
+    By16 = 0b0011,
+}
+
+fn base() -> VirtAddr {
+    let base = LOCAL_APIC_BASE.load(Ordering::Relaxed);
+    assert!(base != 0, "local APIC is not initialized");
+    base
+}
+
+fn read(offset: u64) -> u32 {
+    unsafe { core::ptr::read_volatile((base() + offset) as *const u32) }
+}
+
+fn write(offset: u64, value: u32) {
+    unsafe { core::ptr::write_volatile((base() + offset) as *mut u32, value) }
+}
+
+// Local APIC のレジスタを mmio 領域にマップし, 有効化する.
+// `paging::init_paging` の後に呼び出すこと
+pub fn init_local_apic() {
+    let mut apic_base = asm::rdmsr(IA32_APIC_BASE);
+    if !apic_base.get_bit(APIC_BASE_ENABLE) {
+        unsafe { asm::wrmsr(IA32_APIC_BASE, *apic_base.set_bit(APIC_BASE_ENABLE, true)) };
+    }
+    let phys = apic_base.get_bits(12..52) << 12;
+    let virt = paging::map_mmio(phys, paging::PAGE_SIZE).expect("failed to map the local APIC");
+    LOCAL_APIC_BASE.store(virt, Ordering::Relaxed);
+
+    // accept every priority, and software-enable the APIC
+    write(TASK_PRIORITY, 0);
+    let mut svr = read(SPURIOUS_INTERRUPT_VECTOR);
+    let _ = svr.set_bits(0..8, SPURIOUS_VECTOR as u32).set_bit(8, true);
+    write(SPURIOUS_INTERRUPT_VECTOR, svr);
+    mask_timer();
+    debug!("local APIC: id {}, version {:#x}, registers at {:#x}", local_apic_id(), read(VERSION) & 0xff, phys);
+}
+
+pub fn local_apic_id() -> u8 {
+    read(ID).get_bits(24..32) as u8
+}
+
+// The initial APIC ID from CPUID. Unlike `local_apic_id` it works before
+// `init_local_apic`, which is what the logger needs.
+pub fn cpu_id() -> u8 {
+    let cpuid = unsafe { core::arch::x86_64::__cpuid(1) };
+    (cpuid.ebx >> 24) as u8
+}
+
+pub fn end_of_interrupt() {
+    write(EOI, 0);
+}
+
+// ------------------------------------------------------
+// Timer
+// ------------------------------------------------------
+
+// starts counting down from `initial_count`. In periodic mode the count is reloaded on
+// every interrupt.
+pub fn start_timer(mode: TimerMode, divide: TimerDivide, vector: u8, initial_count: u32) {
+    write(TIMER_DIVIDE_CONFIG, divide as u32);
+    let mut lvt = 0u32;
+    let _ = lvt.set_bits(0..8, vector as u32).set_bits(17..19, mode as u32);
+    write(LVT_TIMER, lvt);
+    write(TIMER_INITIAL_COUNT, initial_count);
+}
+
+// starts counting down without raising an interrupt, for calibration
+pub fn start_masked_timer(divide: TimerDivide, initial_count: u32) {
+    write(TIMER_DIVIDE_CONFIG, divide as u32);
+    let mut lvt = 0u32;
+    let _ = lvt.set_bit(LVT_MASKED, true);
+    write(LVT_TIMER, lvt);
+    write(TIMER_INITIAL_COUNT, initial_count);
+}
+
+pub fn stop_timer() {
+    write(TIMER_INITIAL_COUNT, 0);
+}
+
+pub fn mask_timer() {
+    let mut lvt = read(LVT_TIMER);
+    write(LVT_TIMER, *lvt.set_bit(LVT_MASKED, true));
+}
+
+pub fn timer_current_count() -> u32 {
+    read(TIMER_CURRENT_COUNT)
+}
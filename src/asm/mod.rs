@@ -38,3 +38,133 @@ pub fn lidt(idt: &InterruptDescriptorTablePointer) {
     }
 }
 
+
+#[inline]
+pub fn read_cr0() -> u64 {
+    let value: u64;
+    unsafe {
+        asm!("mov {}, cr0", out(reg) value, options(nomem, nostack, preserves_flags));
+    }
+    value
+}
+
+#[inline]
+pub unsafe fn write_cr0(value: u64) {
+    asm!("mov cr0, {}", in(reg) value, options(nostack, preserves_flags));
+}
+
+#[inline]
+pub fn read_cr2() -> u64 {
+    let value: u64;
+    unsafe {
+        asm!("mov {}, cr2", out(reg) value, options(nomem, nostack, preserves_flags));
+    }
+    value
+}
+
+#[inline]
+pub fn read_cr3() -> u64 {
+    let value: u64;
+    unsafe {
+        asm!("mov {}, cr3", out(reg) value, options(nomem, nostack, preserves_flags));
+    }
+    value
+}
+
+#[inline]
+pub unsafe fn write_cr3(value: u64) {
+    asm!("mov cr3, {}", in(reg) value, options(nostack, preserves_flags));
+}
+
+#[inline]
+pub fn read_cr4() -> u64 {
+    let value: u64;
+    unsafe {
+        asm!("mov {}, cr4", out(reg) value, options(nomem, nostack, preserves_flags));
+    }
+    value
+}
+
+// must be inlined to see the caller's register
+#[inline(always)]
+pub fn read_rip() -> u64 {
+    let value: u64;
+    unsafe {
+        asm!("lea {}, [rip]", out(reg) value, options(nomem, nostack, preserves_flags));
+    }
+    value
+}
+
+// must be inlined to see the caller's register
+#[inline(always)]
+pub fn read_rbp() -> u64 {
+    let value: u64;
+    unsafe {
+        asm!("mov {}, rbp", out(reg) value, options(nomem, nostack, preserves_flags));
+    }
+    value
+}
+
+#[inline]
+pub fn invlpg(addr: u64) {
+    unsafe {
+        asm!("invlpg [{}]", in(reg) addr, options(nostack, preserves_flags));
+    }
+}
+
+#[inline]
+pub fn rdmsr(msr: u32) -> u64 {
+    let (high, low): (u32, u32);
+    unsafe {
+        asm!("rdmsr", in("ecx") msr, out("eax") low, out("edx") high, options(nomem, nostack, preserves_flags));
+    }
+    ((high as u64) << 32) | (low as u64)
+}
+
+#[inline]
+pub unsafe fn wrmsr(msr: u32, value: u64) {
+    let low = value as u32;
+    let high = (value >> 32) as u32;
+    asm!("wrmsr", in("ecx") msr, in("eax") low, in("edx") high, options(nostack, preserves_flags));
+}
+
+#[inline]
+pub fn lgdt(gdt: &crate::gdt::GlobalDescriptorTablePointer) {
+    unsafe {
+        asm!("lgdt [{}]", in(reg) gdt, options(readonly, nostack, preserves_flags));
+    }
+}
+
+#[inline]
+pub unsafe fn ltr(selector: u16) {
+    asm!("ltr {:x}", in(reg) selector, options(nostack, preserves_flags));
+}
+
+// CS can only be changed by a far jump/return
+#[inline]
+pub unsafe fn set_cs(selector: u16) {
+    asm!(
+        "push {sel}",
+        "lea {tmp}, [rip + 2f]",
+        "push {tmp}",
+        "retfq",
+        "2:",
+        sel = in(reg) selector as u64,
+        tmp = lateout(reg) _,
+        options(preserves_flags),
+    );
+}
+
+#[inline]
+pub unsafe fn set_data_segments(ss: u16, others: u16) {
+    asm!(
+        "mov ss, {ss:x}",
+        "mov ds, {others:x}",
+        "mov es, {others:x}",
+        "mov fs, {others:x}",
+        "mov gs, {others:x}",
+        ss = in(reg) ss,
+        others = in(reg) others,
+        options(nostack, preserves_flags),
+    );
+}
@@ -0,0 +1,102 @@
+//! バックトレース
+//!
+//! The kernel is built with frame pointers (`frame-pointer` in kernel_target.json), so
+//! every frame starts with the caller's RBP followed by the return address. The walk stops
+//! at the first frame pointer that is null, misaligned, unmapped or does not move up the
+//! stack, so it is safe to run on a corrupted stack.
+
+use crate::asm;
+use crate::ksyms;
+use crate::paging;
+use core::fmt;
+
+pub const MAX_FRAMES: usize = 32;
+
+#[derive(Clone, Copy)]
+pub struct Backtrace {
+    frames: [u64; MAX_FRAMES],
+    len: usize,
+}
+
+fn is_canonical(addr: u64) -> bool {
+    let upper = addr >> 47;
+    upper == 0 || upper == 0x1ffff
+}
+
+// whether `[addr, addr + 16)` (saved RBP and return address) can be read
+fn is_readable_frame(addr: u64) -> bool {
+    addr != 0
+        && addr % 8 == 0
+        && is_canonical(addr)
+        && is_canonical(addr + 15)
+        && paging::try_virt_to_phys(addr).is_some()
+        && paging::try_virt_to_phys(addr + 15).is_some()
+}
+
+impl Backtrace {
+    // `rip` is the first entry, then the return addresses of the frames from `rbp` upwards
+    pub fn from_frame(rip: u64, rbp: u64) -> Self {
+        let mut backtrace = Self { frames: [0; MAX_FRAMES], len: 0 };
+        backtrace.push(rip);
+        let mut rbp = rbp;
+        while backtrace.len < MAX_FRAMES && is_readable_frame(rbp) {
+            let (next, ret) = unsafe { (*(rbp as *const u64), *((rbp + 8) as *const u64)) };
+            if ret == 0 {
+                break;
+            }
+            backtrace.push(ret);
+            // callers live at higher addresses; anything else is a loop or garbage
+            if next <= rbp {
+                break;
+            }
+            rbp = next;
+        }
+        backtrace
+    }
+
+    // starts in the function that calls `capture`
+    #[inline(always)]
+    pub fn capture() -> Self {
+        Self::from_frame(asm::read_rip(), asm::read_rbp())
+    }
+
+    fn push(&mut self, addr: u64) {
+        self.frames[self.len] = addr;
+        self.len += 1;
+    }
+
+    pub fn frames(&self) -> &[u64] {
+        &self.frames[..self.len]
+    }
+}
+
+// `addr` with the symbol it belongs to, e.g. `0x10a2f3 potatOS::pci::scan_bus+0x43`
+pub struct Symbolized(pub u64);
+
+impl fmt::Display for Symbolized {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#018x}", self.0)?;
+        match ksyms::lookup(self.0) {
+            Some(symbol) => write!(f, " {}+{:#x}", symbol.name, self.0 - symbol.addr),
+            None => write!(f, " ?"),
+        }
+    }
+}
+
+impl fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, &addr) in self.frames().iter().enumerate() {
+            // return addresses point after the call, which may already be the next function
+            let lookup_addr = if i == 0 { addr } else { addr - 1 };
+            write!(f, "  #{:<2} {:#018x}", i, addr)?;
+            match ksyms::lookup(lookup_addr) {
+                Some(symbol) => writeln!(f, " {}+{:#x}", symbol.name, addr - symbol.addr)?,
+                None => writeln!(f, " ?")?,
+            }
+        }
+        if !ksyms::is_available() {
+            writeln!(f, "  (no symbols: run tools/embed-symbols.sh on the kernel image)")?;
+        }
+        Ok(())
+    }
+}
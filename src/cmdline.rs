@@ -0,0 +1,39 @@
+//! カーネルコマンドライン
+//!
+//! potato_loader hands over the contents of `cmdline.txt` on the ESP, e.g.
+//! `loglevel=info log.pci=trace console=serial`. It is copied into the kernel at the very
+//! start of `init`, before anything that could be configured by it, and every subsystem
+//! reads its own options with `get`/`has` or goes through `cmdline()` itself.
+//!
+//! Options handled so far:
+//! - `loglevel=<level>`, `log.<module>=<level>`: `logger::apply_cmdline`
+//! - `console=serial|fb|both`: `output::apply_cmdline`
+//! - `resolution=<w>x<h>`: the loader, when it picks the GOP mode
+//! - `init=<program>`: reserved for the first user program
+
+use crate::sync::Once;
+use boot_info::{BootInfo, MAX_CMDLINE_LEN};
+use potato_utils::cmdline::CommandLine;
+use potato_utils::fixed_string::FixedString;
+
+pub use potato_utils::cmdline::{parse_resolution, CmdlineOption};
+
+static CMDLINE: Once<FixedString<MAX_CMDLINE_LEN>> = Once::new();
+
+// later calls keep the first command line
+pub fn init_cmdline(boot_info: &BootInfo) {
+    let _ = CMDLINE.set(FixedString::from(boot_info.cmdline()));
+}
+
+// empty until `init_cmdline`
+pub fn cmdline() -> CommandLine<'static> {
+    CommandLine::new(CMDLINE.get().map_or("", |cmdline| cmdline.as_str()))
+}
+
+pub fn get(key: &str) -> Option<&'static str> {
+    cmdline().get(key)
+}
+
+pub fn has(key: &str) -> bool {
+    cmdline().has(key)
+}
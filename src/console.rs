@@ -2,6 +2,7 @@
 use crate::graphics::{
     PixelColor, FrameBuffer, Font, ShinonomeFont
 };
+use potato_utils::text_buffer::TextBuffer;
 
 #[derive(Clone, Copy)]
 struct Color {
@@ -29,19 +30,13 @@ impl ConsoleChar {
     };
 }
 
-#[derive(Clone, Copy)]
-struct Cursor {
-    x: usize,
-    y: usize,
-}
-
 pub struct Console {
-    rows: usize, // <= 600/16 (== QEMU window size / hankaku font vertical length) < 40
-    columns: usize, // <= 800/8 = 100
-    buffer: [char; 10000],
+    // rows <= 600/16 (== QEMU window size / hankaku font vertical length) < 40
+    // columns <= 800/8 = 100
+    text: TextBuffer,
     color: Color,
-    cursor: Cursor,
-    scroll_flag: bool,
+    // where `render`ed text goes, once windows exist
+    window: Option<WindowId>,
 }
 
 const ROWS: usize = 10;
@@ -51,20 +46,25 @@ use crate::graphics::PixelWriter;
 impl Console {
     pub const fn new() -> Self {
         Self {
-            rows: ROWS,
-            columns: COLUMNS,
-            buffer: [' '; 10000],
+            text: TextBuffer::new(ROWS, COLUMNS),
             color: Color::DEFAULT,
-            cursor: Cursor {x: 0, y: 0},
-            scroll_flag: false,
+            window: None,
         }
     }
 
+    pub fn init(&mut self) {
+        self.text.init();
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        self.text.is_initialized()
+    }
+
     pub fn rows(&self) -> usize {
-        self.rows
+        self.text.rows()
     }
     pub fn columns(&self) -> usize {
-        self.columns
+        self.text.columns()
     }
     pub fn fg(&self) -> PixelColor {
         self.color.fg
@@ -72,16 +72,22 @@ impl Console {
     pub fn bg(&self) -> PixelColor {
         self.color.bg
     }
+    pub fn window(&self) -> Option<WindowId> {
+        self.window
+    }
 
 
     pub fn render(&mut self, writer: &dyn PixelWriter, font: &dyn Font) {
+        if !self.is_initialized() {
+            return;
+        }
         let (font_x, font_y) = font.char_size();
-        for y in 0..self.rows {
-            for x in 0..self.columns {
-                let index = y*self.columns + x;
-                let ch = self.buffer[index];
+        let scrolled = self.text.take_scrolled();
+        for y in 0..self.rows() {
+            for x in 0..self.columns() {
+                let ch = self.text.get(x, y);
                 use crate::graphics::Vector2D;
-                if self.scroll_flag {
+                if scrolled {
                     writer.fill_rect(
                         Vector2D::new(x*font_x, y*font_y),
                         Vector2D::new(font_x, font_y),
@@ -91,57 +97,10 @@ impl Console {
                 font.write_ascii(writer, font_x*x, font_y*y, ch, &self.color.fg, &self.color.bg);
             }
         }
-        self.scroll_flag = false;
     }
 
     pub fn put_string(&mut self, s: &str) {
-        s.chars().for_each(|c| {
-            if c == '\n' { self.new_line() }
-            else if self.cursor.x < self.columns - 1 {
-                let index = self.cursor.y * self.columns + self.cursor.x;
-                self.buffer[index] = c;
-                self.cursor.x += 1;
-            }
-            // TODO: else  { todo!() }
-            // (currently it stops storing s to self.buffer when self.cursor.x > self.columns)
-        });
-    }
-
-    fn move_cursor_forward(&mut self) {
-        self.cursor.x += 1;
-        if self.cursor.x == self.columns {
-            self.cursor.x = 0;
-            if self.cursor.y + 1 == self.rows {
-                self.scroll_up();
-            } else {
-                self.cursor.y += 1;
-            }
-        }
-    }
-
-    fn move_cursor_backward(&mut self) {
-        if self.cursor.x > 0 {
-            self.cursor.x -= 1;
-        }
-    }
-
-    fn new_line(&mut self) {
-        self.cursor.x = 0;
-        if self.cursor.y == self.rows - 1 {
-            // スクロールの必要あり
-            self.scroll_up();
-        } else {
-            // スクロールの必要なし
-            self.cursor.y += 1;
-        }
-    }
-
-    fn scroll_up(&mut self) {
-        self.scroll_flag = true;
-        let end = self.rows * self.columns;
-        let src = self.columns..end;
-        self.buffer.copy_within(src, 0);
-        self.buffer[(end-self.columns)..end].fill(' ');
+        self.text.put_string(s);
     }
 
 }
@@ -156,7 +115,7 @@ impl fmt::Write for Console {
 
 #[macro_export]
 macro_rules! kprint {
-    ($($arg:tt)*) => ($crate::console::_kprint(format_args!($($arg)*)));
+    ($($arg:tt)*) => ($crate::output::_print(format_args!($($arg)*)));
 }
 
 #[macro_export]
@@ -171,106 +130,67 @@ macro_rules! kprintln {
 // TODO: 2. initialize WRITER (frame buffer) in kernel_main -> FINISHED
 // TODO: 3. really need spin mutex?
 // TODO: 4. is the implementation correct?
-pub static CONSOLE: SpinMutex<Console> = SpinMutex::new(
+pub static CONSOLE: IrqSpinMutex<Console> = IrqSpinMutex::new(
+    "CONSOLE",
     Console::new()
 );
 
 pub static CONSOLE_FONT: ShinonomeFont = ShinonomeFont::new();
 
-
-use crate::graphics::WRITER;
-pub fn _kprint(args: fmt::Arguments) {
-    use core::fmt::Write;
-    let mut console = CONSOLE.lock();
-    let writer = WRITER.lock();
-    let writer = unsafe { writer.assume_init() };
-    console.write_fmt(args).unwrap();
-    console.render(writer, &CONSOLE_FONT);
-}
-
-#[no_mangle]
-pub extern "C" fn usb_log(_level: i32, msg: *const u8, msg_len: i32) {
-    let s = unsafe { core::slice::from_raw_parts(msg, msg_len as usize) };
-    let s = unsafe { core::str::from_utf8_unchecked(s) };
-    kprint!("{}", s);
-}
-
-// ------------------------------------------------------
-// SpinMutex
-// ------------------------------------------------------
-// TODO: move spinmutex to spinmutex_like.rs
-use core::sync::atomic::{AtomicBool, Ordering};
-use core::cell::UnsafeCell;
-pub struct SpinMutex<T> {
-    lock: AtomicBool,
-    data: UnsafeCell<T>
-}
-
-impl<T> SpinMutex<T> {
-    pub const fn new(data: T) -> Self {
-        Self {
-            lock: AtomicBool::new(false),
-            data: UnsafeCell::new(data),
-        }
+// the framebuffer sink of `kprint!`, in a window of its own; output is dropped until this
+// is called (needs the heap and the layers)
+pub fn init_console() {
+    {
+        let mut console = CONSOLE.lock();
+        console.init();
+        let (font_x, font_y) = CONSOLE_FONT.char_size();
+        let (width, height) = (console.columns() * font_x, console.rows() * font_y);
+        console.window = window::create_window("Console", width, height, &console.bg(), Some(on_key));
     }
+    register_sink(&CONSOLE_SINK).expect("failed to register the console");
+}
 
-    pub fn try_lock(&self) -> Result<SpinMutexGuard<T>, SpinMutexErr> {
-        if !self.lock.swap(true, Ordering::Acquire) {
-            Ok(SpinMutexGuard { mutex: self })
-        } else {
-            Err(SpinMutexErr("lock error"))
-        }
-
+// keys typed while the console is focused are echoed
+fn on_key(_window: WindowId, modifier: u8, keycode: u8) {
+    let ascii = keycode_to_ascii(modifier, keycode);
+    if ascii != 0 {
+        kprint!("{}", ascii as char);
     }
-
-    pub fn lock(&self) -> SpinMutexGuard<T> {
-        loop {
-            if let Ok(guard) = self.try_lock() {
-                return guard;
-            }
-        }
-    }
-
 }
 
-// Send + Sync are required for static 
-unsafe impl<T> Send for SpinMutex<T> {}
-unsafe impl<T> Sync for SpinMutex<T> {}
 
-pub struct SpinMutexGuard<'a, T> {
-    mutex: &'a SpinMutex<T>,
-}
+use crate::keyboard::keycode_to_ascii;
+use crate::window::{self, WindowId};
+use crate::output::{register_sink, OutputSink};
+use crate::sync::IrqSpinMutex;
 
-impl<T> SpinMutexGuard<'_, T> {
-    fn unlock(&self) {
-        self.mutex.lock.swap(false, Ordering::Release);
-    }
-}
+pub struct ConsoleSink;
 
-// when drop, unlock
-use core::ops::Drop;
-impl<T> Drop for SpinMutexGuard<'_, T> {
-    fn drop(&mut self) {
-        self.unlock();
-    }
-}
+pub static CONSOLE_SINK: ConsoleSink = ConsoleSink;
 
-use core::ops::Deref;
-impl<T> Deref for SpinMutexGuard<'_, T> {
-    type Target = T;
-    fn deref(&self) -> &Self::Target {
-        unsafe { &*self.mutex.data.get() }
+impl OutputSink for ConsoleSink {
+    fn name(&self) -> &'static str {
+        "console"
     }
-}
 
-use core::ops::DerefMut;
-impl<T> DerefMut for SpinMutexGuard<'_, T> {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        unsafe { &mut *self.mutex.data.get() }
+    fn write_fmt(&self, args: fmt::Arguments) {
+        use core::fmt::Write;
+        let mut console = CONSOLE.lock();
+        if !console.is_initialized() {
+            return;
+        }
+        console.write_fmt(args).unwrap();
+        // closing the window only stops the output on the screen
+        if let Some(id) = console.window {
+            window::draw(id, |writer| console.render(writer, &CONSOLE_FONT));
+        }
     }
 }
 
-pub struct SpinMutexErr<'a>(&'a str);
-
-
+#[no_mangle]
+pub extern "C" fn usb_log(_level: i32, msg: *const u8, msg_len: i32) {
+    let s = unsafe { core::slice::from_raw_parts(msg, msg_len as usize) };
+    let s = unsafe { core::str::from_utf8_unchecked(s) };
+    kprint!("{}", s);
+}
 
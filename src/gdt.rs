@@ -0,0 +1,183 @@
+//! GDT と TSS
+//!
+//! Replaces the firmware's GDT with our own: kernel/user code and data segments and a
+//! TSS whose IST stacks are used for exceptions that must not run on a possibly broken
+//! stack (double fault, NMI, machine check).
+
+use crate::asm;
+use crate::utils::bit_field::BitField;
+use core::mem::size_of;
+
+pub const KERNEL_CODE_SELECTOR: u16 = 1 << 3;
+pub const KERNEL_DATA_SELECTOR: u16 = 2 << 3;
+// user data comes before user code so that `sysret` can find both from one base
+pub const USER_DATA_SELECTOR: u16 = (3 << 3) | 3;
+pub const USER_CODE_SELECTOR: u16 = (4 << 3) | 3;
+pub const TSS_SELECTOR: u16 = 5 << 3;
+
+// IST の番号 (1..=7). 0 は「IST を使わない」を意味する
+pub const DOUBLE_FAULT_IST_INDEX: u8 = 1;
+pub const NMI_IST_INDEX: u8 = 2;
+pub const MACHINE_CHECK_IST_INDEX: u8 = 3;
+
+const IST_STACK_SIZE: usize = 4096 * 4;
+
+#[derive(Debug, Clone, Copy)]
+#[repr(transparent)]
+pub struct SegmentDescriptor {
+    data: u64,
+}
+
+impl SegmentDescriptor {
+    pub const fn null() -> Self {
+        Self { data: 0 }
+    }
+
+    // 64-bit code segment; base and limit are ignored in long mode
+    pub fn code_segment(dpl: u8) -> Self {
+        let mut data = 0u64;
+        let _ = data
+            .set_bits(0..16, 0xffff) // limit (low)
+            .set_bits(40..44, 0b1010) // type: execute/read
+            .set_bit(44, true) // code or data segment
+            .set_bits(45..47, dpl as u64)
+            .set_bit(47, true) // present
+            .set_bits(48..52, 0xf) // limit (high)
+            .set_bit(53, true) // long mode
+            .set_bit(55, true); // granularity: 4 KiB
+        Self { data }
+    }
+
+    pub fn data_segment(dpl: u8) -> Self {
+        let mut data = 0u64;
+        let _ = data
+            .set_bits(0..16, 0xffff)
+            .set_bits(40..44, 0b0010) // type: read/write
+            .set_bit(44, true)
+            .set_bits(45..47, dpl as u64)
+            .set_bit(47, true)
+            .set_bits(48..52, 0xf)
+            .set_bit(54, true) // 32-bit
+            .set_bit(55, true);
+        Self { data }
+    }
+
+    // a TSS descriptor takes two GDT slots
+    pub fn tss_segment(tss: &'static TaskStateSegment) -> (Self, Self) {
+        let base = tss as *const TaskStateSegment as u64;
+        let limit = (size_of::<TaskStateSegment>() - 1) as u64;
+        let mut low = 0u64;
+        let _ = low
+            .set_bits(0..16, limit.get_bits(0..16))
+            .set_bits(16..40, base.get_bits(0..24))
+            .set_bits(40..44, 0b1001) // type: available 64-bit TSS
+            .set_bit(47, true)
+            .set_bits(48..52, limit.get_bits(16..20))
+            .set_bits(56..64, base.get_bits(24..32));
+        let high = base.get_bits(32..64);
+        (Self { data: low }, Self { data: high })
+    }
+}
+
+#[derive(Debug)]
+#[repr(C, packed(4))]
+pub struct TaskStateSegment {
+    _reserved0: u32,
+    pub privilege_stack_table: [u64; 3],
+    _reserved1: u64,
+    pub interrupt_stack_table: [u64; 7],
+    _reserved2: u64,
+    _reserved3: u16,
+    pub iomap_base: u16,
+}
+
+impl TaskStateSegment {
+    pub const fn new() -> Self {
+        Self {
+            _reserved0: 0,
+            privilege_stack_table: [0; 3],
+            _reserved1: 0,
+            interrupt_stack_table: [0; 7],
+            _reserved2: 0,
+            _reserved3: 0,
+            // no I/O permission bitmap
+            iomap_base: size_of::<TaskStateSegment>() as u16,
+        }
+    }
+
+    pub fn set_ist(&mut self, index: u8, stack_top: u64) {
+        assert!((1..=7).contains(&index));
+        let mut table = self.interrupt_stack_table;
+        table[index as usize - 1] = stack_top;
+        self.interrupt_stack_table = table;
+    }
+}
+
+const GDT_ENTRIES: usize = 7;
+
+#[repr(C, align(16))]
+pub struct GlobalDescriptorTable {
+    data: [SegmentDescriptor; GDT_ENTRIES],
+}
+
+impl GlobalDescriptorTable {
+    pub const fn new() -> Self {
+        Self { data: [SegmentDescriptor::null(); GDT_ENTRIES] }
+    }
+
+    pub fn set(&mut self, selector: u16, desc: SegmentDescriptor) {
+        self.data[(selector >> 3) as usize] = desc;
+    }
+
+    pub fn as_ptr(&self) -> GlobalDescriptorTablePointer {
+        GlobalDescriptorTablePointer {
+            limit: (size_of::<Self>() - 1) as u16,
+            base: self as *const Self as u64,
+        }
+    }
+}
+
+#[repr(C, packed)]
+pub struct GlobalDescriptorTablePointer {
+    limit: u16,
+    base: u64,
+}
+
+#[repr(C, align(16))]
+struct Stack([u8; IST_STACK_SIZE]);
+
+impl Stack {
+    fn top(&'static self) -> u64 {
+        self as *const Self as u64 + IST_STACK_SIZE as u64
+    }
+}
+
+static mut DOUBLE_FAULT_STACK: Stack = Stack([0; IST_STACK_SIZE]);
+static mut NMI_STACK: Stack = Stack([0; IST_STACK_SIZE]);
+static mut MACHINE_CHECK_STACK: Stack = Stack([0; IST_STACK_SIZE]);
+
+static mut TSS: TaskStateSegment = TaskStateSegment::new();
+static mut GDT: GlobalDescriptorTable = GlobalDescriptorTable::new();
+
+// GDT と TSS を設定し, セグメントレジスタを再設定する.
+// これは IDT より先に, 初期化時に一度だけ呼び出すこと (IDT は現在の CS を記録する)
+pub fn init_gdt() {
+    unsafe {
+        TSS.set_ist(DOUBLE_FAULT_IST_INDEX, DOUBLE_FAULT_STACK.top());
+        TSS.set_ist(NMI_IST_INDEX, NMI_STACK.top());
+        TSS.set_ist(MACHINE_CHECK_IST_INDEX, MACHINE_CHECK_STACK.top());
+
+        GDT.set(KERNEL_CODE_SELECTOR, SegmentDescriptor::code_segment(0));
+        GDT.set(KERNEL_DATA_SELECTOR, SegmentDescriptor::data_segment(0));
+        GDT.set(USER_DATA_SELECTOR, SegmentDescriptor::data_segment(3));
+        GDT.set(USER_CODE_SELECTOR, SegmentDescriptor::code_segment(3));
+        let (tss_low, tss_high) = SegmentDescriptor::tss_segment(&TSS);
+        GDT.set(TSS_SELECTOR, tss_low);
+        GDT.set(TSS_SELECTOR + 8, tss_high);
+
+        asm::lgdt(&GDT.as_ptr());
+        asm::set_data_segments(KERNEL_DATA_SELECTOR, 0);
+        asm::set_cs(KERNEL_CODE_SELECTOR);
+        asm::ltr(TSS_SELECTOR);
+    }
+}
@@ -22,7 +22,7 @@ impl PixelColor {
     pub const BLUE: Self = Self {
         red: 0, green: 0, blue: 255,
     };
-    pub fn new(r: u8, g: u8, b: u8) -> Self {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
         Self {
             red: r,
             green: g,
@@ -31,44 +31,74 @@ impl PixelColor {
     }
 }
 
-#[derive(Debug)]
-#[repr(u8)]
-pub enum PixelFormat {
-    PixelRGBResv8BitPerColor,
-    PixelBGRResv8BitPerColor,
-}
+pub use boot_info::PixelFormat;
+use boot_info::FrameBufferInfo;
+use potato_utils::pixel_bitmask::ChannelMasks;
+use potato_utils::raster;
+use potato_utils::rect::{clip_copy, DirtyRegion, Rect};
 
 
-// need init CONSOLE_WRITER in kernel_main
-use crate::console::SpinMutex;
+// need init CONSOLE_WRITER in kernel_main (after the heap is ready)
+use crate::paging::{self, MapError};
+use crate::sync::IrqSpinMutex;
 use core::mem::MaybeUninit;
-pub static WRITER: SpinMutex<MaybeUninit<&dyn PixelWriter>> = SpinMutex::new(
+use alloc::boxed::Box;
+use alloc::vec;
+use core::cell::Cell;
+use core::marker::PhantomData;
+use core::{ptr, slice};
+use core::sync::atomic::{self, AtomicBool, AtomicPtr, Ordering};
+pub static WRITER: IrqSpinMutex<MaybeUninit<&dyn PixelWriter>> = IrqSpinMutex::new(
+    "WRITER",
     MaybeUninit::<&dyn PixelWriter>::uninit()
 );
-pub fn init_global_writer(frame_buffer: FrameBuffer) {
-    WRITER.lock().write(match frame_buffer.pixel_format() {
-        PixelFormat::PixelRGBResv8BitPerColor => {
-            // placement new
-            static mut RGB_WRITER: MaybeUninit<RGBResv8BitPerColorPixelWriter> = MaybeUninit::uninit();
-            unsafe { RGB_WRITER.write(RGBResv8BitPerColorPixelWriter::new(frame_buffer)); }
-            unsafe { RGB_WRITER.assume_init_ref() }
-        },
-        PixelFormat::PixelBGRResv8BitPerColor => {
-            static mut BGR_WRITER: MaybeUninit<BGRResv8BitPerColorPixelWriter> = MaybeUninit::uninit();
-            unsafe { BGR_WRITER.write(BGRResv8BitPerColorPixelWriter::new(frame_buffer)); }
-            unsafe { BGR_WRITER.assume_init_ref() }
-        },
-    });
-}
-
-#[derive(Debug)]
-#[repr(C)]
+// set once `WRITER` holds a writer
+static WRITER_INITIALIZED: AtomicBool = AtomicBool::new(false);
+// the back buffer behind `WRITER`, for `with_direct_writer`, which must not take its lock
+static SCREEN: AtomicPtr<BackBuffer> = AtomicPtr::new(ptr::null_mut());
+
+pub fn is_global_writer_initialized() -> bool {
+    WRITER_INITIALIZED.load(Ordering::Acquire)
+}
+
+// draws into a back buffer (on the heap) that starts out filled with `background`;
+// users of `WRITER` call `flush` when they are done drawing. Returns the back buffer for
+// the layer manager, which composes into it.
+pub fn init_global_writer(frame_buffer: FrameBuffer, background: &PixelColor) -> &'static BackBuffer {
+    // the global writer lives until shutdown
+    let back_buffer: &'static BackBuffer = Box::leak(Box::new(BackBuffer::new(frame_buffer, background)));
+    back_buffer.flush();
+    WRITER.lock().write(back_buffer);
+    SCREEN.store(back_buffer as *const BackBuffer as *mut BackBuffer, Ordering::Release);
+    WRITER_INITIALIZED.store(true, Ordering::Release);
+    back_buffer
+}
+
+// For the crash screen: draws straight into video memory with the writer for the frame
+// buffer's format, so neither `WRITER` nor the back buffer's locks are taken. None before
+// `init_global_writer`.
+pub fn with_direct_writer<R>(f: impl FnOnce(&dyn PixelWriter) -> R) -> Option<R> {
+    let screen = unsafe { SCREEN.load(Ordering::Acquire).as_ref()? };
+    let frame_buffer = screen.frame_buffer.clone();
+    let result = match frame_buffer.pixel_format {
+        PixelFormat::PixelRGBResv8BitPerColor => f(&RGBResv8BitPerColorPixelWriter::new(frame_buffer)),
+        PixelFormat::PixelBGRResv8BitPerColor => f(&BGRResv8BitPerColorPixelWriter::new(frame_buffer)),
+        PixelFormat::PixelBitMask => f(&frame_buffer),
+    };
+    // the frame buffer is write-combining; this drains the CPU's write buffers
+    atomic::fence(Ordering::SeqCst);
+    Some(result)
+}
+
+#[derive(Debug, Clone)]
 pub struct FrameBuffer {
     frame_buffer: *mut u8,
     pixel_per_scan_line: usize,
     horizontal_resolution: usize,
     vertical_resolution: usize,
     pixel_format: PixelFormat,
+    // only for PixelBitMask
+    pixel_bitmask: ChannelMasks,
 }
 
 impl FrameBuffer {
@@ -79,9 +109,34 @@ impl FrameBuffer {
             horizontal_resolution: 0,
             vertical_resolution: 0,
             pixel_format: PixelFormat::PixelRGBResv8BitPerColor,
+            pixel_bitmask: ChannelMasks::EMPTY,
+        }
+    }
+
+    pub fn from_boot_info(info: &FrameBufferInfo) -> Self {
+        Self {
+            frame_buffer: info.frame_buffer,
+            pixel_per_scan_line: info.pixel_per_scan_line,
+            horizontal_resolution: info.horizontal_resolution,
+            vertical_resolution: info.vertical_resolution,
+            pixel_format: info.pixel_format,
+            pixel_bitmask: ChannelMasks {
+                red: info.pixel_bitmask.red,
+                green: info.pixel_bitmask.green,
+                blue: info.pixel_bitmask.blue,
+                reserved: info.pixel_bitmask.reserved,
+            },
         }
     }
 
+    // Moves the frame buffer to a write-combining mapping of its own: after
+    // `paging::init_paging` the identity map no longer reaches video memory.
+    pub fn map_write_combining(self) -> Result<Self, MapError> {
+        let len = self.pixel_per_scan_line * self.vertical_resolution * 4;
+        let virt = paging::map_frame_buffer(self.frame_buffer as u64, len as u64)?;
+        Ok(Self { frame_buffer: virt as *mut u8, ..self })
+    }
+
     pub fn h(&self) -> usize {
         self.horizontal_resolution
     }
@@ -94,6 +149,51 @@ impl FrameBuffer {
         &self.pixel_format
     }
 
+    pub fn encoder(&self) -> PixelEncoder {
+        PixelEncoder { format: self.pixel_format, masks: self.pixel_bitmask }
+    }
+
+    // the pixel as it is stored in video memory
+    fn encode(&self, color: &PixelColor) -> u32 {
+        self.encoder().encode(color)
+    }
+
+    fn pixels(&self) -> Pixels {
+        Pixels {
+            base: self.frame_buffer as *mut u32,
+            stride: self.pixel_per_scan_line,
+            width: self.h(),
+            height: self.v(),
+        }
+    }
+}
+
+// Turns colors into pixels of one frame buffer's format, for buffers that are copied to
+// it as they are, like the shadow buffers of layers.
+#[derive(Clone, Copy)]
+pub struct PixelEncoder {
+    format: PixelFormat,
+    // only for PixelBitMask
+    masks: ChannelMasks,
+}
+
+impl PixelEncoder {
+    pub fn encode(&self, color: &PixelColor) -> u32 {
+        match self.format {
+            PixelFormat::PixelRGBResv8BitPerColor => rgb_pixel(color),
+            PixelFormat::PixelBGRResv8BitPerColor => bgr_pixel(color),
+            PixelFormat::PixelBitMask => self.masks.encode(color.red, color.green, color.blue),
+        }
+    }
+}
+
+// little endian, the reserved byte 0
+const fn rgb_pixel(color: &PixelColor) -> u32 {
+    u32::from_le_bytes([color.red, color.green, color.blue, 0])
+}
+
+const fn bgr_pixel(color: &PixelColor) -> u32 {
+    u32::from_le_bytes([color.blue, color.green, color.red, 0])
 }
 
 impl PixelWriter for FrameBuffer {
@@ -104,15 +204,22 @@ impl PixelWriter for FrameBuffer {
         self.v()
     }
     fn draw_pixel(&self, x: usize, y: usize, color: &PixelColor) {
-        let pixel_position = self.pixel_per_scan_line * y + x;
-        let color_data = match self.pixel_format {
-            PixelFormat::PixelRGBResv8BitPerColor => [color.red, color.green, color.blue],
-            PixelFormat::PixelBGRResv8BitPerColor => [color.blue, color.green, color.red],
-        };
-        let pixel = unsafe { self.frame_buffer.add(4*pixel_position) };
-        for (i, &item) in color_data.iter().enumerate() {
-            unsafe { pixel.add(i).write_volatile(item) };
-        }
+        self.pixels().put(x, y, self.encode(color));
+    }
+    fn fill_rect(&self, pos: Vector2D<usize>, size: Vector2D<usize>, color: &PixelColor) {
+        self.pixels().fill(to_rect(pos, size), self.encode(color));
+    }
+    fn copy_rect(&self, src: Vector2D<usize>, size: Vector2D<usize>, dst: Vector2D<usize>) {
+        self.pixels().copy(to_rect(src, size), dst);
+    }
+    fn draw_line(&self, from: Vector2D<usize>, to: Vector2D<usize>, color: &PixelColor) {
+        self.pixels().line(from, to, self.encode(color));
+    }
+    fn draw_circle(&self, center: Vector2D<usize>, radius: usize, color: &PixelColor) {
+        self.pixels().circle(center, radius, self.encode(color));
+    }
+    fn draw_bitmap(&self, pos: Vector2D<usize>, rows: &[u8], color: &PixelColor) {
+        self.pixels().bitmap(pos, rows, self.encode(color));
     }
 }
 
@@ -125,26 +232,170 @@ pub trait PixelWriter {
         (self.horizontal_resolution(), self.vertical_resolution())
     }
 
+    // makes what was drawn visible; a no-op for writers that draw to the screen directly
+    fn flush(&self) {}
+
+    // Everything below is clipped against the screen. The defaults go pixel by pixel;
+    // the writers in this file work on whole rows instead.
+
     fn fill_rect(&self, pos: Vector2D<usize>, size: Vector2D<usize>, color: &PixelColor) {
-        for dy in 0..size.y() {
-            for dx in 0..size.x() {
-                self.draw_pixel(pos.x() + dx, pos.y() + dy, color)
+        let (width, height) = self.resolution();
+        let Some(rect) = to_rect(pos, size).clip(width, height) else {
+            return;
+        };
+        for y in rect.y..rect.bottom() {
+            for x in rect.x..rect.right() {
+                self.draw_pixel(x, y, color)
             }
         }
     }
 
+    // a one pixel wide outline of the `size` pixels at `pos`
     fn draw_rect(&self, pos: Vector2D<usize>, size: Vector2D<usize>, color: &PixelColor) {
-        for dx in 0..size.x() {
-            self.draw_pixel(pos.x()+dx, pos.y(), color);
-            self.draw_pixel(pos.x()+dx, pos.y()+size.y(), color);
+        let (x, y, width, height) = (pos.x(), pos.y(), size.x(), size.y());
+        if width == 0 || height == 0 {
+            return;
         }
-        for dy in 0..size.y() {
-            self.draw_pixel(pos.x(), pos.y()+dy, color);
-            self.draw_pixel(pos.x()+size.x(), pos.y()+dy, color);
+        let sides = height.saturating_sub(2);
+        self.fill_rect(pos, Vector2D::new(width, 1), color);
+        self.fill_rect(Vector2D::new(x, y + height - 1), Vector2D::new(width, 1), color);
+        self.fill_rect(Vector2D::new(x, y + 1), Vector2D::new(1, sides), color);
+        self.fill_rect(Vector2D::new(x + width - 1, y + 1), Vector2D::new(1, sides), color);
+    }
+
+    // moves the `size` pixels at `src` to `dst`; the two may overlap, as when scrolling.
+    // Only what is on the screen at both ends is copied.
+    fn copy_rect(&self, src: Vector2D<usize>, size: Vector2D<usize>, dst: Vector2D<usize>);
+
+    // both ends included
+    fn draw_line(&self, from: Vector2D<usize>, to: Vector2D<usize>, color: &PixelColor) {
+        let resolution = self.resolution();
+        raster::line(from.x() as isize, from.y() as isize, to.x() as isize, to.y() as isize, |x, y| {
+            if let Some((x, y)) = on_screen(x, y, resolution) {
+                self.draw_pixel(x, y, color);
+            }
+        });
+    }
+
+    // the outline only
+    fn draw_circle(&self, center: Vector2D<usize>, radius: usize, color: &PixelColor) {
+        let resolution = self.resolution();
+        raster::circle(center.x() as isize, center.y() as isize, radius as isize, |x, y| {
+            if let Some((x, y)) = on_screen(x, y, resolution) {
+                self.draw_pixel(x, y, color);
+            }
+        });
+    }
+
+    // An 8 pixel wide bitmap such as a glyph, one byte per row with the leftmost pixel in
+    // the top bit. Only the set bits are drawn. Writers that record what they drew record
+    // the bitmap's box once instead of every pixel.
+    fn draw_bitmap(&self, pos: Vector2D<usize>, rows: &[u8], color: &PixelColor) {
+        for (dy, bits) in rows.iter().enumerate() {
+            for dx in 0..8 {
+                if (bits << dx) & 0x80 != 0 {
+                    self.draw_pixel(pos.x() + dx, pos.y() + dy, color);
+                }
+            }
         }
     }
 }
 
+fn to_rect(pos: Vector2D<usize>, size: Vector2D<usize>) -> Rect {
+    Rect::new(pos.x(), pos.y(), size.x(), size.y())
+}
+
+fn on_screen(x: isize, y: isize, (width, height): (usize, usize)) -> Option<(usize, usize)> {
+    if x < 0 || y < 0 {
+        return None;
+    }
+    let (x, y) = (x as usize, y as usize);
+    (x < width && y < height).then(|| (x, y))
+}
+
+// 32-bit pixels in rows of `stride` pixels, of which the first `width` are on the screen:
+// video memory or a back buffer. Every operation clips first and returns the part it
+// touched.
+#[derive(Clone, Copy)]
+struct Pixels {
+    base: *mut u32,
+    stride: usize,
+    width: usize,
+    height: usize,
+}
+
+impl Pixels {
+    fn put(&self, x: usize, y: usize, value: u32) -> Option<Rect> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        unsafe { self.base.add(y * self.stride + x).write(value) };
+        Some(Rect::new(x, y, 1, 1))
+    }
+
+    fn fill(&self, rect: Rect, value: u32) -> Option<Rect> {
+        let rect = rect.clip(self.width, self.height)?;
+        for y in rect.y..rect.bottom() {
+            let row = unsafe { slice::from_raw_parts_mut(self.base.add(y * self.stride + rect.x), rect.width) };
+            row.fill(value);
+        }
+        Some(rect)
+    }
+
+    fn copy(&self, src: Rect, dst: Vector2D<usize>) -> Option<Rect> {
+        let screen = Rect::new(0, 0, self.width, self.height);
+        let (src, (x, y)) = clip_copy(src, (dst.x(), dst.y()), screen)?;
+        let copy_row = |dy: usize| unsafe {
+            // `ptr::copy` is a memmove, so rows may overlap horizontally
+            ptr::copy(
+                self.base.add((src.y + dy) * self.stride + src.x),
+                self.base.add((y + dy) * self.stride + x),
+                src.width,
+            )
+        };
+        // moving down, the bottom row goes first so that no row is overwritten before it is read
+        if y > src.y {
+            (0..src.height).rev().for_each(copy_row);
+        } else {
+            (0..src.height).for_each(copy_row);
+        }
+        Some(Rect::new(x, y, src.width, src.height))
+    }
+
+    fn line(&self, from: Vector2D<usize>, to: Vector2D<usize>, value: u32) -> Option<Rect> {
+        raster::line(from.x() as isize, from.y() as isize, to.x() as isize, to.y() as isize, |x, y| {
+            if let Some((x, y)) = on_screen(x, y, (self.width, self.height)) {
+                self.put(x, y, value);
+            }
+        });
+        let (left, top) = (from.x().min(to.x()), from.y().min(to.y()));
+        let (right, bottom) = (from.x().max(to.x()), from.y().max(to.y()));
+        Rect::new(left, top, right - left + 1, bottom - top + 1).clip(self.width, self.height)
+    }
+
+    fn circle(&self, center: Vector2D<usize>, radius: usize, value: u32) -> Option<Rect> {
+        raster::circle(center.x() as isize, center.y() as isize, radius as isize, |x, y| {
+            if let Some((x, y)) = on_screen(x, y, (self.width, self.height)) {
+                self.put(x, y, value);
+            }
+        });
+        let (left, top) = (center.x().saturating_sub(radius), center.y().saturating_sub(radius));
+        Rect::new(left, top, center.x() + radius + 1 - left, center.y() + radius + 1 - top)
+            .clip(self.width, self.height)
+    }
+
+    fn bitmap(&self, pos: Vector2D<usize>, rows: &[u8], value: u32) -> Option<Rect> {
+        for (dy, bits) in rows.iter().enumerate() {
+            for dx in 0..8 {
+                if (bits << dx) & 0x80 != 0 {
+                    self.put(pos.x() + dx, pos.y() + dy, value);
+                }
+            }
+        }
+        to_rect(pos, Vector2D::new(8, rows.len())).clip(self.width, self.height)
+    }
+}
+
 pub struct RGBResv8BitPerColorPixelWriter {
     frame_buffer: FrameBuffer
 }
@@ -163,12 +414,22 @@ impl PixelWriter for RGBResv8BitPerColorPixelWriter {
         self.frame_buffer.v()
     }
     fn draw_pixel(&self, x:usize, y:usize, color: &PixelColor) {
-        let pixel_position = self.frame_buffer.pixel_per_scan_line * y + x;
-        let pixel = unsafe { self.frame_buffer.frame_buffer.add(4*pixel_position) };
-        let color_data = [color.red, color.green, color.blue];
-        for (i, &val) in color_data.iter().enumerate() {
-            unsafe { pixel.add(i).write_volatile(val) };
-        }
+        self.frame_buffer.pixels().put(x, y, rgb_pixel(color));
+    }
+    fn fill_rect(&self, pos: Vector2D<usize>, size: Vector2D<usize>, color: &PixelColor) {
+        self.frame_buffer.pixels().fill(to_rect(pos, size), rgb_pixel(color));
+    }
+    fn copy_rect(&self, src: Vector2D<usize>, size: Vector2D<usize>, dst: Vector2D<usize>) {
+        self.frame_buffer.pixels().copy(to_rect(src, size), dst);
+    }
+    fn draw_line(&self, from: Vector2D<usize>, to: Vector2D<usize>, color: &PixelColor) {
+        self.frame_buffer.pixels().line(from, to, rgb_pixel(color));
+    }
+    fn draw_circle(&self, center: Vector2D<usize>, radius: usize, color: &PixelColor) {
+        self.frame_buffer.pixels().circle(center, radius, rgb_pixel(color));
+    }
+    fn draw_bitmap(&self, pos: Vector2D<usize>, rows: &[u8], color: &PixelColor) {
+        self.frame_buffer.pixels().bitmap(pos, rows, rgb_pixel(color));
     }
 }
 
@@ -191,15 +452,209 @@ impl PixelWriter for BGRResv8BitPerColorPixelWriter {
         self.frame_buffer.v()
     }
     fn draw_pixel(&self, x:usize, y:usize, color: &PixelColor) {
-        let pixel_position = self.frame_buffer.pixel_per_scan_line * y + x;
-        let pixel = unsafe { self.frame_buffer.frame_buffer.add(4*pixel_position) };
-        let color_data = [color.blue, color.green, color.red];
-        for (i, &item) in color_data.iter().enumerate() {
-            unsafe { pixel.add(i).write_volatile(item) };
+        self.frame_buffer.pixels().put(x, y, bgr_pixel(color));
+    }
+    fn fill_rect(&self, pos: Vector2D<usize>, size: Vector2D<usize>, color: &PixelColor) {
+        self.frame_buffer.pixels().fill(to_rect(pos, size), bgr_pixel(color));
+    }
+    fn copy_rect(&self, src: Vector2D<usize>, size: Vector2D<usize>, dst: Vector2D<usize>) {
+        self.frame_buffer.pixels().copy(to_rect(src, size), dst);
+    }
+    fn draw_line(&self, from: Vector2D<usize>, to: Vector2D<usize>, color: &PixelColor) {
+        self.frame_buffer.pixels().line(from, to, bgr_pixel(color));
+    }
+    fn draw_circle(&self, center: Vector2D<usize>, radius: usize, color: &PixelColor) {
+        self.frame_buffer.pixels().circle(center, radius, bgr_pixel(color));
+    }
+    fn draw_bitmap(&self, pos: Vector2D<usize>, rows: &[u8], color: &PixelColor) {
+        self.frame_buffer.pixels().bitmap(pos, rows, bgr_pixel(color));
+    }
+}
+
+// ------------------------------------------------------
+// Back Buffer
+// ------------------------------------------------------
+
+const MAX_DIRTY_RECTS: usize = 16;
+
+// A copy of the screen in RAM, in the frame buffer's pixel format. Drawing only touches
+// RAM and records the dirty rectangles, once per call; `flush` copies their rows to video
+// memory. Pixels off the screen are dropped.
+pub struct BackBuffer {
+    frame_buffer: FrameBuffer,
+    // horizontal_resolution * vertical_resolution, without the padding of the scan lines
+    pixels: Box<[Cell<u32>]>,
+    dirty: IrqSpinMutex<DirtyRegion<MAX_DIRTY_RECTS>>,
+}
+
+impl BackBuffer {
+    pub fn new(frame_buffer: FrameBuffer, background: &PixelColor) -> Self {
+        let background = frame_buffer.encode(background);
+        let pixels = vec![Cell::new(background); frame_buffer.h() * frame_buffer.v()].into_boxed_slice();
+        let back_buffer = Self {
+            frame_buffer,
+            pixels,
+            dirty: IrqSpinMutex::new("BACK_BUFFER_DIRTY", DirtyRegion::new()),
+        };
+        back_buffer.invalidate(Rect::new(0, 0, back_buffer.h(), back_buffer.v()));
+        back_buffer
+    }
+
+    pub fn h(&self) -> usize {
+        self.frame_buffer.h()
+    }
+
+    pub fn v(&self) -> usize {
+        self.frame_buffer.v()
+    }
+
+    pub fn encoder(&self) -> PixelEncoder {
+        self.frame_buffer.encoder()
+    }
+
+    // copies `rect` on the next flush even if nothing was drawn there
+    pub fn invalidate(&self, rect: Rect) {
+        self.dirty.lock().add(rect);
+    }
+
+    // Overwrites pixels starting at (x, y) with already encoded ones, clipped to the
+    // screen. Unlike drawing, this does not invalidate anything: the caller writes many
+    // rows and then invalidates the area once.
+    pub fn write_row(&self, x: usize, y: usize, row: &[u32]) {
+        if x >= self.h() || y >= self.v() {
+            return;
+        }
+        let width = row.len().min(self.h() - x);
+        let dst = &self.pixels[y * self.h() + x..][..width];
+        for (cell, &pixel) in dst.iter().zip(row) {
+            cell.set(pixel);
+        }
+    }
+
+    fn pixels(&self) -> Pixels {
+        Pixels {
+            // the cells make writing through a shared reference fine
+            base: self.pixels.as_ptr() as *mut u32,
+            stride: self.h(),
+            width: self.h(),
+            height: self.v(),
+        }
+    }
+
+    fn touched(&self, rect: Option<Rect>) {
+        if let Some(rect) = rect {
+            self.invalidate(rect);
+        }
+    }
+}
+
+impl PixelWriter for BackBuffer {
+    fn horizontal_resolution(&self) -> usize {
+        self.h()
+    }
+    fn vertical_resolution(&self) -> usize {
+        self.v()
+    }
+    fn draw_pixel(&self, x: usize, y: usize, color: &PixelColor) {
+        self.touched(self.pixels().put(x, y, self.frame_buffer.encode(color)));
+    }
+    fn fill_rect(&self, pos: Vector2D<usize>, size: Vector2D<usize>, color: &PixelColor) {
+        self.touched(self.pixels().fill(to_rect(pos, size), self.frame_buffer.encode(color)));
+    }
+    fn copy_rect(&self, src: Vector2D<usize>, size: Vector2D<usize>, dst: Vector2D<usize>) {
+        self.touched(self.pixels().copy(to_rect(src, size), dst));
+    }
+    fn draw_line(&self, from: Vector2D<usize>, to: Vector2D<usize>, color: &PixelColor) {
+        self.touched(self.pixels().line(from, to, self.frame_buffer.encode(color)));
+    }
+    fn draw_circle(&self, center: Vector2D<usize>, radius: usize, color: &PixelColor) {
+        self.touched(self.pixels().circle(center, radius, self.frame_buffer.encode(color)));
+    }
+    fn draw_bitmap(&self, pos: Vector2D<usize>, rows: &[u8], color: &PixelColor) {
+        self.touched(self.pixels().bitmap(pos, rows, self.frame_buffer.encode(color)));
+    }
+
+    fn flush(&self) {
+        let dirty = self.dirty.lock().take();
+        let screen = self.frame_buffer.frame_buffer as *mut u32;
+        for rect in dirty.iter().filter_map(|rect| rect.clip(self.h(), self.v())) {
+            for y in rect.y..rect.bottom() {
+                let src = self.pixels[y * self.h() + rect.x..][..rect.width].as_ptr() as *const u32;
+                // whole pixels per row; SSE is off in the kernel, so this ends up as `rep movs`
+                unsafe {
+                    let dst = screen.add(y * self.frame_buffer.pixel_per_scan_line + rect.x);
+                    core::ptr::copy_nonoverlapping(src, dst, rect.width);
+                }
+            }
+        }
+    }
+}
+
+// ------------------------------------------------------
+// Shadow Buffer
+// ------------------------------------------------------
+
+// Draws into a layer's pixels, which are in the frame buffer's format but not on the
+// screen, and remembers the area it drew to so that only that is composed again.
+pub struct ShadowWriter<'a> {
+    pixels: Pixels,
+    encoder: PixelEncoder,
+    touched: Cell<Option<Rect>>,
+    _buffer: PhantomData<&'a mut [u32]>,
+}
+
+impl<'a> ShadowWriter<'a> {
+    // `buffer` holds `width` * `height` pixels, row by row
+    pub fn new(buffer: &'a mut [u32], width: usize, height: usize, encoder: PixelEncoder) -> Self {
+        assert!(buffer.len() >= width * height);
+        Self {
+            pixels: Pixels { base: buffer.as_mut_ptr(), stride: width, width, height },
+            encoder,
+            touched: Cell::new(None),
+            _buffer: PhantomData,
+        }
+    }
+
+    // the bounding box of everything drawn so far
+    pub fn touched(&self) -> Option<Rect> {
+        self.touched.get()
+    }
+
+    fn touch(&self, rect: Option<Rect>) {
+        if let Some(rect) = rect {
+            let touched = self.touched.get().map_or(rect, |touched| touched.union(&rect));
+            self.touched.set(Some(touched));
         }
     }
 }
 
+impl PixelWriter for ShadowWriter<'_> {
+    fn horizontal_resolution(&self) -> usize {
+        self.pixels.width
+    }
+    fn vertical_resolution(&self) -> usize {
+        self.pixels.height
+    }
+    fn draw_pixel(&self, x: usize, y: usize, color: &PixelColor) {
+        self.touch(self.pixels.put(x, y, self.encoder.encode(color)));
+    }
+    fn fill_rect(&self, pos: Vector2D<usize>, size: Vector2D<usize>, color: &PixelColor) {
+        self.touch(self.pixels.fill(to_rect(pos, size), self.encoder.encode(color)));
+    }
+    fn copy_rect(&self, src: Vector2D<usize>, size: Vector2D<usize>, dst: Vector2D<usize>) {
+        self.touch(self.pixels.copy(to_rect(src, size), dst));
+    }
+    fn draw_line(&self, from: Vector2D<usize>, to: Vector2D<usize>, color: &PixelColor) {
+        self.touch(self.pixels.line(from, to, self.encoder.encode(color)));
+    }
+    fn draw_circle(&self, center: Vector2D<usize>, radius: usize, color: &PixelColor) {
+        self.touch(self.pixels.circle(center, radius, self.encoder.encode(color)));
+    }
+    fn draw_bitmap(&self, pos: Vector2D<usize>, rows: &[u8], color: &PixelColor) {
+        self.touch(self.pixels.bitmap(pos, rows, self.encoder.encode(color)));
+    }
+}
+
 pub trait Font {
     fn char_size(&self) -> (usize, usize);
     fn write_ascii(&self, writer: &dyn PixelWriter, x: usize, y: usize, c: char, fg: &PixelColor, bg: &PixelColor); 
@@ -225,17 +680,8 @@ impl Font for ShinonomeFont {
             return
         }
         // writer.draw_rect(Vector2D::new(x, y), Vector2D::new(8, 16), bg);
-        for dy in 0..16 {
-            for dx in 0..8 {
-                if ((self.font[index+dy] << dx) & 0x80) > 0 {
-                    writer.draw_pixel(x+dx, y+dy, fg);
-                } 
-                // else {
-                    // writer.draw_pixel(x+dx, y+dy, bg);
-                // }
-            }
-        }
-
+        // the whole glyph at once, so that a back buffer records one dirty rectangle for it
+        writer.draw_bitmap(Vector2D::new(x, y), &self.font[index..index+16], fg);
     }
 }
 
@@ -255,6 +701,7 @@ impl ShinonomeFont {
 }
 
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Vector2D<T: Ord + Copy> {
     x: T, 
     y: T,
@@ -273,3 +720,162 @@ impl<T: Ord + Copy> Vector2D<T> {
         self.y
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    const WIDTH: usize = 8;
+    const HEIGHT: usize = 6;
+    // two pixels of padding at the end of every scan line
+    const STRIDE: usize = 10;
+
+    fn frame_buffer(memory: &mut Vec<u32>) -> FrameBuffer {
+        memory.clear();
+        memory.resize(STRIDE * HEIGHT, 0);
+        FrameBuffer {
+            frame_buffer: memory.as_mut_ptr() as *mut u8,
+            pixel_per_scan_line: STRIDE,
+            horizontal_resolution: WIDTH,
+            vertical_resolution: HEIGHT,
+            pixel_format: PixelFormat::PixelBGRResv8BitPerColor,
+            pixel_bitmask: ChannelMasks::EMPTY,
+        }
+    }
+
+    fn padding_is_untouched(memory: &[u32]) -> bool {
+        memory.chunks(STRIDE).all(|row| row[WIDTH..].iter().all(|&p| p == 0))
+    }
+
+    #[test_case]
+    fn fill_rect_is_clipped() {
+        let mut memory = Vec::new();
+        let writer = BGRResv8BitPerColorPixelWriter::new(frame_buffer(&mut memory));
+        writer.fill_rect(Vector2D::new(6, 4), Vector2D::new(10, 10), &PixelColor::RED);
+        assert_eq!(memory[5 * STRIDE + 7], bgr_pixel(&PixelColor::RED));
+        assert_eq!(memory[4 * STRIDE + 5], 0);
+        assert!(padding_is_untouched(&memory));
+    }
+
+    #[test_case]
+    fn draw_rect_stays_inside_its_size() {
+        let mut memory = Vec::new();
+        let writer = BGRResv8BitPerColorPixelWriter::new(frame_buffer(&mut memory));
+        writer.draw_rect(Vector2D::new(1, 1), Vector2D::new(3, 3), &PixelColor::WHITE);
+        let white = bgr_pixel(&PixelColor::WHITE);
+        assert_eq!(memory[STRIDE + 1], white);
+        assert_eq!(memory[3 * STRIDE + 3], white);
+        assert_eq!(memory[2 * STRIDE + 2], 0);
+        assert_eq!(memory[STRIDE + 4], 0);
+        assert_eq!(memory[4 * STRIDE + 1], 0);
+    }
+
+    #[test_case]
+    fn copy_rect_handles_overlap() {
+        let mut memory = Vec::new();
+        let writer = BGRResv8BitPerColorPixelWriter::new(frame_buffer(&mut memory));
+        for y in 0..HEIGHT {
+            writer.fill_rect(Vector2D::new(0, y), Vector2D::new(WIDTH, 1), &PixelColor::new(y as u8, 0, 0));
+        }
+        // scroll down by two rows, the bottom rows falling off the screen
+        writer.copy_rect(Vector2D::new(0, 0), Vector2D::new(WIDTH, HEIGHT), Vector2D::new(0, 2));
+        for y in 2..HEIGHT {
+            assert_eq!(memory[y * STRIDE + WIDTH - 1], bgr_pixel(&PixelColor::new(y as u8 - 2, 0, 0)));
+        }
+        // and left by one pixel within a row
+        writer.draw_pixel(WIDTH - 1, 0, &PixelColor::GREEN);
+        writer.copy_rect(Vector2D::new(1, 0), Vector2D::new(WIDTH, 1), Vector2D::new(0, 0));
+        assert_eq!(memory[WIDTH - 2], bgr_pixel(&PixelColor::GREEN));
+        assert!(padding_is_untouched(&memory));
+    }
+
+    #[test_case]
+    fn line_and_circle_are_clipped() {
+        let mut memory = Vec::new();
+        let writer = BGRResv8BitPerColorPixelWriter::new(frame_buffer(&mut memory));
+        writer.draw_line(Vector2D::new(0, 0), Vector2D::new(20, 20), &PixelColor::WHITE);
+        writer.draw_circle(Vector2D::new(WIDTH - 1, 0), 4, &PixelColor::WHITE);
+        assert_eq!(memory[5 * STRIDE + 5], bgr_pixel(&PixelColor::WHITE));
+        assert!(padding_is_untouched(&memory));
+    }
+
+    #[test_case]
+    fn rgb_writer_puts_red_in_the_low_byte() {
+        let mut memory = Vec::new();
+        let frame_buffer = FrameBuffer {
+            pixel_format: PixelFormat::PixelRGBResv8BitPerColor,
+            ..frame_buffer(&mut memory)
+        };
+        let writer = RGBResv8BitPerColorPixelWriter::new(frame_buffer);
+        writer.fill_rect(Vector2D::new(0, 0), Vector2D::new(WIDTH, HEIGHT), &PixelColor::new(0x12, 0x34, 0x56));
+        writer.draw_line(Vector2D::new(0, 0), Vector2D::new(WIDTH - 1, 0), &PixelColor::RED);
+        assert_eq!(memory[0], 0xff);
+        assert_eq!(memory[STRIDE], 0x563412);
+        assert!(padding_is_untouched(&memory));
+    }
+
+    #[test_case]
+    fn back_buffer_uses_the_bitmask() {
+        let mut memory = Vec::new();
+        let frame_buffer = FrameBuffer {
+            pixel_format: PixelFormat::PixelBitMask,
+            // 10 bits per color
+            pixel_bitmask: ChannelMasks { red: 0x3ff, green: 0xffc00, blue: 0x3ff00000, reserved: 0xc0000000 },
+            ..frame_buffer(&mut memory)
+        };
+        // what `init_global_writer` makes of a PixelBitMask frame buffer
+        let back_buffer = BackBuffer::new(frame_buffer, &PixelColor::RED);
+        back_buffer.draw_pixel(1, 1, &PixelColor::BLUE);
+        back_buffer.flush();
+        assert_eq!(memory[0], 0x3ff);
+        assert_eq!(memory[STRIDE + 1], 0x3ff00000);
+        assert!(padding_is_untouched(&memory));
+    }
+
+    #[test_case]
+    fn back_buffer_flushes_only_what_was_drawn() {
+        let mut memory = Vec::new();
+        let back_buffer = BackBuffer::new(frame_buffer(&mut memory), &PixelColor::BLACK);
+        back_buffer.flush();
+        // something the back buffer does not know about
+        memory[5 * STRIDE + 5] = 1;
+        back_buffer.fill_rect(Vector2D::new(0, 0), Vector2D::new(2, 2), &PixelColor::BLUE);
+        assert_eq!(memory[0], 0);
+        back_buffer.flush();
+        assert_eq!(memory[STRIDE + 1], bgr_pixel(&PixelColor::BLUE));
+        assert_eq!(memory[5 * STRIDE + 5], 1);
+        assert!(padding_is_untouched(&memory));
+    }
+
+    #[test_case]
+    fn back_buffer_records_a_bitmap_once() {
+        let mut memory = Vec::new();
+        let back_buffer = BackBuffer::new(frame_buffer(&mut memory), &PixelColor::BLACK);
+        back_buffer.flush();
+        // a diagonal, clipped at the bottom of the screen
+        let rows = [0x80, 0x40, 0x20, 0x10, 0x08, 0x04, 0x02, 0x01];
+        back_buffer.draw_bitmap(Vector2D::new(1, 0), &rows, &PixelColor::WHITE);
+        assert_eq!(back_buffer.dirty.lock().iter().collect::<Vec<_>>(), [&Rect::new(1, 0, 7, HEIGHT)]);
+        back_buffer.flush();
+        let white = bgr_pixel(&PixelColor::WHITE);
+        assert_eq!(memory[1], white);
+        assert_eq!(memory[5 * STRIDE + 6], white);
+        assert_eq!(memory[5 * STRIDE + 5], 0);
+        assert!(padding_is_untouched(&memory));
+    }
+
+    #[test_case]
+    fn shadow_writer_tracks_what_it_drew() {
+        let mut memory = Vec::new();
+        let encoder = frame_buffer(&mut memory).encoder();
+        let mut shadow = vec![0; 4 * 3];
+        let writer = ShadowWriter::new(&mut shadow, 4, 3, encoder);
+        assert_eq!(writer.touched(), None);
+        writer.draw_pixel(0, 0, &PixelColor::RED);
+        writer.fill_rect(Vector2D::new(2, 1), Vector2D::new(10, 1), &PixelColor::BLUE);
+        assert_eq!(writer.touched(), Some(Rect::new(0, 0, 4, 2)));
+        assert_eq!(shadow[0], bgr_pixel(&PixelColor::RED));
+        assert_eq!(shadow[7], bgr_pixel(&PixelColor::BLUE));
+    }
+}
@@ -0,0 +1,127 @@
+//! 割り込みの入口
+//!
+//! One 16-byte stub per vector pushes a dummy error code (unless the CPU pushed a real
+//! one) and the vector number, then jumps to a common path that saves every
+//! general-purpose register and calls `interrupt_dispatch` with a pointer to the result.
+//! The kernel is built without SSE, so there is no other state to save.
+
+use super::idt::{InterruptStackFrame, InterruptVector};
+use super::{exceptions, registry};
+use super::vector::FIRST_ALLOCATABLE_VECTOR;
+use crate::pic;
+use core::arch::global_asm;
+
+const ENTRY_STUB_SIZE: u64 = 16;
+
+// the `.if` below has to match `exceptions::has_error_code`
+global_asm!(r#"
+.section .text
+.p2align 4
+.global interrupt_entry_stubs
+interrupt_entry_stubs:
+isr_vector = 0
+.rept 256
+    .p2align 4
+    .if isr_vector == 8 || (isr_vector >= 10 && isr_vector <= 14) || isr_vector == 17 || isr_vector == 21 || isr_vector == 29 || isr_vector == 30
+    .else
+    pushq $0
+    .endif
+    pushq $isr_vector
+    jmp interrupt_common
+    isr_vector = isr_vector + 1
+.endr
+
+interrupt_common:
+    cld
+    pushq %rax
+    pushq %rbx
+    pushq %rcx
+    pushq %rdx
+    pushq %rsi
+    pushq %rdi
+    pushq %rbp
+    pushq %r8
+    pushq %r9
+    pushq %r10
+    pushq %r11
+    pushq %r12
+    pushq %r13
+    pushq %r14
+    pushq %r15
+    movq %rsp, %rdi
+    call interrupt_dispatch
+    popq %r15
+    popq %r14
+    popq %r13
+    popq %r12
+    popq %r11
+    popq %r10
+    popq %r9
+    popq %r8
+    popq %rbp
+    popq %rdi
+    popq %rsi
+    popq %rdx
+    popq %rcx
+    popq %rbx
+    popq %rax
+    # vector and error code
+    addq $16, %rsp
+    iretq
+"#, options(att_syntax));
+
+extern "C" {
+    static interrupt_entry_stubs: u8;
+}
+
+// Registers of the interrupted code, in the order the entry path pushes them. Handlers
+// may modify them; they are restored on return.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct InterruptContext {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+    pub vector: u64,
+    // 0 for vectors without one
+    pub error_code: u64,
+    pub stack_frame: InterruptStackFrame,
+}
+
+impl InterruptContext {
+    pub fn vector(&self) -> u8 {
+        self.vector as u8
+    }
+}
+
+pub fn entry_stub(vector: u8) -> u64 {
+    let base = unsafe { &interrupt_entry_stubs as *const u8 as u64 };
+    base + ENTRY_STUB_SIZE * vector as u64
+}
+
+#[no_mangle]
+extern "C" fn interrupt_dispatch(context: &mut InterruptContext) {
+    let vector = context.vector();
+    if vector < FIRST_ALLOCATABLE_VECTOR {
+        exceptions::handle_exception(context);
+    } else if vector == InterruptVector::Spurious as u8
+        || vector == pic::PIC1_SPURIOUS_VECTOR
+        || vector == pic::PIC2_SPURIOUS_VECTOR
+    {
+        // spurious interrupts must not be acknowledged
+    } else {
+        registry::dispatch(vector);
+    }
+}
@@ -0,0 +1,102 @@
+//! 例外
+//!
+//! Handlers for vectors 0..32. Vectors for which the CPU pushes an error code take it as
+//! a second argument; `set_exception_handler` refuses a handler of the wrong kind. Without
+//! a handler, a breakpoint is logged and every other exception panics with an
+//! `ExceptionReport`.
+
+use super::entry::InterruptContext;
+use super::idt::InterruptVector;
+use super::report::ExceptionReport;
+use crate::kprintln;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Clone, Copy)]
+pub enum ExceptionHandler {
+    WithoutErrorCode(fn(&mut InterruptContext)),
+    WithErrorCode(fn(&mut InterruptContext, u64)),
+}
+
+// the entry stubs in `entry` have the same list
+pub fn has_error_code(vector: u8) -> bool {
+    matches!(vector, 0x08 | 0x0a..=0x0e | 0x11 | 0x15 | 0x1d | 0x1e)
+}
+
+pub fn exception_name(vector: u8) -> &'static str {
+    match vector {
+        0x00 => "DIVIDE ERROR",
+        0x01 => "DEBUG",
+        0x02 => "NON MASKABLE INTERRUPT",
+        0x03 => "BREAKPOINT",
+        0x04 => "OVERFLOW",
+        0x05 => "BOUND RANGE EXCEEDED",
+        0x06 => "INVALID OPCODE",
+        0x07 => "DEVICE NOT AVAILABLE",
+        0x08 => "DOUBLE FAULT",
+        0x09 => "COPROCESSOR SEGMENT OVERRUN",
+        0x0a => "INVALID TSS",
+        0x0b => "SEGMENT NOT PRESENT",
+        0x0c => "STACK SEGMENT FAULT",
+        0x0d => "GENERAL PROTECTION FAULT",
+        0x0e => "PAGE FAULT",
+        0x10 => "X87 FLOATING POINT",
+        0x11 => "ALIGNMENT CHECK",
+        0x12 => "MACHINE CHECK",
+        0x13 => "SIMD FLOATING POINT",
+        0x14 => "VIRTUALIZATION",
+        0x15 => "CONTROL PROTECTION",
+        0x1c => "HYPERVISOR INJECTION",
+        0x1d => "VMM COMMUNICATION",
+        0x1e => "SECURITY EXCEPTION",
+        _ => "RESERVED",
+    }
+}
+
+// Function pointers, 0 for the default handler. Exceptions (NMI, machine check) can arrive
+// at any time, so this is read without a lock; the kind follows from `has_error_code`.
+static HANDLERS: [AtomicUsize; 32] = [const { AtomicUsize::new(0) }; 32];
+
+// Handlers for double fault and machine check must not return.
+pub fn set_exception_handler(vector: u8, handler: ExceptionHandler) {
+    assert!(vector < 32, "vector {:#x} is not an exception", vector);
+    let ptr = match handler {
+        ExceptionHandler::WithoutErrorCode(f) => {
+            assert!(!has_error_code(vector), "{} has an error code", exception_name(vector));
+            f as usize
+        }
+        ExceptionHandler::WithErrorCode(f) => {
+            assert!(has_error_code(vector), "{} has no error code", exception_name(vector));
+            f as usize
+        }
+    };
+    HANDLERS[vector as usize].store(ptr, Ordering::Release);
+}
+
+pub fn reset_exception_handler(vector: u8) {
+    HANDLERS[vector as usize].store(0, Ordering::Release);
+}
+
+pub(super) fn handle_exception(context: &mut InterruptContext) {
+    let vector = context.vector();
+    match HANDLERS[vector as usize].load(Ordering::Acquire) {
+        0 => default_handler(context),
+        ptr if has_error_code(vector) => {
+            let handler: fn(&mut InterruptContext, u64) = unsafe { core::mem::transmute(ptr) };
+            let error_code = context.error_code;
+            handler(context, error_code);
+        }
+        ptr => {
+            let handler: fn(&mut InterruptContext) = unsafe { core::mem::transmute(ptr) };
+            handler(context);
+        }
+    }
+}
+
+fn default_handler(context: &mut InterruptContext) {
+    let vector = context.vector();
+    if vector == InterruptVector::Breakpoint as u8 {
+        kprintln!("breakpoint at {:#x}", context.stack_frame.rip);
+        return;
+    }
+    panic!("{}", ExceptionReport::new(context));
+}
@@ -0,0 +1,304 @@
+//! IDT
+//!
+//! A single table for all 256 vectors. `init_idt` points every entry at its stub in
+//! `entry`; afterwards only the attributes change (IST index, DPL), through the setters
+//! below. The table stays loaded, so changes take effect immediately.
+
+use super::entry;
+use crate::gdt;
+use crate::sync::SpinMutex;
+use crate::utils::bit_field::BitField;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GateType {
+    // clears RFLAGS.IF on entry
+    Interrupt = 0xe,
+    Trap = 0xf,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(transparent)]
+pub struct InterruptDescriptorAttribute {
+    data: u16
+}
+
+impl InterruptDescriptorAttribute {
+
+    pub const fn missing() -> Self {
+        Self { data: 0 }
+    }
+
+    // present, ring 0, no IST
+    pub fn new(gate_type: GateType) -> Self {
+        Self::missing()
+            .set_type(gate_type as u8)
+            .set_dpl(0)
+            .set_present(true)
+    }
+
+    pub fn get_ist(&self) -> u8 {
+        self.data.get_bits(0..3) as u8
+    }
+
+    // IST の番号 (1..=7), 0 は「IST を使わない」
+    #[must_use]
+    pub fn set_ist(mut self, val: u8) -> Self {
+        assert!(val < 1 << 3);
+        self.data = *self.data.set_bits(0..3, val as u16);
+        self
+    }
+
+    pub fn get_type(&self) -> u8 {
+        self.data.get_bits(8..12) as u8
+    }
+
+    #[must_use]
+    pub fn set_type(mut self, val: u8) -> Self {
+        assert!(val < 1 << 4);
+        self.data = *self.data.set_bits(8..12, val as u16);
+        self
+    }
+
+    // the lowest privilege level that may raise the vector with `int n`
+    pub fn get_dpl(&self) -> u8 {
+        self.data.get_bits(13..15) as u8
+    }
+
+    #[must_use]
+    pub fn set_dpl(mut self, val: u8) -> Self {
+        assert!(val < 1 << 2);
+        self.data = *self.data.set_bits(13..15, val as u16);
+        self
+    }
+
+    pub fn get_present(&self) -> bool {
+        self.data.get_bit(15)
+    }
+
+    #[must_use]
+    pub fn set_present(mut self, val: bool) -> Self {
+        self.data = *self.data.set_bit(15, val);
+        self
+    }
+}
+
+impl Default for InterruptDescriptorAttribute {
+    fn default() -> Self {
+        Self::new(GateType::Interrupt)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+#[repr(C)]
+pub struct InterruptDescriptor {
+    offset_low: u16,
+    segment_selector: u16,
+    attribute: InterruptDescriptorAttribute, // u16
+    offset_middle: u16,
+    offset_high: u32,
+    _reserved: u32,
+}
+
+impl InterruptDescriptor {
+    pub const fn missing() -> Self {
+        Self {
+            offset_low: 0,
+            segment_selector: 0,
+            attribute: InterruptDescriptorAttribute::missing(),
+            offset_middle: 0,
+            offset_high: 0,
+            _reserved: 0,
+        }
+    }
+
+    pub fn new(handler_ptr: u64, attr: InterruptDescriptorAttribute) -> Self {
+        Self {
+            offset_low: handler_ptr.get_bits(0..16) as u16,
+            segment_selector: gdt::KERNEL_CODE_SELECTOR,
+            attribute: attr,
+            offset_middle: handler_ptr.get_bits(16..32) as u16,
+            offset_high: handler_ptr.get_bits(32..64) as u32,
+            _reserved: 0,
+        }
+    }
+
+    pub fn get_offset(&self) -> u64 {
+        *0_u64
+            .set_bits(0..16, self.offset_low as u64)
+            .set_bits(16..32, self.offset_middle as u64)
+            .set_bits(32..64, self.offset_high as u64)
+    }
+
+    #[must_use]
+    pub fn set_offset(mut self, offset: u64) -> Self {
+        self.offset_low = offset.get_bits(0..16) as u16;
+        self.offset_middle = offset.get_bits(16..32) as u16;
+        self.offset_high = offset.get_bits(32..64) as u32;
+        self
+    }
+
+    pub fn get_ss(&self) -> u16 {
+        self.segment_selector
+    }
+
+    #[must_use]
+    pub fn set_ss(mut self, val: u16) -> Self {
+        self.segment_selector = val;
+        self
+    }
+
+    pub fn get_attribute(&self) -> InterruptDescriptorAttribute {
+        self.attribute
+    }
+
+    #[must_use]
+    pub fn set_attribute(mut self, attr: InterruptDescriptorAttribute) -> Self {
+        self.attribute = attr;
+        self
+    }
+}
+
+#[derive(Debug)]
+#[repr(transparent)]
+pub struct InterruptDescriptorTable {
+    data: [InterruptDescriptor; 256]
+}
+
+impl InterruptDescriptorTable {
+    pub const fn missing() -> Self {
+        Self { data: [InterruptDescriptor::missing(); 256] }
+    }
+
+    pub fn set_handler(&mut self, iv: u8, handler_ptr: u64, attr: InterruptDescriptorAttribute) {
+        self.set_descriptor(iv, InterruptDescriptor::new(handler_ptr, attr));
+    }
+
+    pub fn set_descriptor(&mut self, iv: u8, desc: InterruptDescriptor) {
+        self.data[iv as usize] = desc;
+    }
+
+    pub fn descriptor(&self, iv: u8) -> InterruptDescriptor {
+        self.data[iv as usize]
+    }
+
+    fn update_attribute<F>(&mut self, iv: u8, f: F)
+    where F: FnOnce(InterruptDescriptorAttribute) -> InterruptDescriptorAttribute {
+        let desc = self.data[iv as usize];
+        self.data[iv as usize] = desc.set_attribute(f(desc.get_attribute()));
+    }
+
+    pub fn as_ptr(&self) -> InterruptDescriptorTablePointer {
+        use core::mem::size_of;
+        InterruptDescriptorTablePointer {
+           limit: (size_of::<Self>() -1) as u16,
+           offset: self as *const Self as u64,
+           _reserved: [0; 5],
+        }
+    }
+
+    pub fn load(&self) {
+        self.as_ptr().load()
+    }
+}
+
+pub static IDT: SpinMutex<InterruptDescriptorTable> = SpinMutex::new(InterruptDescriptorTable::missing());
+
+// runs the handler for `vector` on the given IST stack (1..=7), or on the current stack for 0
+pub fn set_ist(vector: u8, ist: u8) {
+    IDT.lock().update_attribute(vector, |attr| attr.set_ist(ist));
+}
+
+// whether `int vector` may be executed from ring 3; otherwise it raises #GP
+pub fn set_user_callable(vector: u8, user_callable: bool) {
+    let dpl = if user_callable { 3 } else { 0 };
+    IDT.lock().update_attribute(vector, |attr| attr.set_dpl(dpl));
+}
+
+pub fn set_gate_type(vector: u8, gate_type: GateType) {
+    IDT.lock().update_attribute(vector, |attr| attr.set_type(gate_type as u8));
+}
+
+// IDT を初期化し, ロードする
+// これは, 初期化時に一度だけ, gdt::init_gdt の後に呼び出すこと
+pub fn init_idt() {
+    let mut idt = IDT.lock();
+    for vector in 0..=255u8 {
+        idt.set_handler(vector, entry::entry_stub(vector), InterruptDescriptorAttribute::new(GateType::Interrupt));
+    }
+    // the interrupted stack may be unusable
+    idt.update_attribute(InterruptVector::DoubleFault as u8, |attr| attr.set_ist(gdt::DOUBLE_FAULT_IST_INDEX));
+    idt.update_attribute(InterruptVector::NonMaskableInterrupt as u8, |attr| attr.set_ist(gdt::NMI_IST_INDEX));
+    idt.update_attribute(InterruptVector::MachineCheck as u8, |attr| attr.set_ist(gdt::MACHINE_CHECK_IST_INDEX));
+    // `int3` and `into` are meant to be used from user mode
+    idt.update_attribute(InterruptVector::Breakpoint as u8, |attr| attr.set_dpl(3));
+    idt.update_attribute(InterruptVector::Overflow as u8, |attr| attr.set_dpl(3));
+    idt.load();
+}
+
+#[repr(C, packed)]
+pub struct InterruptDescriptorTablePointer {
+    limit: u16,
+    offset: u64,
+    _reserved: [u16; 5],
+}
+
+impl InterruptDescriptorTablePointer {
+    pub fn load(&self) {
+        crate::asm::lidt(self);
+    }
+
+    #[must_use]
+    pub fn set_limit(mut self, limit: u16) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    #[must_use]
+    pub fn set_offset(mut self, offset: u64) -> Self {
+        self.offset = offset;
+        self
+    }
+
+}
+
+// - https://wiki.osdev.org/Interrupt_Vector_Table
+// - https://www.amd.com/system/files/TechDocs/24593.pdf: Table 8-1. Interrupt Vector Source and Cause
+#[derive(Debug, Clone, Copy)]
+pub enum InterruptVector {
+    DivideByZeroError = 0x00,
+    Debug = 0x01,
+    NonMaskableInterrupt = 0x02,
+    Breakpoint = 0x03,
+    Overflow = 0x04,
+    BoundRange = 0x05,
+    InvalidOpcode = 0x06,
+    DeviceNotAvailable = 0x07,
+    DoubleFault = 0x08,
+    InvalidTss = 0x0A,
+    SegmentNotPresent = 0x0B,
+    Stack = 0x0C,
+    GeneralProtection = 0x0D,
+    PageFault = 0x0E,
+    X87FloatingPoint = 0x10,
+    AlignmentCheck = 0x11,
+    MachineCheck = 0x12,
+    SimdFloatingPoint = 0x13,
+    Virtualization = 0x14,
+    ControlProtection = 0x15,
+    HypervisorInjection = 0x1C,
+    VmmCommunication = 0x1D,
+    Security = 0x1E,
+    LocalApicTimer = 0x41,
+    Spurious = 0xff,
+}
+
+// pushed by the CPU on every interrupt
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct InterruptStackFrame {
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
@@ -0,0 +1,15 @@
+//! 割り込み
+//!
+//! Every one of the 256 IDT entries points at a stub in `entry`, which saves all
+//! general-purpose registers into an `InterruptContext` and calls `interrupt_dispatch`.
+//! From there exceptions go to the handlers in `exceptions` and everything else to the
+//! handlers drivers attached through `registry`.
+
+pub mod idt;
+pub mod entry;
+pub mod exceptions;
+pub mod report;
+pub mod vector;
+pub mod registry;
+
+pub use entry::InterruptContext;
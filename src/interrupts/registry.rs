@@ -0,0 +1,136 @@
+//! 割り込みハンドラの登録
+//!
+//! Drivers attach handlers to an allocated vector (see `vector`) at runtime, either as a
+//! function pointer with a context pointer or as a closure. `entry::interrupt_dispatch`
+//! hands every non-exception vector to `dispatch`, which calls every handler on the vector
+//! in registration order (so level-triggered lines can be shared), counts the interrupt
+//! and sends the EOI. Handlers run with interrupts disabled and the registry locked: they
+//! must not register or unregister handlers themselves.
+
+use super::idt::InterruptVector;
+use super::vector::{self, FIRST_ALLOCATABLE_VECTOR};
+use crate::apic;
+use crate::pic;
+use crate::sync::IrqSpinMutex;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+// `context` is the pointer given to `register_handler`
+pub type HandlerFn = fn(vector: u8, context: *mut ()) -> IrqReturn;
+
+// Whether the device behind a handler raised the interrupt. A shared vector asks all of
+// its handlers; an interrupt nobody claims is counted in `unhandled_count`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqReturn {
+    Handled,
+    NotHandled,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HandlerId {
+    vector: u8,
+    id: u32,
+}
+
+impl HandlerId {
+    pub fn vector(&self) -> u8 {
+        self.vector
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegistryError {
+    // an exception or a spurious vector
+    InvalidVector(u8),
+    // not taken from `vector::allocate_vector` (or reserved)
+    NotAllocated(u8),
+    NotRegistered(HandlerId),
+}
+
+enum HandlerKind {
+    Fn { handler: HandlerFn, context: *mut () },
+    Closure(Box<dyn FnMut(u8) -> IrqReturn + Send>),
+}
+
+struct Handler {
+    id: u32,
+    kind: HandlerKind,
+}
+
+// whoever registers a context pointer guarantees it can be used from interrupt context
+unsafe impl Send for Handler {}
+
+impl Handler {
+    fn call(&mut self, vector: u8) -> IrqReturn {
+        match &mut self.kind {
+            HandlerKind::Fn { handler, context } => handler(vector, *context),
+            HandlerKind::Closure(closure) => closure(vector),
+        }
+    }
+}
+
+const NO_HANDLERS: Vec<Handler> = Vec::new();
+static HANDLERS: IrqSpinMutex<[Vec<Handler>; 256]> = IrqSpinMutex::new("INTERRUPT_HANDLERS", [NO_HANDLERS; 256]);
+static NEXT_HANDLER_ID: AtomicU32 = AtomicU32::new(0);
+
+static INTERRUPT_COUNTS: [AtomicU64; 256] = [const { AtomicU64::new(0) }; 256];
+static UNHANDLED_COUNTS: [AtomicU64; 256] = [const { AtomicU64::new(0) }; 256];
+
+pub fn register_handler(vector: u8, handler: HandlerFn, context: *mut ()) -> Result<HandlerId, RegistryError> {
+    register(vector, HandlerKind::Fn { handler, context })
+}
+
+pub fn register_closure<F>(vector: u8, closure: F) -> Result<HandlerId, RegistryError>
+where F: FnMut(u8) -> IrqReturn + Send + 'static {
+    register(vector, HandlerKind::Closure(Box::new(closure)))
+}
+
+fn register(vector: u8, kind: HandlerKind) -> Result<HandlerId, RegistryError> {
+    // spurious interrupts are never dispatched (see `entry::interrupt_dispatch`)
+    let is_spurious = vector == InterruptVector::Spurious as u8
+        || vector == pic::PIC1_SPURIOUS_VECTOR
+        || vector == pic::PIC2_SPURIOUS_VECTOR;
+    if vector < FIRST_ALLOCATABLE_VECTOR || is_spurious {
+        return Err(RegistryError::InvalidVector(vector));
+    }
+    if !vector::is_allocated(vector) {
+        return Err(RegistryError::NotAllocated(vector));
+    }
+    let id = NEXT_HANDLER_ID.fetch_add(1, Ordering::Relaxed);
+    let mut handlers = HANDLERS.lock();
+    handlers[vector as usize].push(Handler { id, kind });
+    Ok(HandlerId { vector, id })
+}
+
+// Once the last handler is gone, further interrupts on the vector are acknowledged and
+// counted as unhandled.
+pub fn unregister_handler(id: HandlerId) -> Result<(), RegistryError> {
+    let mut handlers = HANDLERS.lock();
+    let handlers = &mut handlers[id.vector as usize];
+    let idx = handlers.iter().position(|h| h.id == id.id).ok_or(RegistryError::NotRegistered(id))?;
+    handlers.remove(idx);
+    Ok(())
+}
+
+// number of times the vector was raised since boot
+pub fn interrupt_count(vector: u8) -> u64 {
+    INTERRUPT_COUNTS[vector as usize].load(Ordering::Relaxed)
+}
+
+// number of times no handler claimed the vector
+pub fn unhandled_count(vector: u8) -> u64 {
+    UNHANDLED_COUNTS[vector as usize].load(Ordering::Relaxed)
+}
+
+pub(super) fn dispatch(vector: u8) {
+    INTERRUPT_COUNTS[vector as usize].fetch_add(1, Ordering::Relaxed);
+    let mut handled = false;
+    for handler in HANDLERS.lock()[vector as usize].iter_mut() {
+        handled |= handler.call(vector) == IrqReturn::Handled;
+    }
+    if !handled {
+        UNHANDLED_COUNTS[vector as usize].fetch_add(1, Ordering::Relaxed);
+    }
+    apic::end_of_interrupt();
+}
@@ -0,0 +1,194 @@
+//! 例外レポート
+//!
+//! What the default exception handler panics with: the saved registers, the control
+//! registers, the error code decoded for page faults and selector faults, and a
+//! symbolized backtrace of the interrupted code.
+
+use super::entry::InterruptContext;
+use super::exceptions::{exception_name, has_error_code};
+use super::idt::InterruptVector;
+use crate::asm;
+use crate::backtrace::{Backtrace, Symbolized};
+use crate::utils::bit_field::BitField;
+use core::fmt;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ControlRegisters {
+    pub cr0: u64,
+    // the faulting address after a page fault
+    pub cr2: u64,
+    pub cr3: u64,
+    pub cr4: u64,
+}
+
+impl ControlRegisters {
+    pub fn read() -> Self {
+        Self {
+            cr0: asm::read_cr0(),
+            cr2: asm::read_cr2(),
+            cr3: asm::read_cr3(),
+            cr4: asm::read_cr4(),
+        }
+    }
+}
+
+// - https://www.amd.com/system/files/TechDocs/24593.pdf: 8.4.2 Page-Fault Error Code
+#[derive(Debug, Clone, Copy)]
+pub struct PageFaultErrorCode(pub u64);
+
+impl PageFaultErrorCode {
+    pub fn is_protection_violation(&self) -> bool {
+        self.0.get_bit(0)
+    }
+
+    pub fn is_write(&self) -> bool {
+        self.0.get_bit(1)
+    }
+
+    pub fn is_user(&self) -> bool {
+        self.0.get_bit(2)
+    }
+
+    pub fn is_reserved_bit_set(&self) -> bool {
+        self.0.get_bit(3)
+    }
+
+    pub fn is_instruction_fetch(&self) -> bool {
+        self.0.get_bit(4)
+    }
+
+    pub fn is_protection_key(&self) -> bool {
+        self.0.get_bit(5)
+    }
+
+    pub fn is_shadow_stack(&self) -> bool {
+        self.0.get_bit(6)
+    }
+}
+
+// e.g. "kernel write to a non-present page"
+impl fmt::Display for PageFaultErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mode = if self.is_user() { "user" } else { "kernel" };
+        let access = if self.is_instruction_fetch() {
+            "instruction fetch from"
+        } else if self.is_write() {
+            "write to"
+        } else {
+            "read from"
+        };
+        let cause = if self.is_protection_violation() {
+            "a page it may not access"
+        } else {
+            "a non-present page"
+        };
+        write!(f, "{} {} {}", mode, access, cause)?;
+        if self.is_reserved_bit_set() {
+            write!(f, ", reserved bit set in a page table entry")?;
+        }
+        if self.is_protection_key() {
+            write!(f, ", protection key violation")?;
+        }
+        if self.is_shadow_stack() {
+            write!(f, ", shadow stack access")?;
+        }
+        Ok(())
+    }
+}
+
+// error code of #TS, #NP, #SS and #GP
+// - https://www.amd.com/system/files/TechDocs/24593.pdf: 8.4.1 Selector-Error Code
+#[derive(Debug, Clone, Copy)]
+pub struct SelectorErrorCode(pub u64);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DescriptorTable {
+    Gdt,
+    Idt,
+    Ldt,
+}
+
+impl SelectorErrorCode {
+    // raised while delivering an external interrupt
+    pub fn is_external(&self) -> bool {
+        self.0.get_bit(0)
+    }
+
+    pub fn table(&self) -> DescriptorTable {
+        if self.0.get_bit(1) {
+            DescriptorTable::Idt
+        } else if self.0.get_bit(2) {
+            DescriptorTable::Ldt
+        } else {
+            DescriptorTable::Gdt
+        }
+    }
+
+    pub fn index(&self) -> u64 {
+        self.0.get_bits(3..16)
+    }
+}
+
+// e.g. "GDT entry 5 (selector 0x28)"
+impl fmt::Display for SelectorErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0 == 0 {
+            return write!(f, "not related to a segment");
+        }
+        match self.table() {
+            DescriptorTable::Idt => write!(f, "IDT vector {:#x}", self.index())?,
+            table => write!(f, "{:?} entry {} (selector {:#x})", table, self.index(), self.index() << 3)?,
+        }
+        if self.is_external() {
+            write!(f, ", during an external event")?;
+        }
+        Ok(())
+    }
+}
+
+pub struct ExceptionReport<'a> {
+    context: &'a InterruptContext,
+    control: ControlRegisters,
+    backtrace: Backtrace,
+}
+
+impl<'a> ExceptionReport<'a> {
+    // Call before anything else can fault: CR2 is overwritten by the next page fault.
+    pub fn new(context: &'a InterruptContext) -> Self {
+        Self {
+            context,
+            control: ControlRegisters::read(),
+            backtrace: Backtrace::from_frame(context.stack_frame.rip, context.rbp),
+        }
+    }
+}
+
+impl fmt::Display for ExceptionReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let c = self.context;
+        let s = &c.stack_frame;
+        let vector = c.vector();
+        writeln!(f, "EXCEPTION: {} (vector {:#x})", exception_name(vector), vector)?;
+        writeln!(f, "at {}", Symbolized(s.rip))?;
+        if has_error_code(vector) {
+            write!(f, "Error Code: {:#x}", c.error_code)?;
+            match vector {
+                v if v == InterruptVector::PageFault as u8 => {
+                    write!(f, ": {} at {:#x}", PageFaultErrorCode(c.error_code), self.control.cr2)?
+                }
+                0x0a..=0x0d => write!(f, ": {}", SelectorErrorCode(c.error_code))?,
+                _ => {}
+            }
+            writeln!(f)?;
+        }
+        writeln!(f, "RAX={:016x} RBX={:016x} RCX={:016x} RDX={:016x}", c.rax, c.rbx, c.rcx, c.rdx)?;
+        writeln!(f, "RSI={:016x} RDI={:016x} RBP={:016x} RSP={:016x}", c.rsi, c.rdi, c.rbp, s.rsp)?;
+        writeln!(f, "R8 ={:016x} R9 ={:016x} R10={:016x} R11={:016x}", c.r8, c.r9, c.r10, c.r11)?;
+        writeln!(f, "R12={:016x} R13={:016x} R14={:016x} R15={:016x}", c.r12, c.r13, c.r14, c.r15)?;
+        writeln!(f, "RIP={:016x} RFL={:016x} CS={:04x} SS={:04x}", s.rip, s.rflags, s.cs, s.ss)?;
+        let cr = &self.control;
+        writeln!(f, "CR0={:016x} CR2={:016x} CR3={:016x} CR4={:016x}", cr.cr0, cr.cr2, cr.cr3, cr.cr4)?;
+        writeln!(f, "Backtrace:")?;
+        write!(f, "{}", self.backtrace)
+    }
+}
@@ -0,0 +1,145 @@
+//! 割り込みベクタの割り当て
+//!
+//! Vectors 0..32 are exceptions and never handed out. Of the 32..=255 range, the legacy
+//! PIC vectors and the fixed vectors in `idt::InterruptVector` are reserved from the
+//! start; everything else is allocated at runtime by drivers (MSI, IO APIC inputs).
+
+use super::idt::InterruptVector;
+use crate::pic;
+use crate::sync::IrqSpinMutex;
+
+pub const FIRST_ALLOCATABLE_VECTOR: u8 = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VectorError {
+    // below `FIRST_ALLOCATABLE_VECTOR`
+    Exception(u8),
+    AlreadyAllocated(u8),
+    NotAllocated(u8),
+}
+
+// one bit per vector, set if allocated
+struct VectorBitmap([u64; 4]);
+
+impl VectorBitmap {
+    const fn new() -> Self {
+        let mut bitmap = Self([0; 4]);
+        let mut vector = 0;
+        while vector < FIRST_ALLOCATABLE_VECTOR {
+            bitmap = bitmap.with(vector);
+            vector += 1;
+        }
+        // masked, but IRQ 7/15 still show up spuriously
+        let mut vector = pic::PIC1_VECTOR_OFFSET;
+        while vector < pic::PIC2_VECTOR_OFFSET + 8 {
+            bitmap = bitmap.with(vector);
+            vector += 1;
+        }
+        bitmap
+            .with(InterruptVector::LocalApicTimer as u8)
+            .with(InterruptVector::Spurious as u8)
+    }
+
+    const fn with(mut self, vector: u8) -> Self {
+        self.0[vector as usize / 64] |= 1 << (vector % 64);
+        self
+    }
+
+    fn get(&self, vector: u8) -> bool {
+        self.0[vector as usize / 64] & (1 << (vector % 64)) != 0
+    }
+
+    fn set(&mut self, vector: u8, allocated: bool) {
+        if allocated {
+            self.0[vector as usize / 64] |= 1 << (vector % 64);
+        } else {
+            self.0[vector as usize / 64] &= !(1 << (vector % 64));
+        }
+    }
+}
+
+static ALLOCATED: IrqSpinMutex<VectorBitmap> = IrqSpinMutex::new("ALLOCATED_VECTORS", VectorBitmap::new());
+
+// the lowest free vector
+pub fn allocate_vector() -> Option<u8> {
+    allocate_vectors(1)
+}
+
+// `count` consecutive vectors aligned to `count`, as multi-message MSI requires.
+// Returns the first one. `count` must be a power of two.
+pub fn allocate_vectors(count: usize) -> Option<u8> {
+    assert!(count.is_power_of_two() && count <= 32, "invalid vector count {}", count);
+    let mut allocated = ALLOCATED.lock();
+    let first = (FIRST_ALLOCATABLE_VECTOR as usize..=255)
+        .step_by(count)
+        .find(|&first| (first..first + count).all(|v| !allocated.get(v as u8)))?;
+    for vector in first..first + count {
+        allocated.set(vector as u8, true);
+    }
+    Some(first as u8)
+}
+
+// allocates a specific vector, e.g. one that firmware or a fixed LVT entry already uses
+pub fn reserve_vector(vector: u8) -> Result<(), VectorError> {
+    if vector < FIRST_ALLOCATABLE_VECTOR {
+        return Err(VectorError::Exception(vector));
+    }
+    let mut allocated = ALLOCATED.lock();
+    if allocated.get(vector) {
+        return Err(VectorError::AlreadyAllocated(vector));
+    }
+    allocated.set(vector, true);
+    Ok(())
+}
+
+// the caller must have unregistered its handlers and stopped the device from raising it
+pub fn free_vector(vector: u8) -> Result<(), VectorError> {
+    if vector < FIRST_ALLOCATABLE_VECTOR {
+        return Err(VectorError::Exception(vector));
+    }
+    let mut allocated = ALLOCATED.lock();
+    if !allocated.get(vector) {
+        return Err(VectorError::NotAllocated(vector));
+    }
+    allocated.set(vector, false);
+    Ok(())
+}
+
+pub fn is_allocated(vector: u8) -> bool {
+    ALLOCATED.lock().get(vector)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interrupts::idt::InterruptVector;
+
+    #[test_case]
+    fn allocate_and_free() {
+        let vector = allocate_vector().unwrap();
+        assert!(vector >= FIRST_ALLOCATABLE_VECTOR);
+        assert!(is_allocated(vector));
+        assert_eq!(reserve_vector(vector), Err(VectorError::AlreadyAllocated(vector)));
+        free_vector(vector).unwrap();
+        assert!(!is_allocated(vector));
+        assert_eq!(free_vector(vector), Err(VectorError::NotAllocated(vector)));
+    }
+
+    #[test_case]
+    fn allocate_vectors_is_aligned() {
+        let first = allocate_vectors(8).unwrap();
+        assert_eq!(first % 8, 0);
+        for vector in first..first + 8 {
+            assert!(is_allocated(vector));
+            free_vector(vector).unwrap();
+        }
+    }
+
+    #[test_case]
+    fn fixed_vectors_are_reserved() {
+        assert!(is_allocated(InterruptVector::LocalApicTimer as u8));
+        assert!(is_allocated(InterruptVector::Spurious as u8));
+        assert_eq!(reserve_vector(0x0e), Err(VectorError::Exception(0x0e)));
+        assert_eq!(free_vector(0x0e), Err(VectorError::Exception(0x0e)));
+    }
+}
@@ -0,0 +1,200 @@
+//! IO APIC
+//!
+//! Routes device interrupts (GSIs) to the local APIC. The IO APICs come from the MADT,
+//! and interrupt source overrides decide polarity and trigger mode. Every input starts
+//! masked; `register_irq` attaches the handler to the vector in the interrupt registry
+//! and then unmasks the input. The registry acknowledges with `apic::end_of_interrupt`,
+//! which also ends level-triggered interrupts at the IO APIC.
+
+use crate::acpi::{self, madt::{Polarity, TriggerMode}};
+use crate::apic;
+use crate::interrupts::registry::{self, HandlerFn, HandlerId, RegistryError};
+use crate::paging::{self, VirtAddr};
+use crate::pic;
+use crate::sync::IrqSpinMutex;
+use crate::utils::bit_field::BitField;
+use crate::{debug, warn};
+use alloc::vec::Vec;
+
+// MMIO registers
+const IOREGSEL: u64 = 0x00;
+const IOWIN: u64 = 0x10;
+// indirect registers
+const IOAPICID: u32 = 0x00;
+const IOAPICVER: u32 = 0x01;
+const IOREDTBL: u32 = 0x10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoApicError {
+    // no IO APIC handles this GSI (or there is no MADT)
+    NoIoApic(u32),
+    Registry(RegistryError),
+}
+
+impl From<RegistryError> for IoApicError {
+    fn from(e: RegistryError) -> Self {
+        Self::Registry(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RedirectionEntry(u64);
+
+impl RedirectionEntry {
+    const MASKED: usize = 16;
+
+    // fixed delivery, physical destination
+    pub fn new(vector: u8, destination: u8, polarity: Polarity, trigger: TriggerMode) -> Self {
+        let mut data = 0u64;
+        let _ = data
+            .set_bits(0..8, vector as u64)
+            .set_bit(13, polarity == Polarity::ActiveLow)
+            .set_bit(15, trigger == TriggerMode::Level)
+            .set_bits(56..64, destination as u64);
+        Self(data)
+    }
+
+    pub fn is_masked(&self) -> bool {
+        self.0.get_bit(Self::MASKED)
+    }
+
+    #[must_use]
+    pub fn set_masked(mut self, masked: bool) -> Self {
+        let _ = self.0.set_bit(Self::MASKED, masked);
+        self
+    }
+}
+
+struct IoApic {
+    base: VirtAddr,
+    id: u8,
+    gsi_base: u32,
+    entries: u32,
+}
+
+impl IoApic {
+    fn read(&self, reg: u32) -> u32 {
+        unsafe {
+            core::ptr::write_volatile((self.base + IOREGSEL) as *mut u32, reg);
+            core::ptr::read_volatile((self.base + IOWIN) as *const u32)
+        }
+    }
+
+    fn write(&self, reg: u32, value: u32) {
+        unsafe {
+            core::ptr::write_volatile((self.base + IOREGSEL) as *mut u32, reg);
+            core::ptr::write_volatile((self.base + IOWIN) as *mut u32, value);
+        }
+    }
+
+    fn handles(&self, gsi: u32) -> bool {
+        self.gsi_base <= gsi && gsi < self.gsi_base + self.entries
+    }
+
+    fn read_entry(&self, gsi: u32) -> RedirectionEntry {
+        let reg = IOREDTBL + 2 * (gsi - self.gsi_base);
+        RedirectionEntry(self.read(reg) as u64 | (self.read(reg + 1) as u64) << 32)
+    }
+
+    // the low half holds the mask bit, so it is written last
+    fn write_entry(&self, gsi: u32, entry: RedirectionEntry) {
+        let reg = IOREDTBL + 2 * (gsi - self.gsi_base);
+        self.write(reg + 1, (entry.0 >> 32) as u32);
+        self.write(reg, entry.0 as u32);
+    }
+}
+
+static IO_APICS: IrqSpinMutex<Vec<IoApic>> = IrqSpinMutex::new("IO_APICS", Vec::new());
+
+// レガシー PIC を無効化し, MADT に記載された IO APIC の入力をすべてマスクする.
+// `init_idt`, `acpi::init_acpi` と `apic::init_local_apic` の後に呼び出すこと
+pub fn init_ioapic() {
+    // their spurious IRQs are ignored by `interrupts::entry`
+    pic::disable_legacy_pics();
+
+    let madt = match acpi::madt() {
+        Some(madt) => madt,
+        None => {
+            warn!("ioapic: no MADT, only MSI interrupts are available");
+            return;
+        }
+    };
+    let mut io_apics = IO_APICS.lock();
+    for io_apic in madt.io_apics.iter() {
+        let base = paging::map_mmio(io_apic.address as u64, paging::PAGE_SIZE)
+            .expect("failed to map an IO APIC");
+        let mut io_apic = IoApic { base, id: io_apic.id, gsi_base: io_apic.gsi_base, entries: 0 };
+        io_apic.entries = io_apic.read(IOAPICVER).get_bits(16..24) + 1;
+        for gsi in io_apic.gsi_base..io_apic.gsi_base + io_apic.entries {
+            let entry = io_apic.read_entry(gsi);
+            io_apic.write_entry(gsi, entry.set_masked(true));
+        }
+        debug!(
+            "ioapic: id {} (reg {}), GSI {}..{}",
+            io_apic.id, io_apic.read(IOAPICID).get_bits(24..28), io_apic.gsi_base, io_apic.gsi_base + io_apic.entries
+        );
+        io_apics.push(io_apic);
+    }
+}
+
+// ISA IRQs are edge-triggered/active-high and PCI interrupts level-triggered/active-low,
+// unless an interrupt source override says otherwise
+fn gsi_mode(gsi: u32) -> (Polarity, TriggerMode) {
+    let isa_default = (Polarity::ActiveHigh, TriggerMode::Edge);
+    let o = acpi::madt().and_then(|madt| {
+        madt.interrupt_source_overrides.iter().find(|o| o.gsi == gsi)
+    });
+    match o {
+        Some(o) => {
+            let polarity = match o.polarity {
+                Polarity::ConformsToBus => isa_default.0,
+                p => p,
+            };
+            let trigger = match o.trigger {
+                TriggerMode::ConformsToBus => isa_default.1,
+                t => t,
+            };
+            (polarity, trigger)
+        }
+        None if gsi < 16 => isa_default,
+        None => (Polarity::ActiveLow, TriggerMode::Level),
+    }
+}
+
+fn with_io_apic<R>(gsi: u32, f: impl FnOnce(&IoApic) -> R) -> Result<R, IoApicError> {
+    let io_apics = IO_APICS.lock();
+    let io_apic = io_apics.iter().find(|io| io.handles(gsi)).ok_or(IoApicError::NoIoApic(gsi))?;
+    Ok(f(io_apic))
+}
+
+// Delivers `gsi` to this CPU as `vector` (from `vector::allocate_vector`), handled by
+// `handler`. Several GSIs may share a vector.
+pub fn register_irq(gsi: u32, vector: u8, handler: HandlerFn, context: *mut ()) -> Result<HandlerId, IoApicError> {
+    let (polarity, trigger) = gsi_mode(gsi);
+    let entry = RedirectionEntry::new(vector, apic::local_apic_id(), polarity, trigger);
+    let id = with_io_apic(gsi, |io_apic| {
+        // the handler has to be in place before the input is unmasked
+        let id = registry::register_handler(vector, handler, context)?;
+        io_apic.write_entry(gsi, entry);
+        Ok::<_, IoApicError>(id)
+    })??;
+    debug!("ioapic: GSI {} -> vector {:#x} ({:?}, {:?})", gsi, vector, polarity, trigger);
+    Ok(id)
+}
+
+// like `register_irq`, for an ISA IRQ number (e.g. 4 for COM1)
+pub fn register_isa_irq(irq: u8, vector: u8, handler: HandlerFn, context: *mut ()) -> Result<HandlerId, IoApicError> {
+    let gsi = match acpi::madt() {
+        Some(madt) => madt.isa_irq_to_gsi(irq).0,
+        None => irq as u32,
+    };
+    register_irq(gsi, vector, handler, context)
+}
+
+pub fn mask_irq(gsi: u32) -> Result<(), IoApicError> {
+    with_io_apic(gsi, |io_apic| io_apic.write_entry(gsi, io_apic.read_entry(gsi).set_masked(true)))
+}
+
+pub fn unmask_irq(gsi: u32) -> Result<(), IoApicError> {
+    with_io_apic(gsi, |io_apic| io_apic.write_entry(gsi, io_apic.read_entry(gsi).set_masked(false)))
+}
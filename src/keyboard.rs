@@ -0,0 +1,68 @@
+//! USB キーボード
+//!
+//! The HID keyboard driver calls `keyboard_observer` while the main loop processes xHC
+//! events; key presses are forwarded to the main loop as `Message::KeyPush`. Characters
+//! typed on the serial console are mapped back to HID usage IDs with `ascii_to_keycode`
+//! and arrive as the same messages.
+
+use crate::message::{post_message, Message};
+
+pub const L_CONTROL: u8 = 0b0000_0001;
+pub const L_SHIFT: u8 = 0b0000_0010;
+pub const L_ALT: u8 = 0b0000_0100;
+pub const L_GUI: u8 = 0b0000_1000;
+pub const R_CONTROL: u8 = 0b0001_0000;
+pub const R_SHIFT: u8 = 0b0010_0000;
+pub const R_ALT: u8 = 0b0100_0000;
+pub const R_GUI: u8 = 0b1000_0000;
+
+// US layout, indexed by HID usage ID
+const KEYCODE_MAP: [u8; 256] = keymap(b"\
+\0\0\0\0abcdefghijklmnopqrstuvwxyz1234567890\n\0\x08\t -=[]\\#;'`,./");
+const KEYCODE_MAP_SHIFTED: [u8; 256] = keymap(b"\
+\0\0\0\0ABCDEFGHIJKLMNOPQRSTUVWXYZ!@#$%^&*()\n\0\x08\t _+{}|~:\"~<>?");
+
+const fn keymap(head: &[u8]) -> [u8; 256] {
+    let mut map = [0u8; 256];
+    let mut i = 0;
+    while i < head.len() {
+        map[i] = head[i];
+        i += 1;
+    }
+    // keypad
+    let keypad = b"/*-+\n1234567890.";
+    let mut j = 0;
+    while j < keypad.len() {
+        map[0x54 + j] = keypad[j];
+        j += 1;
+    }
+    map
+}
+
+// 0 if the key has no ASCII representation
+pub fn keycode_to_ascii(modifier: u8, keycode: u8) -> u8 {
+    if modifier & (L_SHIFT | R_SHIFT) != 0 {
+        KEYCODE_MAP_SHIFTED[keycode as usize]
+    } else {
+        KEYCODE_MAP[keycode as usize]
+    }
+}
+
+// The modifier and key that type `ascii`, looked up in the same tables. Terminals send CR
+// for Enter and DEL for Backspace.
+pub fn ascii_to_keycode(ascii: u8) -> Option<(u8, u8)> {
+    let ascii = match ascii {
+        b'\r' => b'\n',
+        0x7f => 0x08,
+        0 => return None,
+        ascii => ascii,
+    };
+    if let Some(keycode) = KEYCODE_MAP.iter().position(|&c| c == ascii) {
+        return Some((0, keycode as u8));
+    }
+    KEYCODE_MAP_SHIFTED.iter().position(|&c| c == ascii).map(|keycode| (L_SHIFT, keycode as u8))
+}
+
+pub extern "C" fn keyboard_observer(modifier: u8, keycode: u8) {
+    post_message(Message::KeyPush { modifier, keycode });
+}
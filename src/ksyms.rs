@@ -0,0 +1,59 @@
+//! カーネルのシンボルテーブル
+//!
+//! The image reserves a `.ksyms` section that `tools/embed-symbols.sh` fills after linking
+//! with the function symbols of the final ELF, one `<address> <type> <name>` line each,
+//! sorted by address (`nm -n`). Until then the section is all zeros and lookups fail.
+
+use core::arch::global_asm;
+use core::str;
+
+const KERNEL_SYMBOLS_SIZE: usize = 1024 * 1024;
+
+// defined in assembly so that the compiler cannot assume the contents are zero
+global_asm!(r#"
+.section .ksyms, "a", @progbits
+.global kernel_symbols
+kernel_symbols:
+    .space 1024 * 1024
+"#);
+
+extern "C" {
+    static kernel_symbols: [u8; KERNEL_SYMBOLS_SIZE];
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Symbol {
+    pub name: &'static str,
+    pub addr: u64,
+}
+
+fn table() -> &'static [u8] {
+    let table = unsafe { &kernel_symbols };
+    let len = table.iter().position(|&b| b == 0).unwrap_or(table.len());
+    &table[..len]
+}
+
+fn parse_line(line: &'static [u8]) -> Option<Symbol> {
+    let line = str::from_utf8(line).ok()?;
+    let mut fields = line.splitn(3, ' ');
+    let addr = u64::from_str_radix(fields.next()?, 16).ok()?;
+    let _kind = fields.next()?;
+    let name = fields.next()?;
+    Some(Symbol { name, addr })
+}
+
+pub fn is_available() -> bool {
+    !table().is_empty()
+}
+
+// the symbol `addr` belongs to, i.e. the last one at or below it
+pub fn lookup(addr: u64) -> Option<Symbol> {
+    let mut found = None;
+    for symbol in table().split(|&b| b == b'\n').filter_map(parse_line) {
+        if symbol.addr > addr {
+            break;
+        }
+        found = Some(symbol);
+    }
+    found
+}
@@ -0,0 +1,182 @@
+//! レイヤー
+//!
+//! Everything on the screen is a layer: the desktop background at the bottom, windows (the
+//! console among them) above it and the mouse cursor on top. A layer draws into its own shadow buffer through
+//! `draw`; moving, showing or raising it composes the screen area it affects again, from
+//! the bottom layer up, into the back buffer behind `WRITER` and flushes that area.
+//!
+//! `LAYERS` is taken after the lock of whatever owns a layer (`WINDOWS`, `MOUSE`), so
+//! nothing here may log or print while holding it. The crash screen draws on video
+//! memory directly, over all layers.
+
+use crate::graphics::{BackBuffer, PixelColor, PixelEncoder, PixelWriter, ShadowWriter};
+use crate::sync::IrqSpinMutex;
+use potato_utils::layer::{Canvas, Layer, LayerStack};
+use potato_utils::rect::Rect;
+
+pub use potato_utils::layer::LayerId;
+
+// z values of the layers the kernel creates; layers with equal z stack in the order they
+// were added or last raised
+pub const DESKTOP_Z: i32 = 0;
+pub const WINDOW_Z: i32 = 10;
+pub const MOUSE_Z: i32 = i32::MAX;
+
+pub struct LayerManager {
+    stack: LayerStack,
+    screen: &'static BackBuffer,
+    encoder: PixelEncoder,
+}
+
+pub static LAYERS: IrqSpinMutex<Option<LayerManager>> = IrqSpinMutex::new("LAYERS", None);
+
+// the back buffer as the target of `LayerStack::compose`
+struct ScreenCanvas(&'static BackBuffer);
+
+impl Canvas for ScreenCanvas {
+    fn write_row(&mut self, x: usize, y: usize, pixels: &[u32]) {
+        self.0.write_row(x, y, pixels);
+    }
+}
+
+impl LayerManager {
+    fn new(screen: &'static BackBuffer) -> Self {
+        Self {
+            stack: LayerStack::new(screen.h(), screen.v()),
+            screen,
+            encoder: screen.encoder(),
+        }
+    }
+
+    // a visible `width` x `height` layer at (0, 0) filled with `background`
+    pub fn new_layer(&mut self, width: usize, height: usize, z: i32, background: &PixelColor) -> LayerId {
+        let layer = Layer::new(width, height, z, self.encoder.encode(background));
+        let id = self.stack.add(layer);
+        self.compose();
+        id
+    }
+
+    // starts out all `transparent`, so nothing shows until something is drawn
+    pub fn new_transparent_layer(&mut self, width: usize, height: usize, z: i32, transparent: &PixelColor) -> LayerId {
+        let transparent = self.encoder.encode(transparent);
+        let layer = Layer::new(width, height, z, transparent).with_transparent(Some(transparent));
+        let id = self.stack.add(layer);
+        self.compose();
+        id
+    }
+
+    pub fn remove_layer(&mut self, id: LayerId) {
+        self.stack.remove(id);
+        self.compose();
+    }
+
+    // Draws into the layer's shadow buffer with the layer's own coordinates, then puts
+    // what was drawn on the screen. Returns None for an unknown layer.
+    pub fn draw<R>(&mut self, id: LayerId, f: impl FnOnce(&dyn PixelWriter) -> R) -> Option<R> {
+        let encoder = self.encoder;
+        let layer = self.stack.get_mut(id)?;
+        let (width, height) = (layer.width(), layer.height());
+        let writer = ShadowWriter::new(layer.pixels_mut(), width, height, encoder);
+        let result = f(&writer);
+        if let Some(touched) = writer.touched() {
+            self.stack.invalidate_rect(id, touched);
+            self.compose();
+        }
+        Some(result)
+    }
+
+    pub fn screen_size(&self) -> (usize, usize) {
+        (self.screen.h(), self.screen.v())
+    }
+
+    pub fn position(&self, id: LayerId) -> Option<(isize, isize)> {
+        self.stack.get(id).map(Layer::position)
+    }
+
+    pub fn size(&self, id: LayerId) -> Option<(usize, usize)> {
+        self.stack.get(id).map(|layer| (layer.width(), layer.height()))
+    }
+
+    pub fn move_to(&mut self, id: LayerId, x: isize, y: isize) {
+        self.stack.move_to(id, x, y);
+        self.compose();
+    }
+
+    pub fn move_relative(&mut self, id: LayerId, dx: isize, dy: isize) {
+        self.stack.move_relative(id, dx, dy);
+        self.compose();
+    }
+
+    pub fn set_visible(&mut self, id: LayerId, visible: bool) {
+        self.stack.set_visible(id, visible);
+        self.compose();
+    }
+
+    // also raises the layer above the others with the same z
+    pub fn set_z(&mut self, id: LayerId, z: i32) {
+        self.stack.set_z(id, z);
+        self.compose();
+    }
+
+    // pixels of `color` let the layers below show through
+    pub fn set_transparent(&mut self, id: LayerId, color: Option<&PixelColor>) {
+        let transparent = color.map(|color| self.encoder.encode(color));
+        self.stack.set_transparent(id, transparent);
+        self.compose();
+    }
+
+    // the topmost layer showing at (x, y) on the screen
+    pub fn layer_at(&self, x: usize, y: usize) -> Option<LayerId> {
+        self.stack.layer_at(x, y)
+    }
+
+    // bottom to top
+    pub fn ids(&self) -> impl DoubleEndedIterator<Item = LayerId> + '_ {
+        self.stack.ids()
+    }
+
+    // on the screen, clipped to it
+    pub fn area(&self, id: LayerId) -> Option<Rect> {
+        self.stack.area(id)
+    }
+
+    pub fn is_visible(&self, id: LayerId) -> bool {
+        self.stack.get(id).map_or(false, Layer::is_visible)
+    }
+
+    fn compose(&mut self) {
+        let composed = self.stack.compose(&mut ScreenCanvas(self.screen));
+        if composed.is_empty() {
+            return;
+        }
+        for rect in composed.iter() {
+            self.screen.invalidate(*rect);
+        }
+        self.screen.flush();
+    }
+}
+
+// Composes into `screen` from now on, starting with a desktop layer of `background` that
+// covers the whole screen. Needs the heap.
+pub fn init_layers(screen: &'static BackBuffer, background: &PixelColor) {
+    let mut manager = LayerManager::new(screen);
+    manager.new_layer(screen.h(), screen.v(), DESKTOP_Z, background);
+    *LAYERS.lock() = Some(manager);
+}
+
+// `f` with the layer manager, or None before `init_layers`
+pub fn with_layers<R>(f: impl FnOnce(&mut LayerManager) -> R) -> Option<R> {
+    LAYERS.lock().as_mut().map(f)
+}
+
+pub fn new_layer(width: usize, height: usize, z: i32, background: &PixelColor) -> Option<LayerId> {
+    with_layers(|layers| layers.new_layer(width, height, z, background))
+}
+
+pub fn draw<R>(id: LayerId, f: impl FnOnce(&dyn PixelWriter) -> R) -> Option<R> {
+    with_layers(|layers| layers.draw(id, f)).flatten()
+}
+
+pub fn move_to(id: LayerId, x: isize, y: isize) {
+    with_layers(|layers| layers.move_to(id, x, y));
+}
@@ -1,9 +1,19 @@
 #![no_std]
 #![feature(const_maybe_uninit_assume_init)]
-#![feature(abi_x86_interrupt)]
+#![feature(alloc_error_handler)]
+#![feature(custom_test_frameworks)]
+#![test_runner(crate::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+#![cfg_attr(test, no_main)]
+
+extern crate alloc;
 
 pub mod graphics;
 pub mod console;
+pub mod layer;
+pub mod window;
+pub mod output;
+pub mod cmdline;
 pub mod mouse;
 pub mod sync;
 pub mod pci;
@@ -12,10 +22,34 @@ pub mod logger;
 pub mod xhc;
 pub mod utils;
 pub mod asm;
+pub mod memory_manager;
+pub mod paging;
+pub mod allocator;
+pub mod gdt;
+pub mod message;
+pub mod keyboard;
+pub mod acpi;
+pub mod apic;
+pub mod pic;
+pub mod ioapic;
+pub mod timer;
+pub mod ksyms;
+pub mod backtrace;
+pub mod serial;
+pub mod power;
+pub mod panic;
+#[cfg(test)]
+pub mod testing;
 
 use core::panic::PanicInfo;
-// TODO: write another panic function for release build
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    crate::panic::handle_panic(info)
+}
+
+#[cfg(test)]
 #[panic_handler]
-fn panic(_info: &PanicInfo) -> ! {
-    loop {}
+fn panic(info: &PanicInfo) -> ! {
+    crate::testing::handle_test_panic(info)
 }
\ No newline at end of file
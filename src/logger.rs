@@ -1,91 +1,272 @@
-use crate::sync::SpinMutex;
-use core::ops::Deref;
-use core::fmt::Arguments;
+//! ログ
+//!
+//! The kernel's implementation of the `log` facade. Both the `error!` .. `trace!` macros
+//! below and the `log` crate's own macros end up in `KernelLogger`, which filters by module
+//! (`LogFilter`: a default level plus per-module levels, set at boot), stamps the record
+//! with the uptime and the CPU, keeps the last `LOG_RING_CAPACITY` records for `records`
+//! and passes it to every registered `LogSink`. The default sink prints a line to the
+//! output sinks, i.e. the console and COM1.
+//!
+//! Records are formatted into a fixed-size buffer, so logging does not allocate and is
+//! fine in interrupt handlers. Messages longer than `LOG_MESSAGE_LEN` are cut.
 
-static LOG_LEVEL: SpinMutex<LogLevel> = SpinMutex::new(LogLevel::Error);
+use crate::apic;
+use crate::cmdline;
+use crate::output;
+use crate::sync::IrqSpinMutex;
+use crate::timer;
+use crate::warn;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use core::time::Duration;
+use log::{Log, Metadata, Record};
+use potato_utils::fixed_string::FixedString;
+use potato_utils::log_filter::{FilterError, LogFilter, MAX_TARGET_LEN};
+use potato_utils::ring::Ring;
 
-pub fn set_log_level(level: LogLevel) {
-    LOG_LEVEL.lock().set(level);
-}
+pub use log::{Level, LevelFilter};
+
+pub const LOG_MESSAGE_LEN: usize = 160;
+pub const LOG_RING_CAPACITY: usize = 256;
+const MAX_LOG_SINKS: usize = 4;
 
 #[derive(Clone, Copy)]
-pub enum LogLevel {
-    Error = 0,
-    Warn,
-    Info,
-    Debug,
-    Trace,
+pub struct LogRecord {
+    pub level: Level,
+    pub uptime: Duration,
+    // initial APIC ID
+    pub cpu: u8,
+    // the module path unless the caller set a target
+    pub target: FixedString<MAX_TARGET_LEN>,
+    pub message: FixedString<LOG_MESSAGE_LEN>,
 }
 
-impl LogLevel {
-    fn set(&mut self, level: LogLevel) {
-        *self = level;
+// [    1.234567] cpu0 WARN  potatOS::acpi: message
+impl fmt::Display for LogRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "[{:>5}.{:06}] cpu{} {:<5} {}: {}",
+            self.uptime.as_secs(),
+            self.uptime.subsec_micros(),
+            self.cpu,
+            self.level,
+            self.target,
+            self.message,
+        )?;
+        if self.message.is_truncated() {
+            f.write_str("...")?;
+        }
+        Ok(())
     }
 }
 
+pub trait LogSink: Sync {
+    fn name(&self) -> &'static str;
+    // called after filtering, with no logger lock held
+    fn log(&self, record: &LogRecord);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogSinkError {
+    TooManySinks,
+    AlreadyRegistered,
+    NotRegistered,
+}
+
+// prints records to `kprint!`'s output sinks
+pub struct OutputLogSink;
+
+pub static OUTPUT_LOG_SINK: OutputLogSink = OutputLogSink;
+
+impl LogSink for OutputLogSink {
+    fn name(&self) -> &'static str {
+        "output"
+    }
+
+    fn log(&self, record: &LogRecord) {
+        output::_print(format_args!("{}\n", record));
+    }
+}
+
+static FILTER: IrqSpinMutex<LogFilter> = IrqSpinMutex::new("LOG_FILTER", LogFilter::new(LevelFilter::Error));
+static RING: IrqSpinMutex<Ring<LogRecord, LOG_RING_CAPACITY>> = IrqSpinMutex::new("LOG_RING", Ring::new());
+static SINKS: IrqSpinMutex<[Option<&'static dyn LogSink>; MAX_LOG_SINKS]> =
+    IrqSpinMutex::new("LOG_SINKS", [None; MAX_LOG_SINKS]);
+
+pub struct KernelLogger;
+
+pub static LOGGER: KernelLogger = KernelLogger;
+
+impl Log for KernelLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        FILTER.lock().enabled(metadata.target(), metadata.level())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let mut message = FixedString::new();
+        let _ = write!(message, "{}", record.args());
+        let record = LogRecord {
+            level: record.level(),
+            uptime: timer::uptime(),
+            cpu: apic::cpu_id(),
+            target: FixedString::from(record.target()),
+            message,
+        };
+        RING.lock().push(record);
+        let sinks = *SINKS.lock();
+        for sink in sinks.iter().flatten() {
+            sink.log(&record);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+// Needs nothing, so it runs first; records logged before this are dropped.
+pub fn init_logger() {
+    log::set_logger(&LOGGER).expect("a logger is already set");
+    log::set_max_level(FILTER.lock().max_level());
+    register_log_sink(&OUTPUT_LOG_SINK).unwrap();
+}
+
+fn update_filter(f: impl FnOnce(&mut LogFilter)) {
+    let mut filter = FILTER.lock();
+    f(&mut filter);
+    log::set_max_level(filter.max_level());
+}
+
+// the level of modules without a level of their own
+pub fn set_log_level(level: LevelFilter) {
+    update_filter(|filter| filter.set_default_level(level));
+}
+
+pub fn set_module_level(target: &str, level: LevelFilter) -> Result<(), FilterError> {
+    let mut result = Ok(());
+    update_filter(|filter| result = filter.set_module_level(target, level));
+    result
+}
+
+// `loglevel=<level>` sets the default level and `log.<module>=<level>` the level of a
+// module; `<module>` may leave out the leading `potatOS::` (`log.pci=trace`)
+pub fn apply_cmdline() {
+    let cmdline = cmdline::cmdline();
+    if let Some(level) = cmdline.get("loglevel") {
+        match level.parse() {
+            Ok(level) => set_log_level(level),
+            Err(_) => warn!("cmdline: invalid loglevel {:?}", level),
+        }
+    }
+    for option in cmdline.with_prefix("log.") {
+        let level = match option.value.map(str::parse) {
+            Some(Ok(level)) => level,
+            _ => {
+                warn!("cmdline: invalid level for log.{}", option.key);
+                continue;
+            }
+        };
+        let mut target = FixedString::<MAX_TARGET_LEN>::new();
+        if option.key != "potatOS" && !option.key.starts_with("potatOS::") {
+            target.push_str("potatOS::");
+        }
+        target.push_str(option.key);
+        if target.is_truncated() {
+            warn!("cmdline: module name too long: log.{}", option.key);
+            continue;
+        }
+        if let Err(e) = set_module_level(target.as_str(), level) {
+            warn!("cmdline: log.{}: {:?}", option.key, e);
+        }
+    }
+}
+
+// replaces the filter with one parsed from `spec`, e.g. "warn,potatOS::xhc=debug"
+pub fn set_log_filter(spec: &str) -> Result<(), FilterError> {
+    let parsed = LogFilter::parse(spec)?;
+    update_filter(|filter| *filter = parsed);
+    Ok(())
+}
+
+pub fn register_log_sink(sink: &'static dyn LogSink) -> Result<(), LogSinkError> {
+    let mut sinks = SINKS.lock();
+    if sinks.iter().flatten().any(|s| s.name() == sink.name()) {
+        return Err(LogSinkError::AlreadyRegistered);
+    }
+    let slot = sinks.iter_mut().find(|s| s.is_none()).ok_or(LogSinkError::TooManySinks)?;
+    *slot = Some(sink);
+    Ok(())
+}
+
+pub fn unregister_log_sink(name: &str) -> Result<(), LogSinkError> {
+    let mut sinks = SINKS.lock();
+    let slot = sinks
+        .iter_mut()
+        .find(|s| matches!(s, Some(sink) if sink.name() == name))
+        .ok_or(LogSinkError::NotRegistered)?;
+    *slot = None;
+    Ok(())
+}
+
+// the kept records, oldest first (for dmesg)
+pub fn records() -> Vec<LogRecord> {
+    RING.lock().iter().copied().collect()
+}
+
+// records that no longer fit into the ring
+pub fn dropped_records() -> u64 {
+    RING.lock().dropped()
+}
+
 #[macro_export]
 macro_rules! error {
     ($($args:tt)*) => (
-        use $crate::logger::LogLevel;
-        $crate::logger::_log(LogLevel::Error, file!(), line!(), format_args!($($args)*));
+        $crate::logger::_log($crate::logger::Level::Error, module_path!(), file!(), line!(), format_args!($($args)*))
     );
 }
 
 #[macro_export]
 macro_rules! warn {
     ($($args:tt)*) => (
-        use $crate::logger::LogLevel;
-        $crate::logger::_log(LogLevel::Warn, file!(), line!(), format_args!($($args)*));
+        $crate::logger::_log($crate::logger::Level::Warn, module_path!(), file!(), line!(), format_args!($($args)*))
     );
 }
 
 #[macro_export]
 macro_rules! info {
     ($($args:tt)*) => (
-        use $crate::logger::LogLevel;
-        $crate::logger::_log(LogLevel::Info, file!(), line!(), format_args!($($args)*));
+        $crate::logger::_log($crate::logger::Level::Info, module_path!(), file!(), line!(), format_args!($($args)*))
     );
 }
 
 #[macro_export]
 macro_rules! debug {
     ($($args:tt)*) => (
-        use $crate::logger::LogLevel;
-        $crate::logger::_log(LogLevel::Debug, file!(), line!(), format_args!($($args)*));
+        $crate::logger::_log($crate::logger::Level::Debug, module_path!(), file!(), line!(), format_args!($($args)*))
     );
 }
 
 #[macro_export]
 macro_rules! trace {
     ($($args:tt)*) => (
-        use $crate::logger::LogLevel;
-        $crate::logger::_log(LogLevel::Trace, file!(), line!(), format_args!($($args)*));
+        $crate::logger::_log($crate::logger::Level::Trace, module_path!(), file!(), line!(), format_args!($($args)*))
     );
 }
 
-pub fn _log(level: LogLevel, file: &str, line: u32, args: Arguments) {
-    let global_level = *LOG_LEVEL.lock().deref() as u8;
-    if level as u8 > global_level {
-        return
+pub fn _log(level: Level, target: &'static str, file: &'static str, line: u32, args: fmt::Arguments) {
+    if level > log::max_level() {
+        return;
     }
-
-    let level = match level {
-        LogLevel::Error => {"ERROR"},
-        LogLevel::Warn => {"WARN"},
-        LogLevel::Info => {"INFO"},
-        LogLevel::Debug => {"DEBUG"},
-        LogLevel::Trace => {"TRACE"},
-    };
-    use crate::kprintln;
-    kprintln!("[{} ({}:{})] {}", level, file, line, args);
+    log::logger().log(
+        &Record::builder()
+            .level(level)
+            .target(target)
+            .module_path_static(Some(target))
+            .file_static(Some(file))
+            .line(Some(line))
+            .args(args)
+            .build(),
+    );
 }
-
-
-
-
-
-
-
-
-
-
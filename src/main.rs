@@ -5,58 +5,158 @@
 use potatOS::graphics::{
     FrameBuffer, 
     PixelColor, 
-    PixelWriter,
     init_global_writer,
 };
-use potatOS::{kprintln, debug, trace};
-use potatOS::mouse::{mouse_observer, init_mouse};
+use potatOS::{kprintln, debug, trace, warn};
+use potatOS::mouse::{MOUSE, init_mouse};
+use potatOS::message::{pop_message, Message};
 use potatOS::pci::{
     self,
     scan_all_bus,
     Device,
 };
 use potatOS::interrupts::idt::init_idt;
-use potatOS::xhc::{XHC_CONTROLLER, init_xhc};
-use potatOS::logger::set_log_level;
+use potatOS::xhc::{self, init_xhc};
+use potatOS::logger::{self, init_logger};
+use potatOS::memory_manager::{init_memory_manager, reclaim_boot_services_memory};
+use potatOS::paging::{self, init_paging};
+use potatOS::gdt::init_gdt;
+use potatOS::apic::init_local_apic;
+use potatOS::ioapic::init_ioapic;
+use potatOS::timer::{self, init_timer};
+use potatOS::acpi;
+use potatOS::power;
+use potatOS::cmdline::{self, init_cmdline};
+use potatOS::console::init_console;
+use potatOS::layer::init_layers;
+use potatOS::window;
+use potatOS::output;
+use potatOS::serial::{self, init_serial, init_serial_input};
 use mikanos_usb as usb;
-use core::arch::asm;
+use boot_info::BootInfo;
+use core::arch::{asm, global_asm};
 
+// UEFI のスタック (BOOT_SERVICES_DATA) から, カーネルイメージ内のスタックに切り替える.
+// The page below the stack is unmapped in `init` so that an overflow faults
+// (and ends up in the double fault handler on its IST stack) instead of corrupting .bss.
+global_asm!(r#"
+.section .bss.kernel_main_stack, "aw", @nobits
+.align 4096
+kernel_main_stack_guard:
+    .space 4096
+kernel_main_stack:
+    .space 1024 * 1024
+kernel_main_stack_top:
 
-fn init(fb: FrameBuffer) {
-    set_log_level(LogLevel::Error);
-    init_global_writer(fb);
-    init_mouse();
+.section .text
+.global kernel_main
+kernel_main:
+    lea rsp, [rip + kernel_main_stack_top]
+    call kernel_main_new_stack
+1:
+    hlt
+    jmp 1b
+"#);
+
+extern "C" {
+    static kernel_main_stack_guard: u8;
+}
+
+fn init(boot_info: &'static BootInfo, fb: FrameBuffer) {
+    init_logger();
+    init_cmdline(boot_info);
+    logger::apply_cmdline();
+    init_serial();
+    init_memory_manager(&boot_info.memory_map);
+    init_paging(boot_info);
+    // the heap is usable from here
+    let fb = fb.map_write_combining().expect("failed to map the frame buffer");
+    let screen = init_global_writer(fb, &PixelColor::WHITE);
+    init_layers(screen, &PixelColor::WHITE);
+    init_console();
+    output::apply_cmdline();
+    if let Some(init) = cmdline::get("init") {
+        warn!("init={}: there are no user programs yet", init);
+    }
+    init_gdt();
     init_idt();
+    if let Err(e) = acpi::init_acpi(boot_info) {
+        warn!("acpi: {:?}", e);
+    }
+    power::init_reset_register();
+    if let Some(pm_timer) = acpi::fadt().and_then(|fadt| fadt.pm_timer) {
+        timer::set_pm_timer(pm_timer.port, pm_timer.is_32bit);
+    }
+    init_local_apic();
+    init_ioapic();
+    if let Err(e) = init_serial_input() {
+        warn!("serial input is polled: {:?}", e);
+    }
+    init_timer();
+    let guard_page = unsafe { &kernel_main_stack_guard as *const u8 as u64 };
+    paging::unmap_page(guard_page).expect("failed to unmap the stack guard page");
+    // firmware page tables, GDT and stack are no longer in use
+    reclaim_boot_services_memory(&boot_info.memory_map);
+    init_mouse();
     scan_all_bus().unwrap();
     init_xhc();
     kprintln!("Welcome to potatOS!");
     trace!("finished initialization");
 }
 
+// called from `kernel_main` after switching stacks
 #[no_mangle]
-pub extern "C" fn kernel_main(frame_buffer: FrameBuffer) -> ! { // TODO: 引数を参照にする. 8 byte を超える値は参照渡しにすべき.
-    for x in 0..frame_buffer.h() {
-        for y in 0..frame_buffer.v() {
-            frame_buffer.draw_pixel(x, y, &PixelColor::new(255, 255, 255))
-        }
+pub extern "C" fn kernel_main_new_stack(boot_info: &'static BootInfo) -> ! {
+    if !boot_info.is_compatible() {
+        // the loader and the kernel disagree on the BootInfo layout; nothing can be trusted
+        loop { x86_64::instructions::hlt(); }
     }
 
+    let frame_buffer = FrameBuffer::from_boot_info(&boot_info.frame_buffer);
+
     // init 
-    init(frame_buffer);
+    init(boot_info, frame_buffer);
     // end init
 
 
-    // loop { XHC_CONTROLLER.lock().as_mut().unwrap().process_event().unwrap(); }
-
     // breakpoint test
     // x86_64::instructions::interrupts::int3();
 
+    use x86_64::instructions::interrupts;
     loop {
-        x86_64::instructions::hlt();
+        serial::poll_serial_input();
+        // checking the queue and halting must be atomic, or a message posted in between
+        // would wait for the next interrupt
+        interrupts::disable();
+        match pop_message() {
+            Some(message) => {
+                interrupts::enable();
+                handle_message(message);
+            }
+            None => interrupts::enable_and_hlt(),
+        }
     }
 
 }
 
+fn handle_message(message: Message) {
+    match message {
+        Message::XhciInterrupt => xhc::process_events(),
+        Message::MouseMove { buttons, dx, dy } => {
+            let (x, y) = {
+                let mut mouse = MOUSE.lock();
+                mouse.move_relative(dx as isize, dy as isize);
+                mouse.pos()
+            };
+            window::handle_mouse(x, y, buttons);
+        }
+        Message::KeyPush { modifier, keycode } => window::handle_key(modifier, keycode),
+        Message::TimerTimeout { timeout, value } => {
+            trace!("timer timeout: {} {}", timeout, value);
+        }
+    }
+}
+
 #[allow(unused)]
 unsafe fn divide_by_zero() {
     // asm 
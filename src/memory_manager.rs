@@ -0,0 +1,328 @@
+//! 物理メモリ (フレーム) の管理
+//!
+//! A bitmap allocator seeded from the memory map handed over by potato_loader.
+//! One bit per 4 KiB frame; a set bit means the frame is in use. A second bitmap records
+//! which frames may be handed out at all (usable RAM above 1 MiB), so that `free` cannot
+//! turn MMIO, ACPI tables or low memory into free frames.
+
+use boot_info::{MemoryMap, MemoryType, UEFI_PAGE_SIZE};
+use crate::sync::SpinMutex;
+use crate::utils::bit_field::BitField;
+use crate::{debug, trace};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+pub const FRAME_SIZE: usize = 4096;
+pub const MAX_PHYSICAL_MEMORY: usize = 32 * 1024 * 1024 * 1024; // 32 GiB
+const FRAME_COUNT: usize = MAX_PHYSICAL_MEMORY / FRAME_SIZE;
+const BITS_PER_MAP_LINE: usize = 64;
+const MAP_LINE_COUNT: usize = FRAME_COUNT / BITS_PER_MAP_LINE;
+
+// frames below 1 MiB are never handed out (real mode structures, AP trampoline etc.)
+const LOW_MEMORY_END: usize = 0x10_0000;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum FrameAllocError {
+    OutOfMemory,
+    InvalidRange,
+}
+type Result<T> = core::result::Result<T, FrameAllocError>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FrameId(usize);
+
+impl FrameId {
+    pub const fn new(id: usize) -> Self {
+        Self(id)
+    }
+
+    pub fn containing_address(addr: u64) -> Self {
+        Self(addr as usize / FRAME_SIZE)
+    }
+
+    pub fn id(&self) -> usize {
+        self.0
+    }
+
+    pub fn addr(&self) -> u64 {
+        (self.0 * FRAME_SIZE) as u64
+    }
+
+    pub fn add(&self, n: usize) -> Self {
+        Self(self.0 + n)
+    }
+}
+
+// constraints for contiguous allocations (mainly for DMA buffers)
+#[derive(Debug, Clone, Copy)]
+pub struct FrameConstraint {
+    // alignment in frames (must be a power of two)
+    pub align: usize,
+    // the whole allocation must end at or below this physical address
+    pub max_addr: Option<u64>,
+}
+
+impl FrameConstraint {
+    pub const NONE: Self = Self { align: 1, max_addr: None };
+    pub const BELOW_4G: Self = Self { align: 1, max_addr: Some(0x1_0000_0000) };
+
+    #[must_use]
+    pub fn aligned(mut self, align: usize) -> Self {
+        assert!(align.is_power_of_two());
+        self.align = align;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryStats {
+    pub total_frames: usize,
+    pub free_frames: usize,
+}
+
+impl MemoryStats {
+    pub fn used_frames(&self) -> usize {
+        self.total_frames - self.free_frames
+    }
+    pub fn total_bytes(&self) -> usize {
+        self.total_frames * FRAME_SIZE
+    }
+    pub fn free_bytes(&self) -> usize {
+        self.free_frames * FRAME_SIZE
+    }
+}
+
+pub struct BitmapMemoryManager {
+    alloc_map: [u64; MAP_LINE_COUNT],
+    // a set bit: the frame is usable RAM the allocator manages
+    usable_map: [u64; MAP_LINE_COUNT],
+    range_begin: FrameId,
+    range_end: FrameId,
+    // frames that are backed by usable RAM (whether allocated or not)
+    total_frames: usize,
+    free_frames: usize,
+}
+
+impl BitmapMemoryManager {
+    // nothing can be allocated until `reset` and `add_free_range` are called
+    pub const fn new() -> Self {
+        Self {
+            alloc_map: [0; MAP_LINE_COUNT],
+            usable_map: [0; MAP_LINE_COUNT],
+            range_begin: FrameId(0),
+            range_end: FrameId(0),
+            total_frames: 0,
+            free_frames: 0,
+        }
+    }
+
+    // marks every frame as in use
+    fn reset(&mut self) {
+        self.alloc_map.fill(!0);
+        self.usable_map.fill(0);
+        self.set_memory_range(FrameId(0), FrameId(FRAME_COUNT));
+        self.total_frames = 0;
+        self.free_frames = 0;
+    }
+
+    pub fn allocate(&mut self, num_frames: usize) -> Result<FrameId> {
+        self.allocate_with(num_frames, FrameConstraint::NONE)
+    }
+
+    pub fn allocate_with(&mut self, num_frames: usize, constraint: FrameConstraint) -> Result<FrameId> {
+        if num_frames == 0 {
+            return Err(FrameAllocError::InvalidRange);
+        }
+        let end = match constraint.max_addr {
+            Some(max_addr) => self.range_end.min(FrameId::containing_address(max_addr)),
+            None => self.range_end,
+        };
+
+        let mut start = align_up(self.range_begin.id(), constraint.align);
+        loop {
+            if start + num_frames > end.id() {
+                return Err(FrameAllocError::OutOfMemory);
+            }
+            match (0..num_frames).find(|&i| self.get_bit(FrameId(start + i))) {
+                // [start, start + i] contains an allocated frame; retry after it
+                Some(i) => start = align_up(start + i + 1, constraint.align),
+                None => {
+                    self.mark_allocated(FrameId(start), num_frames);
+                    return Ok(FrameId(start));
+                }
+            }
+        }
+    }
+
+    // fails without freeing anything if any of the frames is not usable RAM
+    pub fn free(&mut self, start: FrameId, num_frames: usize) -> Result<()> {
+        if start < self.range_begin || start.add(num_frames) > self.range_end {
+            return Err(FrameAllocError::InvalidRange);
+        }
+        if (0..num_frames).any(|i| !self.is_usable(start.add(i))) {
+            return Err(FrameAllocError::InvalidRange);
+        }
+        for i in 0..num_frames {
+            let frame = start.add(i);
+            if self.get_bit(frame) {
+                self.set_bit(frame, false);
+                self.free_frames += 1;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn mark_allocated(&mut self, start: FrameId, num_frames: usize) {
+        for i in 0..num_frames {
+            let frame = start.add(i);
+            if frame >= self.range_end {
+                break;
+            }
+            if !self.get_bit(frame) {
+                self.set_bit(frame, true);
+                self.free_frames -= 1;
+            }
+        }
+    }
+
+    pub fn stats(&self) -> MemoryStats {
+        MemoryStats {
+            total_frames: self.total_frames,
+            free_frames: self.free_frames,
+        }
+    }
+
+    // registers usable RAM as free frames
+    fn add_free_range(&mut self, start: FrameId, num_frames: usize) {
+        for i in 0..num_frames {
+            let frame = start.add(i);
+            if frame >= self.range_end {
+                break;
+            }
+            if frame.addr() < LOW_MEMORY_END as u64 {
+                self.total_frames += 1;
+                continue;
+            }
+            // registered frames may be allocated by now
+            if !self.is_usable(frame) {
+                self.set_usable(frame);
+                self.set_bit(frame, false);
+                self.total_frames += 1;
+                self.free_frames += 1;
+            }
+        }
+    }
+
+    fn set_memory_range(&mut self, range_begin: FrameId, range_end: FrameId) {
+        self.range_begin = range_begin;
+        self.range_end = range_end.min(FrameId(FRAME_COUNT));
+    }
+
+    fn is_usable(&self, frame: FrameId) -> bool {
+        self.usable_map[frame.id() / BITS_PER_MAP_LINE].get_bit(frame.id() % BITS_PER_MAP_LINE)
+    }
+
+    fn set_usable(&mut self, frame: FrameId) {
+        let _ = self.usable_map[frame.id() / BITS_PER_MAP_LINE].set_bit(frame.id() % BITS_PER_MAP_LINE, true);
+    }
+
+    fn get_bit(&self, frame: FrameId) -> bool {
+        let line = frame.id() / BITS_PER_MAP_LINE;
+        let bit = frame.id() % BITS_PER_MAP_LINE;
+        self.alloc_map[line].get_bit(bit)
+    }
+
+    fn set_bit(&mut self, frame: FrameId, allocated: bool) {
+        let line = frame.id() / BITS_PER_MAP_LINE;
+        let bit = frame.id() % BITS_PER_MAP_LINE;
+        let _ = self.alloc_map[line].set_bit(bit, allocated);
+    }
+}
+
+fn align_up(value: usize, align: usize) -> usize {
+    (value + align - 1) & !(align - 1)
+}
+
+// UEFI の memory type のうち, カーネルが自由に使ってよいもの
+// LOADER_CODE/LOADER_DATA hold the kernel image and the BootInfo itself, so they stay in use.
+// BOOT_SERVICES_CODE/DATA still hold the firmware page tables and the boot stack, so
+// they are only returned by `reclaim_boot_services_memory` once the kernel stops using them.
+fn is_available(ty: MemoryType) -> bool {
+    ty == MemoryType::CONVENTIONAL
+}
+
+fn is_boot_services(ty: MemoryType) -> bool {
+    ty == MemoryType::BOOT_SERVICES_CODE || ty == MemoryType::BOOT_SERVICES_DATA
+}
+
+pub static MEMORY_MANAGER: SpinMutex<BitmapMemoryManager> = SpinMutex::new(BitmapMemoryManager::new());
+
+pub fn init_memory_manager(memory_map: &MemoryMap) {
+    let mut manager = MEMORY_MANAGER.lock();
+    manager.reset();
+    let mut available_end = 0;
+    for desc in memory_map.iter() {
+        trace!(
+            "mmap: {:?} {:#x}-{:#x}",
+            desc.ty, desc.phys_start, desc.phys_end()
+        );
+        if is_available(desc.ty) {
+            manager.add_free_range(
+                FrameId::containing_address(desc.phys_start),
+                (desc.page_count * UEFI_PAGE_SIZE) as usize / FRAME_SIZE,
+            );
+        }
+        if is_available(desc.ty) || is_boot_services(desc.ty) {
+            available_end = available_end.max(desc.phys_end());
+        }
+    }
+    let range_end = FrameId::containing_address(available_end);
+    manager.set_memory_range(FrameId::containing_address(LOW_MEMORY_END as u64), range_end);
+    let stats = manager.stats();
+    debug!(
+        "memory manager: {} MiB free / {} MiB",
+        stats.free_bytes() / 1024 / 1024,
+        stats.total_bytes() / 1024 / 1024
+    );
+}
+
+static BOOT_SERVICES_RECLAIMED: AtomicBool = AtomicBool::new(false);
+
+// BOOT_SERVICES_CODE/DATA を空き領域として登録する.
+// Must only be called after the kernel runs on its own page tables and stack. Later calls
+// do nothing, since the frames may be in use again by then.
+pub fn reclaim_boot_services_memory(memory_map: &MemoryMap) {
+    if BOOT_SERVICES_RECLAIMED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let mut manager = MEMORY_MANAGER.lock();
+    for desc in memory_map.iter().filter(|desc| is_boot_services(desc.ty)) {
+        manager.add_free_range(
+            FrameId::containing_address(desc.phys_start),
+            (desc.page_count * UEFI_PAGE_SIZE) as usize / FRAME_SIZE,
+        );
+    }
+}
+
+pub fn allocate_frames(num_frames: usize) -> Result<FrameId> {
+    MEMORY_MANAGER.lock().allocate(num_frames)
+}
+
+pub fn allocate_frame() -> Result<FrameId> {
+    allocate_frames(1)
+}
+
+pub fn allocate_frames_with(num_frames: usize, constraint: FrameConstraint) -> Result<FrameId> {
+    MEMORY_MANAGER.lock().allocate_with(num_frames, constraint)
+}
+
+pub fn free_frames(start: FrameId, num_frames: usize) -> Result<()> {
+    MEMORY_MANAGER.lock().free(start, num_frames)
+}
+
+pub fn free_frame(frame: FrameId) -> Result<()> {
+    free_frames(frame, 1)
+}
+
+pub fn memory_stats() -> MemoryStats {
+    MEMORY_MANAGER.lock().stats()
+}
@@ -0,0 +1,136 @@
+//! 割り込みハンドラからメインループへのメッセージキュー
+//!
+//! Interrupt handlers must not take locks that normal code also takes, so they only push a
+//! `Message` here and return; the main loop in `kernel_main` pops and handles them with
+//! interrupts enabled. The queue is a bounded lock-free MPSC ring (Vyukov style): any
+//! context may push, only the main loop pops.
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+const MESSAGE_QUEUE_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Message {
+    // the xHC has events on its primary event ring
+    XhciInterrupt,
+    TimerTimeout { timeout: u64, value: i32 },
+    KeyPush { modifier: u8, keycode: u8 },
+    // `buttons` as in `MouseButton`, also reported when only they changed
+    MouseMove { buttons: u8, dx: i8, dy: i8 },
+}
+
+struct Slot<T> {
+    // sequence number of the slot minus its index, so that all-zero is the initial state.
+    // seq == pos: free for the producer at `pos`, seq == pos + 1: filled for the consumer at `pos`
+    stamp: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+impl<T> Slot<T> {
+    const fn new() -> Self {
+        Self {
+            stamp: AtomicUsize::new(0),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+}
+
+pub struct MessageQueue<T, const N: usize> {
+    slots: [Slot<T>; N],
+    // next position to push to
+    tail: AtomicUsize,
+    // next position to pop from; only the consumer writes this
+    head: AtomicUsize,
+    dropped: AtomicUsize,
+}
+
+// producers hand values over to the consumer, which may run in another context
+unsafe impl<T: Send, const N: usize> Sync for MessageQueue<T, N> {}
+
+impl<T, const N: usize> MessageQueue<T, N> {
+    pub const fn new() -> Self {
+        // positions wrap around usize::MAX, which only lines up with the slots for powers of two
+        assert!(N.is_power_of_two());
+        Self {
+            slots: [const { Slot::new() }; N],
+            tail: AtomicUsize::new(0),
+            head: AtomicUsize::new(0),
+            dropped: AtomicUsize::new(0),
+        }
+    }
+
+    fn seq(&self, index: usize) -> usize {
+        self.slots[index].stamp.load(Ordering::Acquire).wrapping_add(index)
+    }
+
+    fn set_seq(&self, index: usize, seq: usize) {
+        self.slots[index].stamp.store(seq.wrapping_sub(index), Ordering::Release);
+    }
+
+    // returns the value back if the queue is full
+    pub fn push(&self, value: T) -> Result<(), T> {
+        let mut pos = self.tail.load(Ordering::Relaxed);
+        loop {
+            let index = pos % N;
+            let diff = self.seq(index).wrapping_sub(pos) as isize;
+            if diff == 0 {
+                match self.tail.compare_exchange_weak(
+                    pos, pos.wrapping_add(1), Ordering::Relaxed, Ordering::Relaxed
+                ) {
+                    Ok(_) => {
+                        unsafe { (*self.slots[index].value.get()).write(value) };
+                        self.set_seq(index, pos.wrapping_add(1));
+                        return Ok(());
+                    }
+                    Err(current) => pos = current,
+                }
+            } else if diff < 0 {
+                // the consumer has not emptied this slot since the last lap
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                return Err(value);
+            } else {
+                // another producer took `pos`
+                pos = self.tail.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    // Must only be called from the single consumer.
+    // A slot whose producer was interrupted between reserving and filling it stops the
+    // consumer until the producer finishes; later messages are not reordered past it.
+    pub fn pop(&self) -> Option<T> {
+        let pos = self.head.load(Ordering::Relaxed);
+        let index = pos % N;
+        if self.seq(index) != pos.wrapping_add(1) {
+            return None;
+        }
+        let value = unsafe { (*self.slots[index].value.get()).assume_init_read() };
+        self.head.store(pos.wrapping_add(1), Ordering::Relaxed);
+        self.set_seq(index, pos.wrapping_add(N));
+        Some(value)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        let pos = self.head.load(Ordering::Relaxed);
+        self.seq(pos % N) != pos.wrapping_add(1)
+    }
+
+    // number of messages lost because the queue was full
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+pub static MESSAGE_QUEUE: MessageQueue<Message, MESSAGE_QUEUE_CAPACITY> = MessageQueue::new();
+
+// safe to call from interrupt handlers. Returns false if the message was dropped.
+pub fn post_message(message: Message) -> bool {
+    MESSAGE_QUEUE.push(message).is_ok()
+}
+
+// main loop only
+pub fn pop_message() -> Option<Message> {
+    MESSAGE_QUEUE.pop()
+}
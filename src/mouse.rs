@@ -1,9 +1,8 @@
 
-use crate::graphics::{
-    WRITER,
-    PixelColor,
-};
-use crate::sync::SpinMutex;
+use crate::graphics::{PixelColor, PixelWriter};
+use crate::layer::{self, LayerId, MOUSE_Z};
+use crate::sync::IrqSpinMutex;
+use crate::message::{post_message, Message};
 
 pub const MOUSE_CURSOR_WIDTH: usize = 15;
 pub const MOUSE_CURSOR_HEIGHT: usize = 24;
@@ -43,29 +42,30 @@ pub enum MouseButton {
 
 
 
-pub extern "C" fn mouse_observer(dx: i8, dy: i8) {
-    // kprintln!("mouse_observer({}, {})", dx, dy);
-    let mut mouse = MOUSE.lock();
-    let (dx, dy) = (dx as isize, dy as isize);
-    mouse.move_relative(dx, dy);
+pub extern "C" fn mouse_observer(buttons: u8, dx: i8, dy: i8) {
+    // kprintln!("mouse_observer({}, {}, {})", buttons, dx, dy);
+    post_message(Message::MouseMove { buttons, dx, dy });
 }
 
-pub static MOUSE: SpinMutex<Mouse> = SpinMutex::new(Mouse::new());
+pub static MOUSE: IrqSpinMutex<Mouse> = IrqSpinMutex::new("MOUSE", Mouse::new());
 pub fn init_mouse() {
     let mut mouse = MOUSE.lock();
     mouse.init(200, 300);
-    mouse.draw();
 }
 
+// the pixels around the shape; no color of the shape
+const TRANSPARENT: PixelColor = PixelColor::new(1, 2, 3);
+
 pub struct Mouse {
     x: isize,
     y: isize,
     max_x: isize,
     max_y: isize,
+    layer: Option<LayerId>,
 }
 
 use core::fmt;
-impl fmt::Display for SpinMutex<Mouse> {
+impl fmt::Display for IrqSpinMutex<Mouse> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mouse = self.lock().pos();
         write!(f, "mouse({}, {})", mouse.0, mouse.1)
@@ -74,24 +74,28 @@ impl fmt::Display for SpinMutex<Mouse> {
 
 impl Mouse {
     pub const fn new() -> Self {
-        Self { x: 0, y: 0, max_x: 0, max_y: 0 }
+        Self { x: 0, y: 0, max_x: 0, max_y: 0, layer: None }
     }
 
+    // needs the layers
     pub fn init(&mut self, x: isize, y: isize) {
         (self.x, self.y) = (x, y);
-        let writer = unsafe { WRITER.lock().assume_init() };
-        (self.max_x, self.max_y) = (writer.horizontal_resolution() as isize, writer.vertical_resolution() as isize);
+        self.layer = layer::with_layers(|layers| {
+            let id = layers.new_transparent_layer(MOUSE_CURSOR_WIDTH, MOUSE_CURSOR_HEIGHT, MOUSE_Z, &TRANSPARENT);
+            layers.move_to(id, x, y);
+            layers.draw(id, draw_cursor);
+            let (width, height) = layers.screen_size();
+            (self.max_x, self.max_y) = (width as isize, height as isize);
+            id
+        });
     }
 
     pub fn pos(&self) -> (isize, isize) {
         (self.x, self.y)
     }
 
+    // the cursor is a layer, so whatever it covered comes back by itself
     pub fn move_relative(&mut self, dx: isize, dy: isize) {
-        // 1. if x + self.x < 0 { self.x = 0 }
-        // 2. else if x + self.x > self.max_x { self.x = self.max_x }
-        // 3. else { self.x += x }
-        self.erase();
         // todo: usize でもつなら self.x + dx で負になるか事前に判定
         self.x = match self.x + dx {
             v if v < 0 => { 0 },
@@ -103,44 +107,22 @@ impl Mouse {
             v if v > self.max_y => { self.max_y },
             v => { v },
         };
-        self.draw();
-    }
-
-    // todo: 色を指定できるようにする
-    pub fn erase(&self) {
-        let writer = unsafe { WRITER.lock().assume_init() };
-        for dy  in 0..MOUSE_CURSOR_SHAPE.len() {
-            MOUSE_CURSOR_SHAPE[dy].chars()
-                .enumerate()
-                .for_each(|(dx, c)| {
-                    let x = self.x as usize + dx;
-                    let y = self.y as usize + dy;
-                    match c {
-                        '@' | '.' => writer.draw_pixel(x, y, &PixelColor::WHITE),
-                        ' ' => { /* do nothing */ },
-                        c => panic!("Unexpected cursor shape: {}", c),
-                    }
-            });
+        if let Some(id) = self.layer {
+            layer::move_to(id, self.x, self.y);
         }
-
     }
+}
 
-    pub fn draw(&self) {
-        let writer = unsafe { WRITER.lock().assume_init() };
-        for dy in 0..MOUSE_CURSOR_SHAPE.len() {
-            MOUSE_CURSOR_SHAPE[dy].chars()
-                .enumerate()
-                .for_each(|(dx, c)| {
-                    let x = self.x as usize + dx;
-                    let y = self.y as usize + dy;
-                    match c {
-                        '@' => writer.draw_pixel(x, y, &PixelColor::BLACK),
-                        '.' => writer.draw_pixel(x, y, &PixelColor::WHITE),
-                        ' ' => { /* do nothing */ },
-                        c => panic!("Unexpected cursor shape: {}", c),
-                    }
-            });
+// into the cursor's layer, at its top left corner
+fn draw_cursor(writer: &dyn PixelWriter) {
+    for (dy, row) in MOUSE_CURSOR_SHAPE.iter().enumerate() {
+        for (dx, c) in row.chars().enumerate() {
+            match c {
+                '@' => writer.draw_pixel(dx, dy, &PixelColor::BLACK),
+                '.' => writer.draw_pixel(dx, dy, &PixelColor::WHITE),
+                ' ' => { /* transparent */ },
+                c => panic!("Unexpected cursor shape: {}", c),
+            }
         }
-        
     }
 }
@@ -0,0 +1,70 @@
+//! 出力先
+//!
+//! `kprint!` and the logger write to every registered `OutputSink`: the framebuffer
+//! console and, when there is a UART, COM1. The table is copied out before writing, so a
+//! sink is free to take its own locks and a slow sink does not hold up registration.
+
+use crate::cmdline;
+use crate::sync::IrqSpinMutex;
+use crate::warn;
+use core::fmt;
+
+pub trait OutputSink: Sync {
+    fn name(&self) -> &'static str;
+    fn write_fmt(&self, args: fmt::Arguments);
+}
+
+const MAX_SINKS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputError {
+    TooManySinks,
+    AlreadyRegistered,
+    NotRegistered,
+}
+
+static SINKS: IrqSpinMutex<[Option<&'static dyn OutputSink>; MAX_SINKS]> =
+    IrqSpinMutex::new("SINKS", [None; MAX_SINKS]);
+
+pub fn register_sink(sink: &'static dyn OutputSink) -> Result<(), OutputError> {
+    let mut sinks = SINKS.lock();
+    if sinks.iter().flatten().any(|s| s.name() == sink.name()) {
+        return Err(OutputError::AlreadyRegistered);
+    }
+    let slot = sinks.iter_mut().find(|s| s.is_none()).ok_or(OutputError::TooManySinks)?;
+    *slot = Some(sink);
+    Ok(())
+}
+
+pub fn unregister_sink(name: &str) -> Result<(), OutputError> {
+    let mut sinks = SINKS.lock();
+    let slot = sinks
+        .iter_mut()
+        .find(|s| matches!(s, Some(sink) if sink.name() == name))
+        .ok_or(OutputError::NotRegistered)?;
+    *slot = None;
+    Ok(())
+}
+
+pub fn _print(args: fmt::Arguments) {
+    let sinks = *SINKS.lock();
+    for sink in sinks.iter().flatten() {
+        sink.write_fmt(args);
+    }
+}
+
+// `console=serial` drops the framebuffer console and `console=fb` drops COM1; `both`, the
+// default, keeps every sink
+pub fn apply_cmdline() {
+    let drop = match cmdline::get("console") {
+        None | Some("both") => return,
+        Some("serial") => "console",
+        Some("fb") => "serial",
+        Some(other) => {
+            warn!("cmdline: unknown console {:?}", other);
+            return;
+        }
+    };
+    // the sink may be missing anyway, e.g. COM1 on a machine without a UART
+    let _ = unregister_sink(drop);
+}
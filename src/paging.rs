@@ -0,0 +1,487 @@
+//! 4-level paging
+//!
+//! `init_paging` replaces the identity mapping left behind by UEFI with page tables
+//! owned by the kernel:
+//! - the lower half keeps an identity mapping of physical memory (NX), except for the
+//!   kernel image, which is mapped with 4 KiB pages and per-segment permissions
+//! - all of physical memory is also mapped at `PHYSICAL_MEMORY_OFFSET` (direct map)
+//! - device registers are mapped uncached on demand by `map_mmio`, and the frame buffer
+//!   write-combining by `map_frame_buffer`
+//!
+//! "Physical memory" is what the UEFI memory map describes, minus its MMIO ranges. Holes
+//! in the map and MMIO are not mapped with cacheable pages at all; a device is only
+//! reachable through the mapping its driver asks for.
+
+use boot_info::{BootInfo, MemoryType};
+use crate::asm;
+use crate::memory_manager;
+use crate::sync::SpinMutex;
+use crate::utils::bit_field::BitField;
+use crate::debug;
+use core::ops::{BitAnd, BitOr, BitOrAssign};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+pub type PhysAddr = u64;
+pub type VirtAddr = u64;
+
+pub const PAGE_SIZE: u64 = 0x1000;
+pub const PHYSICAL_MEMORY_OFFSET: VirtAddr = 0xffff_8000_0000_0000;
+
+const ENTRY_COUNT: usize = 512;
+const ADDRESS_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageSize {
+    Size4KiB,
+    Size2MiB,
+    Size1GiB,
+}
+
+impl PageSize {
+    pub const fn bytes(&self) -> u64 {
+        match self {
+            PageSize::Size4KiB => 0x1000,
+            PageSize::Size2MiB => 0x20_0000,
+            PageSize::Size1GiB => 0x4000_0000,
+        }
+    }
+
+    // page table level whose entry maps a page of this size (PML4 == 4)
+    const fn level(&self) -> usize {
+        match self {
+            PageSize::Size4KiB => 1,
+            PageSize::Size2MiB => 2,
+            PageSize::Size1GiB => 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct PageFlags(u64);
+
+impl PageFlags {
+    pub const PRESENT: Self = Self(1 << 0);
+    pub const WRITABLE: Self = Self(1 << 1);
+    pub const USER: Self = Self(1 << 2);
+    pub const WRITE_THROUGH: Self = Self(1 << 3);
+    pub const CACHE_DISABLE: Self = Self(1 << 4);
+    pub const ACCESSED: Self = Self(1 << 5);
+    pub const DIRTY: Self = Self(1 << 6);
+    pub const HUGE_PAGE: Self = Self(1 << 7);
+    pub const GLOBAL: Self = Self(1 << 8);
+    pub const NO_EXECUTE: Self = Self(1 << 63);
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub const fn bits(&self) -> u64 {
+        self.0
+    }
+
+    pub const fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    #[must_use]
+    pub const fn without(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+}
+
+impl BitOr for PageFlags {
+    type Output = Self;
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for PageFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl BitAnd for PageFlags {
+    type Output = Self;
+    fn bitand(self, rhs: Self) -> Self {
+        Self(self.0 & rhs.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(transparent)]
+pub struct PageTableEntry(u64);
+
+impl PageTableEntry {
+    pub const fn unused() -> Self {
+        Self(0)
+    }
+
+    pub fn is_present(&self) -> bool {
+        self.0.get_bit(0)
+    }
+
+    pub fn is_huge(&self) -> bool {
+        self.0.get_bit(7)
+    }
+
+    pub fn addr(&self) -> PhysAddr {
+        self.0 & ADDRESS_MASK
+    }
+
+    pub fn flags(&self) -> PageFlags {
+        PageFlags(self.0 & !ADDRESS_MASK)
+    }
+
+    pub fn set(&mut self, addr: PhysAddr, flags: PageFlags) {
+        self.0 = (addr & ADDRESS_MASK) | flags.bits();
+    }
+
+    pub fn set_flags(&mut self, flags: PageFlags) {
+        self.0 = self.addr() | flags.bits();
+    }
+
+    pub fn clear(&mut self) {
+        self.0 = 0;
+    }
+}
+
+#[repr(C, align(4096))]
+pub struct PageTable {
+    entries: [PageTableEntry; ENTRY_COUNT],
+}
+
+impl PageTable {
+    pub fn zero(&mut self) {
+        self.entries.iter_mut().for_each(|entry| entry.clear());
+    }
+}
+
+fn table_index(virt: VirtAddr, level: usize) -> usize {
+    virt.get_bits((12 + 9 * (level - 1))..(21 + 9 * (level - 1))) as usize
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum MapError {
+    AlreadyMapped,
+    NotMapped,
+    // a huge page already covers the requested range
+    HugePageConflict,
+    Unaligned,
+    FrameAllocationFailed,
+}
+type Result<T> = core::result::Result<T, MapError>;
+
+// ------------------------------------------------------
+// TLB shootdown
+// ------------------------------------------------------
+
+// Called after the local TLB has been flushed for [start, start + size).
+// Only the BSP runs for now; APs will register a hook that sends the IPI.
+pub type TlbShootdownHook = fn(start: VirtAddr, size: u64);
+static TLB_SHOOTDOWN_HOOK: SpinMutex<Option<TlbShootdownHook>> = SpinMutex::new(None);
+
+pub fn set_tlb_shootdown_hook(hook: TlbShootdownHook) {
+    *TLB_SHOOTDOWN_HOOK.lock() = Some(hook);
+}
+
+fn flush_tlb(virt: VirtAddr, size: PageSize) {
+    asm::invlpg(virt);
+    if let Some(hook) = *TLB_SHOOTDOWN_HOOK.lock() {
+        hook(virt, size.bytes());
+    }
+}
+
+// ------------------------------------------------------
+// Page Mapper
+// ------------------------------------------------------
+
+pub struct PageMapper {
+    pml4: PhysAddr,
+    // offset at which physical memory is visible to the kernel (0 while running on the UEFI identity map)
+    phys_offset: u64,
+}
+
+impl PageMapper {
+    pub const fn uninitialized() -> Self {
+        Self { pml4: 0, phys_offset: 0 }
+    }
+
+    pub fn pml4(&self) -> PhysAddr {
+        self.pml4
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    fn table(&self, phys: PhysAddr) -> &mut PageTable {
+        unsafe { &mut *((phys + self.phys_offset) as *mut PageTable) }
+    }
+
+    fn allocate_table(&self) -> Result<PhysAddr> {
+        let frame = memory_manager::allocate_frame()
+            .map_err(|_| MapError::FrameAllocationFailed)?;
+        self.table(frame.addr()).zero();
+        Ok(frame.addr())
+    }
+
+    // returns the table at `level` that contains the entry for `virt`, creating missing tables
+    fn walk_create(&self, virt: VirtAddr, level: usize, flags: PageFlags) -> Result<&mut PageTable> {
+        let mut table = self.table(self.pml4);
+        for current in ((level + 1)..=4).rev() {
+            let entry = &mut table.entries[table_index(virt, current)];
+            if !entry.is_present() {
+                let next = self.allocate_table()?;
+                // permissions are enforced by the leaf entry
+                entry.set(next, PageFlags::PRESENT | PageFlags::WRITABLE | (flags & PageFlags::USER));
+            } else if entry.is_huge() {
+                return Err(MapError::HugePageConflict);
+            } else if flags.contains(PageFlags::USER) && !entry.flags().contains(PageFlags::USER) {
+                entry.set_flags(entry.flags() | PageFlags::USER);
+            }
+            table = self.table(entry.addr());
+        }
+        Ok(table)
+    }
+
+    // returns the leaf entry for `virt` and the size of the page it maps
+    fn walk(&self, virt: VirtAddr) -> Option<(&mut PageTableEntry, PageSize)> {
+        let mut table = self.table(self.pml4);
+        for level in (1..=4).rev() {
+            let entry = &mut table.entries[table_index(virt, level)];
+            if !entry.is_present() {
+                return None;
+            }
+            match level {
+                1 => return Some((entry, PageSize::Size4KiB)),
+                2 if entry.is_huge() => return Some((entry, PageSize::Size2MiB)),
+                3 if entry.is_huge() => return Some((entry, PageSize::Size1GiB)),
+                _ => table = self.table(entry.addr()),
+            }
+        }
+        None
+    }
+
+    pub fn map(&self, virt: VirtAddr, phys: PhysAddr, size: PageSize, flags: PageFlags) -> Result<()> {
+        if virt % size.bytes() != 0 || phys % size.bytes() != 0 {
+            return Err(MapError::Unaligned);
+        }
+        let table = self.walk_create(virt, size.level(), flags)?;
+        let entry = &mut table.entries[table_index(virt, size.level())];
+        if entry.is_present() {
+            return Err(MapError::AlreadyMapped);
+        }
+        let mut flags = flags | PageFlags::PRESENT;
+        if size != PageSize::Size4KiB {
+            flags |= PageFlags::HUGE_PAGE;
+        }
+        entry.set(phys, flags);
+        Ok(())
+    }
+
+    // maps [virt, virt + len) to [phys, phys + len) using the largest pages possible
+    pub fn map_range(&self, virt: VirtAddr, phys: PhysAddr, len: u64, flags: PageFlags, allow_1gib: bool) -> Result<()> {
+        let end = virt + len;
+        let (mut virt, mut phys) = (virt, phys);
+        while virt < end {
+            let size = [PageSize::Size1GiB, PageSize::Size2MiB, PageSize::Size4KiB]
+                .iter()
+                .copied()
+                .filter(|&size| allow_1gib || size != PageSize::Size1GiB)
+                .find(|size| {
+                    virt % size.bytes() == 0 && phys % size.bytes() == 0 && virt + size.bytes() <= end
+                })
+                .unwrap_or(PageSize::Size4KiB);
+            self.map(virt, phys, size, flags)?;
+            virt += size.bytes();
+            phys += size.bytes();
+        }
+        Ok(())
+    }
+
+    pub fn unmap(&self, virt: VirtAddr) -> Result<(PhysAddr, PageSize)> {
+        let (entry, size) = self.walk(virt).ok_or(MapError::NotMapped)?;
+        let phys = entry.addr();
+        entry.clear();
+        flush_tlb(virt & !(size.bytes() - 1), size);
+        Ok((phys, size))
+    }
+
+    pub fn update_flags(&self, virt: VirtAddr, flags: PageFlags) -> Result<()> {
+        let (entry, size) = self.walk(virt).ok_or(MapError::NotMapped)?;
+        let mut flags = flags | PageFlags::PRESENT;
+        if size != PageSize::Size4KiB {
+            flags |= PageFlags::HUGE_PAGE;
+        }
+        entry.set_flags(flags);
+        flush_tlb(virt & !(size.bytes() - 1), size);
+        Ok(())
+    }
+
+    pub fn translate(&self, virt: VirtAddr) -> Option<PhysAddr> {
+        let (entry, size) = self.walk(virt)?;
+        Some(entry.addr() + (virt & (size.bytes() - 1)))
+    }
+}
+
+pub static PAGE_MAPPER: SpinMutex<PageMapper> = SpinMutex::new(PageMapper::uninitialized());
+
+// ------------------------------------------------------
+// Initialization
+// ------------------------------------------------------
+
+const IA32_EFER: u32 = 0xc000_0080;
+const EFER_NXE: usize = 11;
+const CR0_WP: usize = 16;
+const IA32_PAT: u32 = 0x277;
+// PAT entry 1 is write-through after reset; `init_paging` makes it write-combining
+const PAT_ENTRY_1: core::ops::Range<usize> = 8..11;
+const PAT_WRITE_COMBINING: u64 = 0x01;
+// selects PAT entry 1
+const WRITE_COMBINING: PageFlags = PageFlags::WRITE_THROUGH;
+
+fn supports_1gib_pages() -> bool {
+    let cpuid = unsafe { core::arch::x86_64::__cpuid(0x8000_0001) };
+    cpuid.edx.get_bit(26)
+}
+
+// flags for a page of the kernel image, merged from every segment that touches it
+fn kernel_page_flags(boot_info: &BootInfo, page: u64) -> PageFlags {
+    let mut writable = false;
+    let mut executable = false;
+    let mut covered = false;
+    for segment in boot_info.kernel_segments() {
+        let seg_start = segment.addr & !(PAGE_SIZE - 1);
+        let seg_end = segment.addr + segment.mem_size;
+        if page + PAGE_SIZE <= seg_start || seg_end <= page {
+            continue;
+        }
+        covered = true;
+        writable |= segment.is_writable();
+        executable |= segment.is_executable();
+    }
+    if !covered {
+        // padding between segments: plain data
+        return PageFlags::WRITABLE | PageFlags::NO_EXECUTE;
+    }
+    let mut flags = PageFlags::empty();
+    if writable {
+        flags |= PageFlags::WRITABLE;
+    }
+    if !executable {
+        flags |= PageFlags::NO_EXECUTE;
+    }
+    flags
+}
+
+// the memory map's ranges that are memory rather than device registers
+fn memory_ranges(boot_info: &BootInfo) -> impl Iterator<Item = (PhysAddr, PhysAddr)> + '_ {
+    boot_info.memory_map.iter()
+        .filter(|desc| desc.ty != MemoryType::MMIO && desc.ty != MemoryType::MMIO_PORT_SPACE)
+        .map(|desc| (desc.phys_start, desc.phys_end()))
+}
+
+// 新しいページテーブルを構築し, CR3 に設定する.
+// This must run before anything takes addresses in the direct map.
+pub fn init_paging(boot_info: &BootInfo) {
+    let mut mapper = PAGE_MAPPER.lock();
+    // UEFI identity-maps all of memory, so the new tables can be written through physical addresses
+    mapper.phys_offset = 0;
+    mapper.pml4 = mapper.allocate_table().expect("failed to allocate PML4");
+
+    let allow_1gib = supports_1gib_pages();
+    let data = PageFlags::WRITABLE | PageFlags::NO_EXECUTE;
+
+    // identity map, except for the 2 MiB regions that contain the kernel image.
+    // page 0 stays unmapped so that null pointer accesses fault.
+    let kernel_start = boot_info.kernel_start & !(PageSize::Size2MiB.bytes() - 1);
+    let kernel_end = (boot_info.kernel_end + PageSize::Size2MiB.bytes() - 1) & !(PageSize::Size2MiB.bytes() - 1);
+    let mut memory_end = 0;
+    for (start, end) in memory_ranges(boot_info) {
+        memory_end = memory_end.max(end);
+        let identity_start = start.max(PAGE_SIZE);
+        for (start, end) in [(identity_start, end.min(kernel_start)), (identity_start.max(kernel_end), end)].iter() {
+            if start < end {
+                mapper.map_range(*start, *start, end - start, data, false)
+                    .expect("failed to identity map");
+            }
+        }
+        // direct map
+        mapper.map_range(PHYSICAL_MEMORY_OFFSET + start, start, end - start, data, allow_1gib)
+            .expect("failed to create direct map");
+    }
+    let mut page = kernel_start.max(PAGE_SIZE);
+    while page < kernel_end {
+        let flags = if boot_info.kernel_start <= page && page < boot_info.kernel_end {
+            kernel_page_flags(boot_info, page)
+        } else {
+            data
+        };
+        mapper.map(page, page, PageSize::Size4KiB, flags).expect("failed to map kernel image");
+        page += PAGE_SIZE;
+    }
+
+    unsafe {
+        asm::wrmsr(IA32_EFER, *asm::rdmsr(IA32_EFER).set_bit(EFER_NXE, true));
+        // honor read-only pages in ring 0 as well
+        asm::write_cr0(*asm::read_cr0().set_bit(CR0_WP, true));
+        // nothing maps with PAT entry 1 yet; loading CR3 below flushes the TLB
+        asm::wrmsr(IA32_PAT, *asm::rdmsr(IA32_PAT).set_bits(PAT_ENTRY_1, PAT_WRITE_COMBINING));
+        asm::write_cr3(mapper.pml4);
+    }
+    mapper.phys_offset = PHYSICAL_MEMORY_OFFSET;
+    debug!(
+        "paging: pml4 {:#x}, direct map up to {:#x}",
+        mapper.pml4, PHYSICAL_MEMORY_OFFSET + memory_end
+    );
+}
+
+pub fn phys_to_virt(phys: PhysAddr) -> VirtAddr {
+    phys + PHYSICAL_MEMORY_OFFSET
+}
+
+pub fn virt_to_phys(virt: VirtAddr) -> Option<PhysAddr> {
+    PAGE_MAPPER.lock().translate(virt)
+}
+
+// For diagnostics from exception and panic context, which may have interrupted a holder of
+// the page table lock: gives up instead of waiting.
+pub fn try_virt_to_phys(virt: VirtAddr) -> Option<PhysAddr> {
+    PAGE_MAPPER.try_lock().ok()?.translate(virt)
+}
+
+pub fn map_page(virt: VirtAddr, phys: PhysAddr, size: PageSize, flags: PageFlags) -> Result<()> {
+    PAGE_MAPPER.lock().map(virt, phys, size, flags)
+}
+
+pub fn unmap_page(virt: VirtAddr) -> Result<(PhysAddr, PageSize)> {
+    PAGE_MAPPER.lock().unmap(virt)
+}
+
+// ------------------------------------------------------
+// MMIO
+// ------------------------------------------------------
+
+// device registers get their own uncached window, above the direct map
+const MMIO_WINDOW_START: VirtAddr = 0xffff_c000_0000_0000;
+static NEXT_MMIO_ADDR: AtomicU64 = AtomicU64::new(MMIO_WINDOW_START);
+
+// maps [phys, phys + len) uncached and returns the virtual address of `phys`
+pub fn map_mmio(phys: PhysAddr, len: u64) -> Result<VirtAddr> {
+    let uncached = PageFlags::CACHE_DISABLE | PageFlags::WRITE_THROUGH;
+    map_device(phys, len, PageFlags::WRITABLE | PageFlags::NO_EXECUTE | uncached)
+}
+
+// Like `map_mmio`, but write-combining: writes are buffered and may be merged or
+// reordered, which is fine for pixels and much faster than uncached, but not for registers.
+pub fn map_frame_buffer(phys: PhysAddr, len: u64) -> Result<VirtAddr> {
+    map_device(phys, len, PageFlags::WRITABLE | PageFlags::NO_EXECUTE | WRITE_COMBINING)
+}
+
+fn map_device(phys: PhysAddr, len: u64, flags: PageFlags) -> Result<VirtAddr> {
+    let start = phys & !(PAGE_SIZE - 1);
+    let end = (phys + len + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+    let virt = NEXT_MMIO_ADDR.fetch_add(end - start, Ordering::Relaxed);
+    PAGE_MAPPER.lock().map_range(virt, start, end - start, flags, false)?;
+    Ok(virt + (phys - start))
+}
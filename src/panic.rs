@@ -0,0 +1,169 @@
+//! パニック
+//!
+//! `handle_panic` disables interrupts, sends the message, location and a backtrace to
+//! COM1 and paints them on a crash screen, then runs the configured `PanicAction`. It
+//! writes to the serial port and to video memory directly, without the locks behind
+//! `WRITER`, because the panicking code may hold any of them.
+
+use crate::backtrace::Backtrace;
+use crate::console::{CONSOLE, CONSOLE_FONT};
+use crate::layer::LAYERS;
+use crate::window::WINDOWS;
+use crate::graphics::{self, Font, PixelColor, PixelWriter, Vector2D, WRITER};
+use crate::power;
+use crate::serial::{SerialPort, COM1};
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+use x86_64::instructions::interrupts;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PanicAction {
+    Halt,
+    // through the ACPI reset register if there is one
+    Reboot,
+    // with the isa-debug-exit device; QEMU exits with `(code << 1) | 1`
+    QemuExit(u8),
+}
+
+impl PanicAction {
+    fn to_raw(self) -> u16 {
+        match self {
+            PanicAction::Halt => 0,
+            PanicAction::Reboot => 1,
+            PanicAction::QemuExit(code) => 0x100 | code as u16,
+        }
+    }
+
+    fn from_raw(raw: u16) -> Self {
+        match raw {
+            1 => PanicAction::Reboot,
+            raw if raw & 0x100 != 0 => PanicAction::QemuExit(raw as u8),
+            _ => PanicAction::Halt,
+        }
+    }
+}
+
+static PANIC_ACTION: AtomicU16 = AtomicU16::new(0);
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+pub fn set_panic_action(action: PanicAction) {
+    PANIC_ACTION.store(action.to_raw(), Ordering::Relaxed);
+}
+
+pub fn panic_action() -> PanicAction {
+    PanicAction::from_raw(PANIC_ACTION.load(Ordering::Relaxed))
+}
+
+const CRASH_SCREEN_BG: PixelColor = PixelColor::new(0x80, 0, 0);
+const CRASH_SCREEN_FG: PixelColor = PixelColor::WHITE;
+const CRASH_SCREEN_MARGIN: usize = 16;
+
+// text on the crash screen; output beyond the bottom edge is dropped
+struct CrashScreen<'a> {
+    writer: &'a dyn PixelWriter,
+    column: usize,
+    row: usize,
+    columns: usize,
+    rows: usize,
+}
+
+impl<'a> CrashScreen<'a> {
+    fn new(writer: &'a dyn PixelWriter) -> Self {
+        let (width, height) = writer.resolution();
+        writer.fill_rect(Vector2D::new(0, 0), Vector2D::new(width, height), &CRASH_SCREEN_BG);
+        let (char_width, char_height) = CONSOLE_FONT.char_size();
+        Self {
+            writer,
+            column: 0,
+            row: 0,
+            columns: width.saturating_sub(2 * CRASH_SCREEN_MARGIN) / char_width,
+            rows: height.saturating_sub(2 * CRASH_SCREEN_MARGIN) / char_height,
+        }
+    }
+
+    fn new_line(&mut self) {
+        self.column = 0;
+        self.row += 1;
+    }
+
+    fn put_char(&mut self, c: char) {
+        if self.column == self.columns {
+            self.new_line();
+        }
+        if self.row >= self.rows {
+            return;
+        }
+        let (char_width, char_height) = CONSOLE_FONT.char_size();
+        let x = CRASH_SCREEN_MARGIN + self.column * char_width;
+        let y = CRASH_SCREEN_MARGIN + self.row * char_height;
+        CONSOLE_FONT.write_ascii(self.writer, x, y, c, &CRASH_SCREEN_FG, &CRASH_SCREEN_BG);
+        self.column += 1;
+    }
+}
+
+impl fmt::Write for CrashScreen<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            match c {
+                '\n' => self.new_line(),
+                c => self.put_char(c),
+            }
+        }
+        Ok(())
+    }
+}
+
+struct PanicReport<'a> {
+    info: &'a PanicInfo<'a>,
+    backtrace: &'a Backtrace,
+}
+
+impl fmt::Display for PanicReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "KERNEL PANIC")?;
+        writeln!(f, "{}", self.info.message())?;
+        if let Some(location) = self.info.location() {
+            writeln!(f, "at {}", location)?;
+        }
+        writeln!(f, "Backtrace:")?;
+        write!(f, "{}", self.backtrace)
+    }
+}
+
+fn draw_crash_screen(report: &PanicReport) {
+    graphics::with_direct_writer(|writer| {
+        let _ = write!(CrashScreen::new(writer), "{}", report);
+    });
+}
+
+pub fn handle_panic(info: &PanicInfo) -> ! {
+    interrupts::disable();
+    let mut serial = SerialPort::new(COM1);
+    if PANICKING.swap(true, Ordering::SeqCst) {
+        // the serial port is the only output simple enough to trust now
+        let _ = writeln!(serial, "\npanicked while panicking: {}", info);
+        power::halt();
+    }
+
+    let backtrace = Backtrace::capture();
+    let report = PanicReport { info, backtrace: &backtrace };
+    let _ = write!(serial, "\n{}", report);
+    for (name, owner) in [
+        (CONSOLE.name(), CONSOLE.owner()),
+        (WINDOWS.name(), WINDOWS.owner()),
+        (LAYERS.name(), LAYERS.owner()),
+        (WRITER.name(), WRITER.owner()),
+    ] {
+        if let Some(owner) = owner {
+            let _ = writeln!(serial, "{} was locked at {}", name, owner);
+        }
+    }
+    draw_crash_screen(&report);
+
+    match panic_action() {
+        PanicAction::Halt => power::halt(),
+        PanicAction::Reboot => power::reboot(),
+        PanicAction::QemuExit(code) => power::qemu_exit(code),
+    }
+}
@@ -1,5 +1,4 @@
 use core::arch::asm;
-use crate::interrupts::idt::InterruptVector;
 
 type Result<T> = core::result::Result<T, ()>;
 
@@ -17,14 +16,7 @@ pub struct Config {
 use crate::utils::bit_field::BitField;
 impl Config {
     pub fn make_address(&self, reg_addr: u8) -> u32 {
-        let mut value = 0;
-        value = *value.set_bit(31, true)
-            .set_bits(24..31, 0)
-            .set_bits(16..24, self.bus as u32)
-            .set_bits(11..16, self.device as u32)
-            .set_bits(8..11, self.function as u32)
-            .set_bits(0..8, reg_addr as u32 & 0xfc);
-        value
+        potato_utils::pci::config_address(self.bus, self.device, self.function, reg_addr)
     }
 
     pub fn read_vendor_id(&self) -> u16 {
@@ -67,12 +59,16 @@ impl Config {
 }
 
 
-use crate::utils::fixed_vec::FixedVec;
-const MAX_DEVICES_NUM: usize = 32;
-pub static mut DEVICES: FixedVec<Device, MAX_DEVICES_NUM> = FixedVec::new();
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use crate::sync::IrqSpinMutex;
+// Set by `scan_all_bus` to a list that is never changed or freed afterwards. Scanning
+// again leaks a new list instead, so slices handed out before stay valid.
+static DEVICES: IrqSpinMutex<&'static [Device]> = IrqSpinMutex::new("PCI_DEVICES", &[]);
 
+// empty until `scan_all_bus`
 pub fn devices() -> &'static [Device] {
-    unsafe { DEVICES.as_slice() }
+    *DEVICES.lock()
 }
 
 pub struct Device {
@@ -156,12 +152,36 @@ impl Device {
         Some(bar)
     }
 
+    // How many bytes a memory BAR decodes, from the bits that stay zero when all ones are
+    // written to it. Memory decoding is off meanwhile, so the device never answers at the
+    // bogus address. None for I/O BARs.
+    pub fn read_bar_size(&self, bar_idx: u8) -> Option<u64> {
+        let bar = self.read_bar(bar_idx)?;
+        if bar & 0b1 != 0 {
+            return None;
+        }
+        let addr = bar_idx*4 + 0x10;
+        let is_64bit = bar & 0b100 != 0;
+        let command = self.read_register(0x04);
+        self.write_register(0x04, command & !0b10);
+        self.write_register(addr, !0);
+        let mut mask = (self.read_register(addr) & !0x0f) as u64 | !0xffff_ffff;
+        self.write_register(addr, bar as u32);
+        if is_64bit {
+            self.write_register(addr+4, !0);
+            mask = (self.read_register(addr+4) as u64) << 32 | mask & 0xffff_ffff;
+            self.write_register(addr+4, (bar >> 32) as u32);
+        }
+        self.write_register(0x04, command);
+        Some(!mask + 1)
+    }
+
     pub fn configure_msi_fixed_destination(
         &self, 
         apic_id: u8, 
         trigger_mode: MSITriggerMode,
         derivary_mode: MSIDeliveryMode,
-        vector: InterruptVector,
+        vector: u8,
         num_vector_exponent: u32,
     ) -> Result<()> {
         let msg_addr: u32 = *0xfee00000.set_bits(12..20, apic_id as u32);
@@ -375,6 +395,13 @@ fn is_single_function_device(header_type: u8) -> bool {
 }
 
 pub fn scan_all_bus() -> Result<()> {
+    let mut devices = Vec::new();
+    scan_host_bridges(&mut devices)?;
+    *DEVICES.lock() = Box::leak(devices.into_boxed_slice());
+    Ok(())
+}
+
+fn scan_host_bridges(devices: &mut Vec<Device>) -> Result<()> {
     // 探索の起点
     let host_bridge = Config {
         bus: 0,
@@ -383,7 +410,7 @@ pub fn scan_all_bus() -> Result<()> {
     };
     // true なら host_bridge が バス0 のホストブリッジで, 
     if is_single_function_device(host_bridge.read_header_type()) {
-        return scan_bus(host_bridge);
+        return scan_bus(devices, host_bridge);
     }
     for function in 1..8 {
         let another_host_bridge = Config {
@@ -395,7 +422,7 @@ pub fn scan_all_bus() -> Result<()> {
         if another_host_bridge.read_vendor_id() == 0xffff {
             continue;
         }
-        scan_bus(Config {
+        scan_bus(devices, Config {
             bus: function,
             device: 0,
             function: 0,
@@ -404,7 +431,7 @@ pub fn scan_all_bus() -> Result<()> {
     Ok(())
 }
 
-fn scan_bus(config: Config) -> Result<()> {
+fn scan_bus(devices: &mut Vec<Device>, config: Config) -> Result<()> {
     for device in 0..32 {
         let dev = Config {
             bus: config.bus,
@@ -414,14 +441,14 @@ fn scan_bus(config: Config) -> Result<()> {
         if dev.read_vendor_id() == 0xffff {
             continue;
         }
-        scan_device(dev)?;
+        scan_device(devices, dev)?;
     }
     Ok(())
 }
 
-fn scan_device(mut config: Config) -> Result<()> {
+fn scan_device(devices: &mut Vec<Device>, mut config: Config) -> Result<()> {
     config.function = 0;
-    scan_function(config)?;
+    scan_function(devices, config)?;
     if is_single_function_device(config.read_header_type()) {
         return Ok(());
     }
@@ -430,19 +457,19 @@ fn scan_device(mut config: Config) -> Result<()> {
         if config.read_vendor_id() == 0xffff {
             continue;
         }
-        scan_function(config)?;
+        scan_function(devices, config)?;
     }
     Ok(())
 }
 
-fn scan_function(config: Config) -> Result<()> {
-    add_device(config)?;
+fn scan_function(devices: &mut Vec<Device>, config: Config) -> Result<()> {
+    devices.push(config.into());
     let (base, sub, _, _) = config.read_class_code();
     // PCI-to-PCI device
     if base == 0x06 && sub == 0x04 {
         let bus_number = config.read_bus_number();
         let secondary_bus = bus_number.get_bits(8..16) as u8;
-        scan_bus(Config {
+        scan_bus(devices, Config {
             bus: secondary_bus,
             device: 0,
             function: 0,
@@ -452,15 +479,6 @@ fn scan_function(config: Config) -> Result<()> {
     }
 }
 
-fn add_device(config: Config) -> Result<()> {
-    let device: Device = config.into();
-    if unsafe { DEVICES.try_push(device).is_ok() } {
-        Ok(())
-    } else {
-        Err(())
-    }
-}
-
 pub struct IOPort {
     port: u16,
 }
@@ -470,6 +488,16 @@ impl IOPort {
         Self { port }
     }
 
+    pub fn read8(&mut self) -> u8 {
+        let al: u8;
+        unsafe { asm!(
+            "in al, dx",
+            out("al") al,
+            in("dx") self.port,
+        ) };
+        al
+    }
+
     pub fn read16(&mut self) -> u16 {
         let eax: u16;
         unsafe { asm!(
@@ -490,6 +518,14 @@ impl IOPort {
         eax
     }
 
+    pub fn write8(&mut self, data: u8) {
+        unsafe { asm!(
+            "out dx, al",
+            in("dx") self.port,
+            in("al") data,
+        ) };
+    }
+
     pub fn write16(&mut self, data: u16) {
         unsafe { asm!(
             "out dx, eax",
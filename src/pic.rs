@@ -0,0 +1,55 @@
+//! 8259 PIC
+//!
+//! Only used to get the legacy PICs out of the way: they are remapped above the
+//! exception vectors (so that a spurious IRQ 7/15 cannot look like an exception) and
+//! fully masked. Interrupts are routed through the IO APIC instead.
+
+use crate::pci::IOPort;
+
+const PIC1_COMMAND: u16 = 0x20;
+const PIC1_DATA: u16 = 0x21;
+const PIC2_COMMAND: u16 = 0xa0;
+const PIC2_DATA: u16 = 0xa1;
+
+pub const PIC1_VECTOR_OFFSET: u8 = 0x20;
+pub const PIC2_VECTOR_OFFSET: u8 = 0x28;
+// IRQ 7 and 15 are raised spuriously even while masked
+pub const PIC1_SPURIOUS_VECTOR: u8 = PIC1_VECTOR_OFFSET + 7;
+pub const PIC2_SPURIOUS_VECTOR: u8 = PIC2_VECTOR_OFFSET + 7;
+
+const ICW1_INIT: u8 = 0x10;
+const ICW1_ICW4: u8 = 0x01;
+const ICW4_8086: u8 = 0x01;
+
+// a write to an unused port takes long enough for the PIC to settle on old hardware
+fn io_wait() {
+    IOPort::new(0x80).write8(0);
+}
+
+pub fn disable_legacy_pics() {
+    let mut pic1_command = IOPort::new(PIC1_COMMAND);
+    let mut pic1_data = IOPort::new(PIC1_DATA);
+    let mut pic2_command = IOPort::new(PIC2_COMMAND);
+    let mut pic2_data = IOPort::new(PIC2_DATA);
+
+    pic1_command.write8(ICW1_INIT | ICW1_ICW4);
+    io_wait();
+    pic2_command.write8(ICW1_INIT | ICW1_ICW4);
+    io_wait();
+    pic1_data.write8(PIC1_VECTOR_OFFSET);
+    io_wait();
+    pic2_data.write8(PIC2_VECTOR_OFFSET);
+    io_wait();
+    pic1_data.write8(1 << 2); // slave on IRQ 2
+    io_wait();
+    pic2_data.write8(2); // cascade identity
+    io_wait();
+    pic1_data.write8(ICW4_8086);
+    io_wait();
+    pic2_data.write8(ICW4_8086);
+    io_wait();
+
+    // mask everything
+    pic1_data.write8(0xff);
+    pic2_data.write8(0xff);
+}
@@ -0,0 +1,78 @@
+//! 電源
+//!
+//! Reboot and QEMU exit. Both are used from the panic path, so they neither allocate nor
+//! take locks.
+
+use crate::acpi::{self, GenericAddress};
+use crate::paging;
+use crate::pci::IOPort;
+use crate::warn;
+use core::sync::atomic::{AtomicU64, Ordering};
+use x86_64::instructions::{hlt, interrupts};
+
+// `-device isa-debug-exit,iobase=0xf4,iosize=0x04`
+pub const QEMU_DEBUG_EXIT_PORT: u16 = 0xf4;
+
+const KEYBOARD_CONTROLLER_COMMAND: u16 = 0x64;
+const KEYBOARD_CONTROLLER_RESET: u8 = 0xfe;
+
+pub fn halt() -> ! {
+    interrupts::disable();
+    loop {
+        hlt();
+    }
+}
+
+// Exits QEMU with status `(code << 1) | 1`. Without the isa-debug-exit device the write
+// is ignored and this halts.
+pub fn qemu_exit(code: u8) -> ! {
+    IOPort::new(QEMU_DEBUG_EXIT_PORT).write32(code as u32);
+    halt()
+}
+
+// where a reset register in memory space is mapped, 0 if there is none. `reboot` runs in
+// the panic path and cannot map it then.
+static RESET_REGISTER: AtomicU64 = AtomicU64::new(0);
+
+// `acpi::init_acpi` の後に呼び出すこと
+pub fn init_reset_register() {
+    let Some(reg) = acpi::fadt().and_then(|fadt| fadt.reset_register) else {
+        return;
+    };
+    if reg.address_space != GenericAddress::SYSTEM_MEMORY || !reg.is_valid() {
+        return;
+    }
+    match paging::map_mmio(reg.address, 1) {
+        Ok(virt) => RESET_REGISTER.store(virt, Ordering::Relaxed),
+        Err(e) => warn!("power: failed to map the reset register: {:?}", e),
+    }
+}
+
+fn write_reset_register(reg: &GenericAddress, value: u8) {
+    match reg.address_space {
+        GenericAddress::SYSTEM_IO => IOPort::new(reg.address as u16).write8(value),
+        GenericAddress::SYSTEM_MEMORY => match RESET_REGISTER.load(Ordering::Relaxed) {
+            0 => {}
+            virt => unsafe { core::ptr::write_volatile(virt as *mut u8, value) },
+        },
+        // PCI configuration space: not supported
+        _ => {}
+    }
+}
+
+// tries the ACPI reset register, then the keyboard controller, then a triple fault
+pub fn reboot() -> ! {
+    interrupts::disable();
+    if let Some(fadt) = acpi::fadt() {
+        if let Some(reg) = fadt.reset_register {
+            write_reset_register(&reg, fadt.reset_value);
+        }
+    }
+    IOPort::new(KEYBOARD_CONTROLLER_COMMAND).write8(KEYBOARD_CONTROLLER_RESET);
+
+    // an exception with an empty IDT escalates to a triple fault, which resets the CPU
+    let empty = crate::interrupts::idt::InterruptDescriptorTable::missing();
+    empty.load();
+    crate::asm::int3();
+    halt()
+}
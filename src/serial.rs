@@ -0,0 +1,230 @@
+//! 16550 UART (COM1)
+//!
+//! Polled output, 8N1 with FIFOs. QEMU connects COM1 to `-serial`, which makes this the
+//! way to get kernel output out of a headless run; `init_serial` registers the port as
+//! an output sink. Received characters are posted to the main loop as
+//! `Message::KeyPush`, from the IRQ 4 handler once `init_serial_input` has routed it, or
+//! from `poll_serial_input` otherwise.
+
+use crate::interrupts::registry::IrqReturn;
+use crate::interrupts::vector;
+use crate::ioapic::{self, IoApicError};
+use crate::keyboard::ascii_to_keycode;
+use crate::message::{post_message, Message};
+use crate::output::{register_sink, OutputSink};
+use crate::pci::IOPort;
+use crate::sync::IrqSpinMutex;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+pub const COM1: u16 = 0x3f8;
+
+// register offsets from the base port
+const DATA: u16 = 0;
+const INTERRUPT_ENABLE: u16 = 1;
+const FIFO_CONTROL: u16 = 2;
+const LINE_CONTROL: u16 = 3;
+const MODEM_CONTROL: u16 = 4;
+const LINE_STATUS: u16 = 5;
+const SCRATCH: u16 = 7;
+// read: interrupt identification
+const INTERRUPT_ID: u16 = 2;
+
+// INTERRUPT_ENABLE
+const RECEIVED_DATA_AVAILABLE: u8 = 1 << 0;
+// INTERRUPT_ID; clear while an interrupt is pending
+const NO_INTERRUPT_PENDING: u8 = 1 << 0;
+// LINE_STATUS
+const DATA_READY: u8 = 1 << 0;
+const TRANSMIT_EMPTY: u8 = 1 << 5;
+
+const LINE_CONTROL_DLAB: u8 = 1 << 7;
+const LINE_CONTROL_8N1: u8 = 0b11;
+
+// the divisor latch divides this
+const UART_CLOCK: u32 = 115200;
+pub const DEFAULT_BAUD_RATE: u32 = 115200;
+
+pub const COM1_IRQ: u8 = 4;
+
+#[derive(Debug, Clone, Copy)]
+pub struct SerialPort {
+    base: u16,
+}
+
+impl SerialPort {
+    pub const fn new(base: u16) -> Self {
+        Self { base }
+    }
+
+    fn port(&self, offset: u16) -> IOPort {
+        IOPort::new(self.base + offset)
+    }
+
+    // False if there is no UART at `base`. `baud_rate` must divide 115200.
+    pub fn init(&self, baud_rate: u32) -> bool {
+        assert!(
+            baud_rate != 0 && UART_CLOCK % baud_rate == 0,
+            "unsupported baud rate {}",
+            baud_rate
+        );
+        // a UART keeps what is written to the scratch register
+        self.port(SCRATCH).write8(0x5a);
+        if self.port(SCRATCH).read8() != 0x5a {
+            return false;
+        }
+        self.port(INTERRUPT_ENABLE).write8(0);
+        let divisor = (UART_CLOCK / baud_rate) as u16;
+        self.port(LINE_CONTROL).write8(LINE_CONTROL_DLAB);
+        self.port(DATA).write8(divisor as u8);
+        self.port(INTERRUPT_ENABLE).write8((divisor >> 8) as u8);
+        self.port(LINE_CONTROL).write8(LINE_CONTROL_8N1);
+        // enable and clear the FIFOs, interrupt at 14 bytes
+        self.port(FIFO_CONTROL).write8(0xc7);
+        // DTR, RTS, OUT2
+        self.port(MODEM_CONTROL).write8(0x0b);
+        true
+    }
+
+    pub fn write_byte(&self, byte: u8) {
+        while self.port(LINE_STATUS).read8() & TRANSMIT_EMPTY == 0 {
+            core::hint::spin_loop();
+        }
+        self.port(DATA).write8(byte);
+    }
+
+    pub fn read_byte(&self) -> Option<u8> {
+        if self.port(LINE_STATUS).read8() & DATA_READY == 0 {
+            return None;
+        }
+        Some(self.port(DATA).read8())
+    }
+
+    pub fn set_receive_interrupt(&self, enabled: bool) {
+        let value = if enabled { RECEIVED_DATA_AVAILABLE } else { 0 };
+        self.port(INTERRUPT_ENABLE).write8(value);
+    }
+
+    pub fn interrupt_pending(&self) -> bool {
+        self.port(INTERRUPT_ID).read8() & NO_INTERRUPT_PENDING == 0
+    }
+}
+
+impl fmt::Write for SerialPort {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            // terminals expect CRLF
+            if byte == b'\n' {
+                self.write_byte(b'\r');
+            }
+            self.write_byte(byte);
+        }
+        Ok(())
+    }
+}
+
+// None if there is no COM1
+pub static SERIAL: IrqSpinMutex<Option<SerialPort>> = IrqSpinMutex::new("SERIAL", None);
+
+// set once the receive interrupt is routed; `poll_serial_input` does nothing after that
+static RECEIVE_INTERRUPT: AtomicBool = AtomicBool::new(false);
+
+pub struct SerialSink;
+
+pub static SERIAL_SINK: SerialSink = SerialSink;
+
+impl OutputSink for SerialSink {
+    fn name(&self) -> &'static str {
+        "serial"
+    }
+
+    fn write_fmt(&self, args: fmt::Arguments) {
+        _serial_print(args);
+    }
+}
+
+// Needs nothing else, so it runs first and catches the output of the rest of the
+// initialization.
+pub fn init_serial() {
+    let port = SerialPort::new(COM1);
+    if port.init(DEFAULT_BAUD_RATE) {
+        *SERIAL.lock() = Some(port);
+        register_sink(&SERIAL_SINK).expect("failed to register the serial port");
+    }
+}
+
+#[derive(Debug)]
+pub enum SerialError {
+    NoSerialPort,
+    NoFreeVector,
+    IoApic(IoApicError),
+}
+
+impl From<IoApicError> for SerialError {
+    fn from(e: IoApicError) -> Self {
+        SerialError::IoApic(e)
+    }
+}
+
+// Routes IRQ 4 to a new vector and turns on the receive interrupt. Needs the IO APIC;
+// on failure input is left to `poll_serial_input`.
+pub fn init_serial_input() -> Result<(), SerialError> {
+    let port = SERIAL.lock().ok_or(SerialError::NoSerialPort)?;
+    let vector = vector::allocate_vector().ok_or(SerialError::NoFreeVector)?;
+    if let Err(e) = ioapic::register_isa_irq(COM1_IRQ, vector, serial_interrupt_handler, core::ptr::null_mut()) {
+        vector::free_vector(vector).unwrap();
+        return Err(e.into());
+    }
+    RECEIVE_INTERRUPT.store(true, Ordering::Relaxed);
+    port.set_receive_interrupt(true);
+    Ok(())
+}
+
+fn post_received(port: &SerialPort) {
+    while let Some(byte) = port.read_byte() {
+        if let Some((modifier, keycode)) = ascii_to_keycode(byte) {
+            post_message(Message::KeyPush { modifier, keycode });
+        }
+    }
+}
+
+fn serial_interrupt_handler(_vector: u8, _context: *mut ()) -> IrqReturn {
+    let port = match *SERIAL.lock() {
+        Some(port) => port,
+        None => return IrqReturn::NotHandled,
+    };
+    // the line may be shared
+    if !port.interrupt_pending() {
+        return IrqReturn::NotHandled;
+    }
+    post_received(&port);
+    IrqReturn::Handled
+}
+
+// Called from the main loop, which wakes up at least on every timer tick.
+pub fn poll_serial_input() {
+    if RECEIVE_INTERRUPT.load(Ordering::Relaxed) {
+        return;
+    }
+    if let Some(port) = *SERIAL.lock() {
+        post_received(&port);
+    }
+}
+
+pub fn _serial_print(args: fmt::Arguments) {
+    use core::fmt::Write;
+    if let Some(mut port) = *SERIAL.lock() {
+        port.write_fmt(args).unwrap();
+    }
+}
+
+#[macro_export]
+macro_rules! serial_print {
+    ($($arg:tt)*) => ($crate::serial::_serial_print(format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! serial_println {
+    () => ($crate::serial_print!("\n"));
+    ($($arg:tt)*) => ($crate::serial_print!("{}\n", format_args!($($arg)*)));
+}
@@ -74,4 +74,210 @@ impl<T> DerefMut for SpinMutexGuard<'_, T> {
     }
 }
 
-pub struct SpinMutexErr<'a>(&'a str);
\ No newline at end of file
+pub struct SpinMutexErr<'a>(&'a str);
+// ------------------------------------------------------
+// IrqSpinMutex
+// ------------------------------------------------------
+// A SpinMutex that disables interrupts while it is held (and restores RFLAGS.IF on unlock),
+// so that an interrupt handler can never spin on a lock the interrupted code holds.
+// Use this for anything that is shared with interrupt context or printed from a panic.
+use core::panic::Location;
+use x86_64::instructions::interrupts;
+
+// with interrupts disabled on a single CPU, spinning this long means nobody will ever unlock
+#[cfg(debug_assertions)]
+const DEADLOCK_SPIN_BUDGET: usize = 1 << 28;
+
+pub struct IrqSpinMutex<T> {
+    lock: AtomicBool,
+    name: &'static str,
+    // call site of the current holder; only valid while `lock` is set
+    owner: UnsafeCell<Option<&'static Location<'static>>>,
+    data: UnsafeCell<T>,
+}
+
+impl<T> IrqSpinMutex<T> {
+    pub const fn new(name: &'static str, data: T) -> Self {
+        Self {
+            lock: AtomicBool::new(false),
+            name,
+            owner: UnsafeCell::new(None),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.lock.load(Ordering::Relaxed)
+    }
+
+    // where the lock was taken, if it is held
+    pub fn owner(&self) -> Option<&'static Location<'static>> {
+        if self.is_locked() {
+            unsafe { *self.owner.get() }
+        } else {
+            None
+        }
+    }
+
+    // Releases the lock regardless of who holds it. Only for the panic path, which has to
+    // print through locks the panicking code may hold; the old holder must never run again.
+    pub unsafe fn force_unlock(&self) {
+        *self.owner.get() = None;
+        self.lock.store(false, Ordering::Release);
+    }
+
+    #[track_caller]
+    pub fn try_lock(&self) -> Result<IrqSpinMutexGuard<T>, SpinMutexErr> {
+        let was_enabled = interrupts::are_enabled();
+        interrupts::disable();
+        if let Some(guard) = self.acquire(was_enabled, Location::caller()) {
+            Ok(guard)
+        } else {
+            if was_enabled {
+                interrupts::enable();
+            }
+            Err(SpinMutexErr("lock error"))
+        }
+    }
+
+    #[track_caller]
+    pub fn lock(&self) -> IrqSpinMutexGuard<T> {
+        let was_enabled = interrupts::are_enabled();
+        interrupts::disable();
+        let caller = Location::caller();
+        #[cfg(debug_assertions)]
+        let mut spins = 0;
+        loop {
+            if let Some(guard) = self.acquire(was_enabled, caller) {
+                return guard;
+            }
+            core::hint::spin_loop();
+            #[cfg(debug_assertions)]
+            {
+                spins += 1;
+                if spins == DEADLOCK_SPIN_BUDGET {
+                    self.deadlock(caller);
+                }
+            }
+        }
+    }
+
+    fn acquire(&self, was_enabled: bool, caller: &'static Location<'static>) -> Option<IrqSpinMutexGuard<T>> {
+        if self.lock.swap(true, Ordering::Acquire) {
+            return None;
+        }
+        unsafe { *self.owner.get() = Some(caller) };
+        Some(IrqSpinMutexGuard { mutex: self, was_enabled })
+    }
+
+    #[cfg(debug_assertions)]
+    #[cold]
+    fn deadlock(&self, caller: &'static Location<'static>) -> ! {
+        match self.owner() {
+            Some(owner) => panic!("deadlock on {}: locked at {}, waiting at {}", self.name, owner, caller),
+            None => panic!("deadlock on {}: waiting at {}", self.name, caller),
+        }
+    }
+}
+
+unsafe impl<T> Send for IrqSpinMutex<T> {}
+unsafe impl<T> Sync for IrqSpinMutex<T> {}
+
+pub struct IrqSpinMutexGuard<'a, T> {
+    mutex: &'a IrqSpinMutex<T>,
+    // RFLAGS.IF before the lock was taken
+    was_enabled: bool,
+}
+
+// guards must be dropped in reverse order of locking, or interrupts are re-enabled too early
+impl<T> Drop for IrqSpinMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        unsafe { *self.mutex.owner.get() = None };
+        self.mutex.lock.store(false, Ordering::Release);
+        if self.was_enabled {
+            interrupts::enable();
+        }
+    }
+}
+
+impl<T> Deref for IrqSpinMutexGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T> DerefMut for IrqSpinMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+// ------------------------------------------------------
+// Once
+// ------------------------------------------------------
+
+use core::mem::MaybeUninit;
+use core::sync::atomic::AtomicU8;
+
+const ONCE_EMPTY: u8 = 0;
+const ONCE_WRITING: u8 = 1;
+const ONCE_READY: u8 = 2;
+
+// A value that is set once and then only read, so readers need no lock: `set` claims the
+// cell, writes the value and only then publishes it to `get`.
+pub struct Once<T> {
+    state: AtomicU8,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+unsafe impl<T: Send + Sync> Sync for Once<T> {}
+
+impl<T> Once<T> {
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU8::new(ONCE_EMPTY),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    // gives `value` back if the cell is already set, or being set
+    pub fn set(&self, value: T) -> Result<(), T> {
+        if self.state
+            .compare_exchange(ONCE_EMPTY, ONCE_WRITING, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return Err(value);
+        }
+        unsafe { (*self.value.get()).write(value) };
+        self.state.store(ONCE_READY, Ordering::Release);
+        Ok(())
+    }
+
+    // None until `set` has finished
+    pub fn get(&self) -> Option<&T> {
+        if self.state.load(Ordering::Acquire) == ONCE_READY {
+            Some(unsafe { (*self.value.get()).assume_init_ref() })
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Once;
+
+    #[test_case]
+    fn once_keeps_the_first_value() {
+        let once = Once::new();
+        assert_eq!(once.get(), None);
+        assert_eq!(once.set(1), Ok(()));
+        assert_eq!(once.set(2), Err(2));
+        assert_eq!(once.get(), Some(&1));
+    }
+}
@@ -0,0 +1,274 @@
+//! カーネル内テスト
+//!
+//! `cargo test` (`makers test`) builds the library with `custom_test_frameworks` and boots
+//! the test binary in QEMU through `tools/run-test.sh`. The binary has its own
+//! `kernel_main`, brings up the parts of the kernel the tests rely on (heap, interrupts,
+//! timer), runs every `#[test_case]` and reports over COM1. The result leaves QEMU through
+//! the isa-debug-exit device. Code that needs none of that belongs in `potato_utils`,
+//! whose tests run on the host (`makers test-host`).
+//!
+//! A `#[test_case]` is either a plain `fn()` or a static `TestCase` built with
+//! `kernel_test!`, which can expect a panic and override the timeout. The kernel cannot
+//! unwind, so a panic in a `should_panic` test abandons its stack: the runner restarts on a
+//! fresh stack with the next test. Locks the test held stay held, so such tests should
+//! panic outside of any. A test that runs past its timeout fails the run from the timer
+//! interrupt, which only fires while interrupts are enabled.
+
+use crate::backtrace::Backtrace;
+use crate::interrupts::idt::InterruptVector;
+use crate::interrupts::registry::{self, IrqReturn};
+use crate::power;
+use crate::serial::{SerialPort, COM1, SERIAL};
+use crate::sync::SpinMutex;
+use crate::timer;
+use crate::{serial_print, serial_println};
+use core::any::type_name;
+use core::fmt::Write;
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use core::time::Duration;
+use x86_64::instructions::interrupts;
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// QEMU exits with `(code << 1) | 1`: 33 and 35. Neither collides with QEMU's own 0 and 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum QemuExitCode {
+    Success = 0x10,
+    Failed = 0x11,
+}
+
+pub fn exit_qemu(code: QemuExitCode) -> ! {
+    power::qemu_exit(code as u8)
+}
+
+pub trait Testable: Sync {
+    fn name(&self) -> &'static str;
+    fn run(&self);
+    fn should_panic(&self) -> bool {
+        false
+    }
+    fn timeout(&self) -> Duration {
+        DEFAULT_TIMEOUT
+    }
+}
+
+impl<T: Fn() + Sync> Testable for T {
+    fn name(&self) -> &'static str {
+        type_name::<T>()
+    }
+
+    fn run(&self) {
+        self()
+    }
+}
+
+pub struct TestCase {
+    pub name: &'static str,
+    pub func: fn(),
+    pub should_panic: bool,
+    pub timeout: Duration,
+}
+
+impl TestCase {
+    pub const fn new(name: &'static str, func: fn()) -> Self {
+        Self { name, func, should_panic: false, timeout: DEFAULT_TIMEOUT }
+    }
+
+    pub const fn should_panic(mut self) -> Self {
+        self.should_panic = true;
+        self
+    }
+
+    pub const fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl Testable for TestCase {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn run(&self) {
+        (self.func)()
+    }
+
+    fn should_panic(&self) -> bool {
+        self.should_panic
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+}
+
+// `kernel_test!(func)` is a `TestCase` named like a plain `#[test_case] fn`:
+//
+//     #[test_case]
+//     static POP_EMPTY: TestCase = kernel_test!(pop_empty).should_panic();
+#[macro_export]
+macro_rules! kernel_test {
+    ($func:ident) => {
+        $crate::testing::TestCase::new(concat!(module_path!(), "::", stringify!($func)), $func)
+    };
+}
+
+static TESTS: SpinMutex<&'static [&'static dyn Testable]> = SpinMutex::new(&[]);
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PASSED: AtomicUsize = AtomicUsize::new(0);
+// in timer ticks; 0 while no test is running
+static DEADLINE: AtomicU64 = AtomicU64::new(0);
+
+pub fn test_runner(tests: &[&dyn Testable]) {
+    // `test_main` never returns (`run_tests_from` exits QEMU), so the slice lives as long
+    // as anything that reads it
+    let tests: &'static [&'static dyn Testable] = unsafe { core::mem::transmute(tests) };
+    *TESTS.lock() = tests;
+    registry::register_handler(InterruptVector::LocalApicTimer as u8, timeout_handler, core::ptr::null_mut())
+        .expect("failed to register the test timeout handler");
+    serial_println!("\nrunning {} tests", tests.len());
+    run_tests_from(0)
+}
+
+extern "C" fn run_tests_from(first: usize) -> ! {
+    let tests = *TESTS.lock();
+    for (index, test) in tests.iter().enumerate().skip(first) {
+        CURRENT.store(index, Ordering::SeqCst);
+        serial_print!("test {} ... ", test.name());
+        DEADLINE.store(timer::ticks() + timer::duration_to_ticks(test.timeout()), Ordering::SeqCst);
+        interrupts::enable();
+        test.run();
+        DEADLINE.store(0, Ordering::SeqCst);
+        if test.should_panic() {
+            serial_println!("FAILED");
+            serial_println!("note: test did not panic as expected");
+            finish(QemuExitCode::Failed);
+        }
+        serial_println!("ok");
+        PASSED.fetch_add(1, Ordering::SeqCst);
+    }
+    finish(QemuExitCode::Success)
+}
+
+fn finish(code: QemuExitCode) -> ! {
+    let total = TESTS.lock().len();
+    let passed = PASSED.load(Ordering::SeqCst);
+    let result = if code == QemuExitCode::Success { "ok" } else { "FAILED" };
+    serial_println!("\ntest result: {}. {} passed; {} failed or not run", result, passed, total - passed);
+    exit_qemu(code)
+}
+
+fn timeout_handler(_vector: u8, _context: *mut ()) -> IrqReturn {
+    let deadline = DEADLINE.load(Ordering::SeqCst);
+    if deadline != 0 && timer::ticks() >= deadline {
+        // the interrupted test may hold SERIAL
+        let mut serial = SerialPort::new(COM1);
+        let _ = writeln!(serial, "FAILED\nnote: test timed out");
+        abort_run(&mut serial)
+    }
+    // the timer driver handles the interrupt itself
+    IrqReturn::NotHandled
+}
+
+fn abort_run(serial: &mut SerialPort) -> ! {
+    let _ = writeln!(serial, "\ntest result: FAILED. {} passed", PASSED.load(Ordering::SeqCst));
+    exit_qemu(QemuExitCode::Failed)
+}
+
+// the `#[panic_handler]` of the test binary
+pub fn handle_test_panic(info: &PanicInfo) -> ! {
+    interrupts::disable();
+    DEADLINE.store(0, Ordering::SeqCst);
+    let mut serial = SerialPort::new(COM1);
+    let tests = match TESTS.try_lock() {
+        Ok(tests) => *tests,
+        Err(_) => &[],
+    };
+    match tests.get(CURRENT.load(Ordering::SeqCst)) {
+        Some(test) if test.should_panic() => {
+            let _ = writeln!(serial, "ok");
+            PASSED.fetch_add(1, Ordering::SeqCst);
+            // the panic may have come from inside `serial_print!`
+            unsafe { SERIAL.force_unlock() };
+            resume_tests(CURRENT.load(Ordering::SeqCst) + 1)
+        }
+        _ => {
+            let _ = writeln!(serial, "FAILED\n{}", info);
+            let _ = write!(serial, "{}", Backtrace::capture());
+            abort_run(&mut serial)
+        }
+    }
+}
+
+extern "C" {
+    static test_main_stack_top: u8;
+}
+
+// drops the stack of the panicking test and continues with `next`
+fn resume_tests(next: usize) -> ! {
+    unsafe {
+        core::arch::asm!(
+            "mov rsp, {stack}",
+            "call {run}",
+            stack = in(reg) &test_main_stack_top as *const u8,
+            run = sym run_tests_from,
+            in("rdi") next,
+            options(noreturn),
+        )
+    }
+}
+
+// the entry point of the test binary, like the one in main.rs
+core::arch::global_asm!(r#"
+.section .bss.test_main_stack, "aw", @nobits
+.align 4096
+test_main_stack:
+    .space 1024 * 1024
+.global test_main_stack_top
+test_main_stack_top:
+
+.section .text
+.global kernel_main
+kernel_main:
+    lea rsp, [rip + test_main_stack_top]
+    call test_kernel_main
+1:
+    hlt
+    jmp 1b
+"#);
+
+#[no_mangle]
+extern "C" fn test_kernel_main(boot_info: &'static boot_info::BootInfo) -> ! {
+    if !boot_info.is_compatible() {
+        exit_qemu(QemuExitCode::Failed);
+    }
+    init_for_tests(boot_info);
+    crate::test_main();
+    // `test_runner` exits QEMU
+    unreachable!()
+}
+
+// what tests may rely on: serial output, the heap, the IDT and the timer
+fn init_for_tests(boot_info: &'static boot_info::BootInfo) {
+    use crate::warn;
+    crate::logger::init_logger();
+    crate::cmdline::init_cmdline(boot_info);
+    crate::logger::apply_cmdline();
+    crate::serial::init_serial();
+    crate::memory_manager::init_memory_manager(&boot_info.memory_map);
+    crate::paging::init_paging(boot_info);
+    crate::gdt::init_gdt();
+    crate::interrupts::idt::init_idt();
+    if let Err(e) = crate::acpi::init_acpi(boot_info) {
+        warn!("acpi: {:?}", e);
+    }
+    if let Some(pm_timer) = crate::acpi::fadt().and_then(|fadt| fadt.pm_timer) {
+        timer::set_pm_timer(pm_timer.port, pm_timer.is_32bit);
+    }
+    crate::apic::init_local_apic();
+    crate::ioapic::init_ioapic();
+    timer::init_timer();
+}
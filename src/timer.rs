@@ -0,0 +1,264 @@
+//! 時間
+//!
+//! The Local APIC timer runs in periodic mode at `TICKS_PER_SECOND` and drives the
+//! monotonic clock (`ticks`/`uptime`) and the software timers that are delivered to
+//! the main loop as `Message::TimerTimeout`. Its frequency is not architectural, so it
+//! is measured at boot against the ACPI PM timer, or the PIT if there is none.
+
+use crate::apic::{self, TimerDivide, TimerMode};
+use crate::interrupts::idt::InterruptVector;
+use crate::interrupts::registry::{self, IrqReturn};
+use crate::message::{post_message, Message};
+use crate::pci::IOPort;
+use crate::sync::IrqSpinMutex;
+use crate::{debug, warn};
+use alloc::collections::BinaryHeap;
+use core::cmp::Ordering as CmpOrdering;
+use core::sync::atomic::{AtomicBool, AtomicU16, AtomicU64, Ordering};
+use core::time::Duration;
+
+pub const TICKS_PER_SECOND: u64 = 100;
+
+const CALIBRATION_MILLIS: u64 = 50;
+
+// 0 until `init_timer`
+static APIC_TIMER_FREQUENCY: AtomicU64 = AtomicU64::new(0);
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+// ------------------------------------------------------
+// Reference clocks
+// ------------------------------------------------------
+
+const PM_TIMER_FREQUENCY: u64 = 3_579_545;
+// 0: no PM timer
+static PM_TIMER_PORT: AtomicU16 = AtomicU16::new(0);
+static PM_TIMER_32BIT: AtomicBool = AtomicBool::new(false);
+
+// Called during initialization once the FADT has been parsed; `init_timer` then
+// calibrates against the PM timer instead of the PIT.
+pub fn set_pm_timer(port: u16, is_32bit: bool) {
+    PM_TIMER_32BIT.store(is_32bit, Ordering::Relaxed);
+    PM_TIMER_PORT.store(port, Ordering::Relaxed);
+}
+
+// The counter wraps every ~4.7 s (24 bits) or ~20 min (32 bits), so the masked deltas
+// between reads are summed up instead of comparing against the start value.
+fn wait_pm_timer(port: u16, target: u64) {
+    let mask = if PM_TIMER_32BIT.load(Ordering::Relaxed) { u32::MAX } else { 0x00ff_ffff };
+    let mut port = IOPort::new(port);
+    let mut last = port.read32();
+    let mut elapsed = 0u64;
+    while elapsed < target {
+        let now = port.read32();
+        elapsed += (now.wrapping_sub(last) & mask) as u64;
+        last = now;
+        core::hint::spin_loop();
+    }
+}
+
+const PIT_FREQUENCY: u64 = 1_193_182;
+const PIT_CHANNEL2: u16 = 0x42;
+const PIT_COMMAND: u16 = 0x43;
+// bit 0: channel 2 gate, bit 1: speaker, bit 5: channel 2 output
+const PIT_CONTROL: u16 = 0x61;
+
+// PIT channel 2 in mode 0 (interrupt on terminal count); polls its output instead of
+// taking an interrupt. At most ~54 ms per call.
+fn wait_pit(count: u16) {
+    let mut control = IOPort::new(PIT_CONTROL);
+    let gate_off = control.read8() & !0b11;
+    control.write8(gate_off);
+    IOPort::new(PIT_COMMAND).write8(0b1011_0000); // channel 2, lobyte/hibyte, mode 0
+    let mut channel2 = IOPort::new(PIT_CHANNEL2);
+    channel2.write8(count as u8);
+    channel2.write8((count >> 8) as u8);
+    // a rising edge on the gate starts the count
+    control.write8(gate_off | 0b1);
+    while control.read8() & (1 << 5) == 0 {
+        core::hint::spin_loop();
+    }
+    control.write8(gate_off);
+}
+
+// how many periods of a `frequency` Hz clock cover `duration`, rounded up so that
+// waiting that long is never too short
+fn clock_count(frequency: u64, duration: Duration) -> u64 {
+    let count = (duration.as_nanos() * frequency as u128 + 999_999_999) / 1_000_000_000;
+    count.min(u64::MAX as u128) as u64
+}
+
+// busy-waits on the reference clock, down to the microseconds drivers wait for; usable
+// before interrupts are enabled
+pub fn delay(duration: Duration) {
+    match PM_TIMER_PORT.load(Ordering::Relaxed) {
+        0 => {
+            let mut count = clock_count(PIT_FREQUENCY, duration);
+            while count > 0 {
+                let step = count.min(0xffff);
+                wait_pit(step as u16);
+                count -= step;
+            }
+        }
+        port => wait_pm_timer(port, clock_count(PM_TIMER_FREQUENCY, duration)),
+    }
+}
+
+// ------------------------------------------------------
+// Software timers
+// ------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timer {
+    // in ticks
+    pub timeout: u64,
+    pub value: i32,
+}
+
+// BinaryHeap is a max-heap: the earliest timeout has to compare greatest
+impl Ord for Timer {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        other.timeout.cmp(&self.timeout).then(self.value.cmp(&other.value))
+    }
+}
+
+impl PartialOrd for Timer {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+static TIMERS: IrqSpinMutex<BinaryHeap<Timer>> = IrqSpinMutex::new("TIMERS", BinaryHeap::new());
+
+// `Message::TimerTimeout { timeout, value }` is posted once `ticks() >= timeout`
+pub fn add_timer(timer: Timer) {
+    TIMERS.lock().push(timer);
+}
+
+pub fn add_timer_after(duration: Duration, value: i32) -> Timer {
+    let timer = Timer { timeout: ticks().saturating_add(duration_to_ticks(duration)), value };
+    add_timer(timer);
+    timer
+}
+
+fn timer_interrupt_handler(_vector: u8, _context: *mut ()) -> IrqReturn {
+    on_timer_interrupt();
+    IrqReturn::Handled
+}
+
+// called from the timer interrupt handler
+pub fn on_timer_interrupt() {
+    let now = TICKS.fetch_add(1, Ordering::Relaxed) + 1;
+    let mut timers = TIMERS.lock();
+    while let Some(timer) = timers.peek() {
+        if timer.timeout > now {
+            break;
+        }
+        post_message(Message::TimerTimeout { timeout: timer.timeout, value: timer.value });
+        timers.pop();
+    }
+}
+
+// ------------------------------------------------------
+// Clock
+// ------------------------------------------------------
+
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+pub fn uptime() -> Duration {
+    let ticks = ticks();
+    Duration::from_secs(ticks / TICKS_PER_SECOND)
+        + Duration::from_nanos((ticks % TICKS_PER_SECOND) * 1_000_000_000 / TICKS_PER_SECOND)
+}
+
+// rounded up, so that waiting this many ticks takes at least `duration`; saturates
+pub fn duration_to_ticks(duration: Duration) -> u64 {
+    clock_count(TICKS_PER_SECOND, duration)
+}
+
+// APIC timer counts per second
+pub fn apic_timer_frequency() -> u64 {
+    APIC_TIMER_FREQUENCY.load(Ordering::Relaxed)
+}
+
+// Sleeps on `hlt` until the clock has advanced by `duration`. The clock only moves while
+// interrupts are enabled, so with interrupts disabled this falls back to `delay`.
+pub fn sleep(duration: Duration) {
+    if !x86_64::instructions::interrupts::are_enabled() {
+        delay(duration);
+        return;
+    }
+    let deadline = Deadline::after(duration);
+    while !deadline.is_expired() {
+        x86_64::instructions::hlt();
+    }
+}
+
+// for polling loops in drivers: `while !ready() { if deadline.is_expired() { return Err(..) } }`
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline {
+    tick: u64,
+}
+
+impl Deadline {
+    pub fn after(duration: Duration) -> Self {
+        Self { tick: ticks().saturating_add(duration_to_ticks(duration)) }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        ticks() >= self.tick
+    }
+}
+
+// ------------------------------------------------------
+// Initialization
+// ------------------------------------------------------
+
+fn calibrate_apic_timer() -> u64 {
+    if PM_TIMER_PORT.load(Ordering::Relaxed) == 0 {
+        warn!("timer: no ACPI PM timer, calibrating against the PIT");
+    }
+    apic::start_masked_timer(TimerDivide::By1, u32::MAX);
+    delay(Duration::from_millis(CALIBRATION_MILLIS));
+    let elapsed = u32::MAX - apic::timer_current_count();
+    apic::stop_timer();
+    elapsed as u64 * 1000 / CALIBRATION_MILLIS
+}
+
+// `apic::init_local_apic` の後に呼び出すこと. Ticks start once interrupts are enabled.
+pub fn init_timer() {
+    let frequency = calibrate_apic_timer();
+    APIC_TIMER_FREQUENCY.store(frequency, Ordering::Relaxed);
+    let count = (frequency / TICKS_PER_SECOND).clamp(1, u32::MAX as u64) as u32;
+    let vector = InterruptVector::LocalApicTimer as u8;
+    registry::register_handler(vector, timer_interrupt_handler, core::ptr::null_mut())
+        .expect("failed to register the timer interrupt handler");
+    apic::start_timer(TimerMode::Periodic, TimerDivide::By1, vector, count);
+    debug!("timer: APIC timer {} Hz, {} ticks/s", frequency, TICKS_PER_SECOND);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn duration_to_ticks_rounds_up_and_saturates() {
+        assert_eq!(duration_to_ticks(Duration::ZERO), 0);
+        assert_eq!(duration_to_ticks(Duration::from_nanos(1)), 1);
+        assert_eq!(duration_to_ticks(Duration::from_secs(1)), TICKS_PER_SECOND);
+        let secs = u64::MAX / TICKS_PER_SECOND;
+        assert_eq!(duration_to_ticks(Duration::from_secs(secs)), secs * TICKS_PER_SECOND);
+        assert_eq!(duration_to_ticks(Duration::from_secs(secs + 1)), u64::MAX);
+        assert_eq!(duration_to_ticks(Duration::MAX), u64::MAX);
+    }
+
+    #[test_case]
+    fn short_delays_wait_at_least_one_period() {
+        assert_eq!(clock_count(PM_TIMER_FREQUENCY, Duration::from_nanos(1)), 1);
+        // 1789.7725 periods
+        assert_eq!(clock_count(PM_TIMER_FREQUENCY, Duration::from_micros(500)), 1790);
+        assert_eq!(clock_count(PIT_FREQUENCY, Duration::from_micros(10)), 12);
+        assert_eq!(clock_count(PIT_FREQUENCY, Duration::from_secs(1)), PIT_FREQUENCY);
+    }
+}
@@ -1,3 +1,2 @@
-pub mod bit_field;
-pub mod fixed_vec;
-pub mod init_once;
\ No newline at end of file
+// moved to the potato_utils crate so that they can be tested on the host
+pub use potato_utils::{bit_field, fixed_vec, init_once};
@@ -0,0 +1,337 @@
+//! ウィンドウ
+//!
+//! A window is a layer with a frame drawn around its client area (see
+//! `potato_utils::window`). Pressing the left button on a window focuses it and raises it
+//! above the other windows; on the close button it closes the window, on the title bar it
+//! starts a drag that follows the mouse until the button is released. Keys go to the
+//! focused window's key handler.
+//!
+//! Owners draw into the client area through `draw`, which hands them a `WindowContext`:
+//! a `PixelWriter` whose (0, 0) is the client area's top left corner and which clips
+//! everything to it, so the frame cannot be drawn over.
+//!
+//! Lock order: `WINDOWS` before `LAYERS`. Key handlers run without `WINDOWS` held, so they
+//! may draw into windows and print.
+
+use crate::console::CONSOLE_FONT;
+use crate::graphics::{Font, PixelColor, PixelWriter, Vector2D};
+use crate::layer::{self, LayerId, LayerManager, WINDOW_Z};
+use crate::mouse::MouseButton;
+use crate::sync::IrqSpinMutex;
+use alloc::string::String;
+use alloc::vec::Vec;
+use potato_utils::rect::{clip_copy, Rect};
+use potato_utils::window::{FramePart, WindowFrame};
+
+const ACTIVE_TITLE_BAR: PixelColor = PixelColor::new(0, 0, 132);
+const INACTIVE_TITLE_BAR: PixelColor = PixelColor::new(128, 128, 128);
+const TITLE: PixelColor = PixelColor::WHITE;
+const BORDER: PixelColor = PixelColor::new(64, 64, 64);
+const CLOSE_BUTTON: PixelColor = PixelColor::new(198, 198, 198);
+
+// called with the focused window, the modifier bits and the HID usage ID of a pressed key
+pub type KeyHandler = fn(WindowId, u8, u8);
+
+// the window's layer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowId(LayerId);
+
+impl WindowId {
+    pub fn layer(&self) -> LayerId {
+        self.0
+    }
+}
+
+struct Window {
+    id: WindowId,
+    title: String,
+    frame: WindowFrame,
+    on_key: Option<KeyHandler>,
+}
+
+pub struct WindowManager {
+    // in the order they were created
+    windows: Vec<Window>,
+    focused: Option<WindowId>,
+    // held by its title bar
+    dragging: Option<WindowId>,
+    // as of the last mouse report
+    buttons: u8,
+    pointer: (isize, isize),
+}
+
+pub static WINDOWS: IrqSpinMutex<WindowManager> = IrqSpinMutex::new("WINDOWS", WindowManager::new());
+
+impl WindowManager {
+    const fn new() -> Self {
+        Self {
+            windows: Vec::new(),
+            focused: None,
+            dragging: None,
+            buttons: 0,
+            pointer: (0, 0),
+        }
+    }
+
+    fn get(&self, id: WindowId) -> Option<&Window> {
+        self.windows.iter().find(|window| window.id == id)
+    }
+
+    // the topmost visible window at (x, y) on the screen
+    fn window_at(&self, layers: &LayerManager, x: isize, y: isize) -> Option<WindowId> {
+        if x < 0 || y < 0 {
+            return None;
+        }
+        layers
+            .ids()
+            .rev()
+            .map(WindowId)
+            .filter(|&id| self.get(id).is_some() && layers.is_visible(id.0))
+            .find(|&id| layers.area(id.0).map_or(false, |area| area.contains_point(x as usize, y as usize)))
+    }
+
+    // and raises it above the other windows
+    fn focus(&mut self, layers: &mut LayerManager, id: WindowId) {
+        let previous = self.focused.replace(id);
+        if let Some(previous) = previous.filter(|&previous| previous != id) {
+            self.draw_title_bar(layers, previous, false);
+        }
+        self.draw_title_bar(layers, id, true);
+        layers.set_z(id.0, WINDOW_Z);
+    }
+
+    fn close(&mut self, layers: &mut LayerManager, id: WindowId) {
+        self.windows.retain(|window| window.id != id);
+        layers.remove_layer(id.0);
+        if self.dragging == Some(id) {
+            self.dragging = None;
+        }
+        if self.focused == Some(id) {
+            self.focused = None;
+            // the window that is now on top
+            let next = layers.ids().rev().map(WindowId).find(|&id| self.get(id).is_some());
+            if let Some(next) = next {
+                self.focus(layers, next);
+            }
+        }
+    }
+
+    fn draw_title_bar(&self, layers: &mut LayerManager, id: WindowId, active: bool) {
+        if let Some(window) = self.get(id) {
+            layers.draw(id.0, |writer| draw_title_bar(writer, &window.frame, &window.title, active));
+        }
+    }
+
+    fn handle_mouse(&mut self, layers: &mut LayerManager, x: isize, y: isize, buttons: u8) {
+        let left = MouseButton::Left as u8;
+        let (dx, dy) = (x - self.pointer.0, y - self.pointer.1);
+        let pressed = buttons & !self.buttons;
+        self.pointer = (x, y);
+        self.buttons = buttons;
+
+        if let Some(id) = self.dragging {
+            if buttons & left == 0 {
+                self.dragging = None;
+            } else if (dx, dy) != (0, 0) {
+                layers.move_relative(id.0, dx, dy);
+            }
+            return;
+        }
+        if pressed & left == 0 {
+            return;
+        }
+        let Some(id) = self.window_at(layers, x, y) else {
+            return;
+        };
+        self.focus(layers, id);
+        let (Some(window), Some((wx, wy))) = (self.get(id), layers.position(id.0)) else {
+            return;
+        };
+        match window.frame.hit_test(x - wx, y - wy) {
+            Some(FramePart::CloseButton) => self.close(layers, id),
+            Some(FramePart::TitleBar) => self.dragging = Some(id),
+            _ => {}
+        }
+    }
+}
+
+// A window of `client_width` x `client_height` at (0, 0), focused and above the other
+// windows. None before the layers are initialized.
+pub fn create_window(
+    title: &str,
+    client_width: usize,
+    client_height: usize,
+    background: &PixelColor,
+    on_key: Option<KeyHandler>,
+) -> Option<WindowId> {
+    let frame = WindowFrame::for_client(client_width, client_height);
+    let mut windows = WINDOWS.lock();
+    layer::with_layers(|layers| {
+        let id = WindowId(layers.new_layer(frame.width(), frame.height(), WINDOW_Z, background));
+        layers.draw(id.0, |writer| draw_frame(writer, &frame));
+        windows.windows.push(Window { id, title: String::from(title), frame, on_key });
+        windows.focus(layers, id);
+        id
+    })
+}
+
+pub fn close_window(id: WindowId) {
+    let mut windows = WINDOWS.lock();
+    layer::with_layers(|layers| windows.close(layers, id));
+}
+
+// (x, y) is where the top left corner of the frame goes
+pub fn move_window(id: WindowId, x: isize, y: isize) {
+    layer::move_to(id.0, x, y);
+}
+
+pub fn focused_window() -> Option<WindowId> {
+    WINDOWS.lock().focused
+}
+
+// Draws into the client area of the window and puts what was drawn on the screen.
+// None if the window was closed.
+pub fn draw<R>(id: WindowId, f: impl FnOnce(&dyn PixelWriter) -> R) -> Option<R> {
+    let client_area = WINDOWS.lock().get(id)?.frame.client_area();
+    layer::draw(id.0, |writer| f(&WindowContext::new(writer, client_area)))
+}
+
+// for every mouse report, after the cursor moved to (x, y)
+pub fn handle_mouse(x: isize, y: isize, buttons: u8) {
+    let mut windows = WINDOWS.lock();
+    layer::with_layers(|layers| windows.handle_mouse(layers, x, y, buttons));
+}
+
+// keys typed while no window is focused are dropped
+pub fn handle_key(modifier: u8, keycode: u8) {
+    let target = {
+        let windows = WINDOWS.lock();
+        windows
+            .focused
+            .and_then(|id| windows.get(id))
+            .and_then(|window| window.on_key.map(|on_key| (window.id, on_key)))
+    };
+    if let Some((id, on_key)) = target {
+        on_key(id, modifier, keycode);
+    }
+}
+
+// border, title bar (as inactive, without a title) and close button
+fn draw_frame(writer: &dyn PixelWriter, frame: &WindowFrame) {
+    let bounds = frame.bounds();
+    writer.draw_rect(Vector2D::new(0, 0), Vector2D::new(bounds.width, bounds.height), &BORDER);
+    draw_title_bar(writer, frame, "", false);
+}
+
+fn draw_title_bar(writer: &dyn PixelWriter, frame: &WindowFrame, title: &str, active: bool) {
+    let title_bar = frame.title_bar();
+    let color = if active { ACTIVE_TITLE_BAR } else { INACTIVE_TITLE_BAR };
+    writer.fill_rect(
+        Vector2D::new(title_bar.x, title_bar.y),
+        Vector2D::new(title_bar.width, title_bar.height),
+        &color,
+    );
+    let (x, y) = frame.title_origin(16);
+    // glyphs are 8 pixels wide; `char_size` includes the console's spacing
+    let capacity = frame.title_capacity(8);
+    let end = title.char_indices().nth(capacity).map_or(title.len(), |(i, _)| i);
+    Font::write_string(&CONSOLE_FONT, writer, x, y, &title[..end], &TITLE, &color);
+
+    let button = frame.close_button();
+    let (left, top) = (button.x, button.y);
+    let (right, bottom) = (button.right() - 1, button.bottom() - 1);
+    writer.fill_rect(Vector2D::new(left, top), Vector2D::new(button.width, button.height), &CLOSE_BUTTON);
+    writer.draw_rect(Vector2D::new(left, top), Vector2D::new(button.width, button.height), &BORDER);
+    writer.draw_line(Vector2D::new(left + 3, top + 3), Vector2D::new(right - 3, bottom - 3), &PixelColor::BLACK);
+    writer.draw_line(Vector2D::new(left + 3, bottom - 3), Vector2D::new(right - 3, top + 3), &PixelColor::BLACK);
+}
+
+// ------------------------------------------------------
+// Window Context
+// ------------------------------------------------------
+
+// Draws into `area` of `writer` as if it were a screen of its own.
+pub struct WindowContext<'a> {
+    writer: &'a dyn PixelWriter,
+    area: Rect,
+}
+
+impl<'a> WindowContext<'a> {
+    pub fn new(writer: &'a dyn PixelWriter, area: Rect) -> Self {
+        Self { writer, area }
+    }
+
+    fn to_writer(&self, x: usize, y: usize) -> Vector2D<usize> {
+        Vector2D::new(self.area.x + x, self.area.y + y)
+    }
+}
+
+// lines and circles use the pixel-by-pixel defaults, which clip through `resolution`
+impl PixelWriter for WindowContext<'_> {
+    fn horizontal_resolution(&self) -> usize {
+        self.area.width
+    }
+    fn vertical_resolution(&self) -> usize {
+        self.area.height
+    }
+    fn draw_pixel(&self, x: usize, y: usize, color: &PixelColor) {
+        if x < self.area.width && y < self.area.height {
+            let pos = self.to_writer(x, y);
+            self.writer.draw_pixel(pos.x(), pos.y(), color);
+        }
+    }
+    fn fill_rect(&self, pos: Vector2D<usize>, size: Vector2D<usize>, color: &PixelColor) {
+        let rect = Rect::new(pos.x(), pos.y(), size.x(), size.y());
+        if let Some(rect) = rect.clip(self.area.width, self.area.height) {
+            self.writer.fill_rect(self.to_writer(rect.x, rect.y), Vector2D::new(rect.width, rect.height), color);
+        }
+    }
+    fn copy_rect(&self, src: Vector2D<usize>, size: Vector2D<usize>, dst: Vector2D<usize>) {
+        let src = Rect::new(src.x(), src.y(), size.x(), size.y());
+        let bounds = Rect::new(0, 0, self.area.width, self.area.height);
+        if let Some((src, (x, y))) = clip_copy(src, (dst.x(), dst.y()), bounds) {
+            self.writer.copy_rect(
+                self.to_writer(src.x, src.y),
+                Vector2D::new(src.width, src.height),
+                self.to_writer(x, y),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics::{FrameBuffer, ShadowWriter};
+    use alloc::vec;
+
+    const WIDTH: usize = 8;
+    const HEIGHT: usize = 6;
+    // the client area
+    const AREA: Rect = Rect::new(2, 1, 4, 3);
+
+    fn outside_is_untouched(pixels: &[u32]) -> bool {
+        pixels.chunks(WIDTH).enumerate().all(|(y, row)| {
+            row.iter().enumerate().all(|(x, &p)| AREA.contains_point(x, y) || p == 0)
+        })
+    }
+
+    #[test_case]
+    fn context_is_clipped_to_the_client_area() {
+        let encoder = FrameBuffer::uninitialized_default().encoder();
+        let mut pixels = vec![0; WIDTH * HEIGHT];
+        let writer = ShadowWriter::new(&mut pixels, WIDTH, HEIGHT, encoder);
+        let context = WindowContext::new(&writer, AREA);
+        assert_eq!(context.resolution(), (4, 3));
+        context.fill_rect(Vector2D::new(1, 1), Vector2D::new(100, 100), &PixelColor::WHITE);
+        context.draw_line(Vector2D::new(0, 0), Vector2D::new(20, 3), &PixelColor::RED);
+        context.draw_circle(Vector2D::new(0, 0), 3, &PixelColor::BLUE);
+        context.copy_rect(Vector2D::new(0, 0), Vector2D::new(4, 3), Vector2D::new(2, 2));
+        context.draw_pixel(4, 0, &PixelColor::GREEN);
+        assert_eq!(writer.touched().map(|touched| AREA.contains(&touched)), Some(true));
+        drop(writer);
+        assert!(outside_is_untouched(&pixels));
+        // (1, 1) of the client area
+        assert_eq!(pixels[2 * WIDTH + 3], encoder.encode(&PixelColor::WHITE));
+    }
+}
@@ -1,34 +1,55 @@
 
-use crate::sync::SpinMutex;
+use crate::sync::IrqSpinMutex;
 use crate::pci::{self, Device};
-use crate::{trace, interrupts};
-use crate::utils::bit_field::BitField;
+use crate::{trace, error};
+use crate::interrupts::{registry::{self, IrqReturn}, vector};
+use crate::message::{post_message, Message};
+use crate::memory_manager::{self, FrameConstraint, FRAME_SIZE};
+use crate::paging;
 use mikanos_usb as usb;
 
+// same size as usb::kMemoryPoolSize in usb_driver/usb/memory.hpp
+const USB_MEMORY_POOL_FRAMES: usize = 32;
 
-pub static XHC_CONTROLLER: SpinMutex<Option<&'static mut usb::xhci::Controller>> 
-    = SpinMutex::new(None); // MaybeUninit, Option, 
+
+pub static XHC_CONTROLLER: IrqSpinMutex<Option<&'static mut usb::xhci::Controller>> 
+    = IrqSpinMutex::new("XHC_CONTROLLER", None); // MaybeUninit, Option, 
+
+pub fn with_controller<F, R>(f: F) -> Option<R>
+where F: FnOnce(&mut usb::xhci::Controller) -> R {
+    XHC_CONTROLLER.lock().as_mut().map(|controller| f(controller))
+}
 
 pub fn init_xhc() {
     let xhc_dev = find_xhc_device();
     if let Some(device) = xhc_dev {
 
         // msi の設定
-        const LOCAL_APIC_ID_REGISTER: *const u32 = 0xfee00020 as *const u32;
-        let bsp_local_apic_id = unsafe { *LOCAL_APIC_ID_REGISTER }.get_bits(24..32) as u8;
+        let vector = vector::allocate_vector().expect("no free interrupt vector for xhc");
+        registry::register_handler(vector, xhc_interrupt_handler, core::ptr::null_mut())
+            .expect("failed to register the xhc interrupt handler");
+        let bsp_local_apic_id = crate::apic::local_apic_id();
         let is_err = device.configure_msi_fixed_destination(
             bsp_local_apic_id, 
             pci::MSITriggerMode::Level, 
             pci::MSIDeliveryMode::Fixed, 
-            interrupts::idt::InterruptVector::XHCI,
+            vector,
             0,
         ).is_err();
         if is_err {
             panic!("msi configuration failed");
         }
 
-        let xhc_bar = device.read_bar(0);
-        let mmio_base = (xhc_bar.unwrap() & !0x0f) as u64;
+        // DMA buffers for the driver. xHC may only support 32-bit addressing.
+        let pool = memory_manager::allocate_frames_with(
+            USB_MEMORY_POOL_FRAMES,
+            FrameConstraint::BELOW_4G.aligned(16), // 64 KiB
+        ).expect("failed to allocate usb memory pool");
+        unsafe { usb::set_memory_pool(pool.addr(), USB_MEMORY_POOL_FRAMES * FRAME_SIZE) };
+
+        let xhc_bar = device.read_bar(0).expect("xhc has no BAR 0") & !0x0f;
+        let xhc_bar_size = device.read_bar_size(0).expect("xhc BAR 0 is not a memory BAR");
+        let mmio_base = paging::map_mmio(xhc_bar, xhc_bar_size).expect("failed to map the xhc registers");
         let mut controller = XHC_CONTROLLER.lock();
         *controller = Some(unsafe { mikanos_usb::xhci::Controller::new(mmio_base) });
         let controller = controller.as_mut().unwrap();
@@ -42,7 +63,9 @@ pub fn init_xhc() {
         controller.run().unwrap();
 
         use crate::mouse::mouse_observer;
+        use crate::keyboard::keyboard_observer;
         usb::HidMouseDriver::set_default_observer(mouse_observer);
+        usb::HidKeyboardDriver::set_default_observer(keyboard_observer);
         controller.configure_connected_ports();
 
 
@@ -51,6 +74,24 @@ pub fn init_xhc() {
 
 }
 
+// the events are processed by the main loop (`process_events`)
+fn xhc_interrupt_handler(_vector: u8, _context: *mut ()) -> IrqReturn {
+    // if the queue is full, the events stay on the event ring until the next message
+    post_message(Message::XhciInterrupt);
+    IrqReturn::Handled
+}
+
+// handles `Message::XhciInterrupt`. The HID drivers call their observers from here.
+pub fn process_events() {
+    with_controller(|controller| {
+        while controller.has_event() {
+            if let Err(e) = controller.process_event() {
+                error!("xhc: {:?}", e);
+            }
+        }
+    });
+}
+
 fn find_xhc_device() -> Option<&'static Device> {
     let mut xhc_dev = None;
     for device in pci::devices() {
@@ -0,0 +1,33 @@
+#!/bin/sh
+# カーネルの関数シンボルを .ksyms セクションに書き込む (src/ksyms.rs を参照)
+#
+# usage: tools/embed-symbols.sh <kernel elf>
+# The section keeps its size, so no address in the image changes.
+
+set -eu
+
+KERNEL=$1
+NM=${NM:-nm}
+OBJCOPY=${OBJCOPY:-objcopy}
+OBJDUMP=${OBJDUMP:-objdump}
+SYMBOLS=$(mktemp)
+trap 'rm -f "$SYMBOLS"' EXIT
+
+SECTION_SIZE=$(printf '%d' "0x$("$OBJDUMP" -h "$KERNEL" | awk '$2 == ".ksyms" { print $3 }')")
+if [ "$SECTION_SIZE" -eq 0 ]; then
+    echo "embed-symbols: $KERNEL has no .ksyms section" >&2
+    exit 1
+fi
+
+# functions only, sorted by address, without the hash suffix of Rust symbols
+"$NM" -n -C --defined-only "$KERNEL" \
+    | grep -E '^[0-9a-f]+ [tTwW] ' \
+    | sed -E 's/::h[0-9a-f]{16}$//' \
+    > "$SYMBOLS"
+
+if [ "$(wc -c < "$SYMBOLS")" -ge "$SECTION_SIZE" ]; then
+    echo "embed-symbols: symbols do not fit into .ksyms ($SECTION_SIZE bytes)" >&2
+    exit 1
+fi
+truncate -s "$SECTION_SIZE" "$SYMBOLS"
+"$OBJCOPY" --update-section .ksyms="$SYMBOLS" "$KERNEL"
@@ -0,0 +1,48 @@
+#!/bin/sh
+# テストカーネルを QEMU で起動する (src/testing.rs を参照)
+#
+# usage: tools/run-test.sh <test kernel elf>
+# Used as the cargo runner by `makers test`. Boots the test binary with the loader,
+# prints COM1 to stdout and turns the isa-debug-exit status into a process status:
+# 33 (QemuExitCode::Success) becomes 0, anything else 1. The image is written with
+# mtools so that tests run without root. TEST_CMDLINE, if set, becomes cmdline.txt.
+
+set -eu
+
+KERNEL=$1
+BOOTLOADER_EFI_PATH=${BOOTLOADER_EFI_PATH:-target/bootloader_target/debug/potato_loader.efi}
+OVMF_PATH=${OVMF_PATH:-/usr/share/OVMF/x64/OVMF.fd}
+TEST_TIMEOUT=${TEST_TIMEOUT:-300}
+WORK=$(mktemp -d)
+trap 'rm -rf "$WORK"' EXIT
+
+cp "$KERNEL" "$WORK/potatOS.elf"
+sh "$(dirname "$0")/embed-symbols.sh" "$WORK/potatOS.elf"
+
+DISK=$WORK/disk.img
+qemu-img create -q -f raw "$DISK" 200M
+mkfs.fat -n 'POTATO OS' -s 2 -f 2 -R 32 -F 32 "$DISK" > /dev/null
+mmd -i "$DISK" ::/EFI ::/EFI/BOOT
+mcopy -i "$DISK" "$BOOTLOADER_EFI_PATH" ::/EFI/BOOT/BOOTX64.EFI
+mcopy -i "$DISK" "$WORK/potatOS.elf" ::/potatOS.elf
+if [ -n "${TEST_CMDLINE:-}" ]; then
+    printf '%s\n' "$TEST_CMDLINE" > "$WORK/cmdline.txt"
+    mcopy -i "$DISK" "$WORK/cmdline.txt" ::/cmdline.txt
+fi
+
+set +e
+timeout "$TEST_TIMEOUT" qemu-system-x86_64 -bios "$OVMF_PATH" \
+  -m 1G \
+  -drive format=raw,file="$DISK" \
+  -device isa-debug-exit,iobase=0xf4,iosize=0x04 \
+  -display none \
+  -serial stdio \
+  -no-reboot
+STATUS=$?
+set -e
+
+case $STATUS in
+    33) exit 0 ;;
+    124) echo "run-test: QEMU did not exit within ${TEST_TIMEOUT}s" >&2; exit 1 ;;
+    *) exit 1 ;;
+esac
@@ -0,0 +1,13 @@
+#!/bin/sh
+# potato_utils のテストをホストで実行する
+#
+# usage: tools/test-host.sh [cargo test args]
+# .cargo/config.toml rebuilds `core` for kernel_target.json for everything below the
+# repository root, and cargo reads it based on the working directory, so cargo runs
+# from outside the repository.
+
+set -eu
+
+ROOT=$(cd "$(dirname "$0")/.." && pwd)
+cd "${TMPDIR:-/tmp}"
+exec cargo test --manifest-path "$ROOT/potato_utils/Cargo.toml" "$@"